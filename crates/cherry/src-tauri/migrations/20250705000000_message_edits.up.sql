@@ -0,0 +1,2 @@
+ALTER TABLE messages ADD COLUMN edit_history TEXT;
+ALTER TABLE messages ADD COLUMN edited_at TIMESTAMP;
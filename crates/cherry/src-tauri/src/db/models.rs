@@ -41,6 +41,10 @@ pub struct  MessageSnapshot {
     pub timestamp: DateTime<Utc>,
     pub reply_to: Option<i64>,
     pub reactions: Option<Value>,
+    // 编辑前的内容，Vec<MessageEdit>
+    pub edit_history: Option<Value>,
+    // 最后一次编辑或撤回的时间
+    pub edited_at: Option<DateTime<Utc>>,
 }
 
 // export interface Message {
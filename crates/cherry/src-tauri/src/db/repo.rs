@@ -2,7 +2,10 @@ use crate::db::models::MessageSnapshot;
 
 use super::models::{Contact, User};
 use anyhow::Ok;
-use cherrycore::types::{Message, ReactionContent};
+use cherrycore::types::{
+    MESSAGE_TYPE_DELETE, MESSAGE_TYPE_EDIT, Message, MessageEdit, ReactionContent,
+};
+use serde_json::Value;
 use sqlx::{ query_as, sqlite::{SqlitePool, SqlitePoolOptions}, Pool, Row, Sqlite };
 use sqlx::query;
 use uuid::Uuid;
@@ -34,13 +37,19 @@ impl Repo {
         Ok(message)
     }
 
-    pub async fn receive_message(&self,  message: Message) -> anyhow::Result<(MessageSnapshot)> {
+    // read_up_to 为会话的已读位置，不大于它的消息为已读
+    pub async fn receive_message(&self,  message: Message, read_up_to: Option<i64>) -> anyhow::Result<(MessageSnapshot)> {
         let pool = self.sqlx_pool.as_ref().unwrap();
         let snapshot:MessageSnapshot  =  match message.type_.as_str(){
             "text" | "image" | "voice" | "video" | "file" | "location" | "contact" | "system" | "encrypted_text" => {
+                let status = if read_up_to.is_some_and(|read_up_to| message.id <= read_up_to) {
+                    "read"
+                } else {
+                    "unread"
+                };
                 MessageSnapshot {
                     id: message.id,
-                    status: "unread".to_string(),
+                    status: status.to_string(),
                     user_id: message.user_id.to_string(),
                     conversation_id: message.conversation_id.to_string(),
                     content: message.content,
@@ -48,7 +57,8 @@ impl Repo {
                     timestamp: message.timestamp,
                     reply_to: message.reply_to,
                     reactions: Some(serde_json::to_value(Vec::<ReactionContent>::new()).unwrap()),
-
+                    edit_history: None,
+                    edited_at: None,
                 }
             }
             "reaction" => {
@@ -76,18 +86,60 @@ impl Repo {
                snapshot_message.reactions = Some(serde_json::to_value(reactions).unwrap());
                snapshot_message
             }
+            MESSAGE_TYPE_EDIT | MESSAGE_TYPE_DELETE => {
+                log::info!("receive {} message: {:?}", message.type_, message);
+                let Some(reply_to) = message.reply_to else {
+                    return Err(anyhow::anyhow!("{} message has no reply_to", message.type_));
+                };
+                let mut snapshot_message = self.get_message_by_id(reply_to).await?;
+                // 服务器已经校验过，这里再确认一次只有作者能修改
+                if snapshot_message.user_id != message.user_id.to_string() {
+                    return Err(anyhow::anyhow!("{} message is not sent by the author", message.type_));
+                }
+                // 已撤回的消息不再变化
+                if snapshot_message.status == "deleted" {
+                    return Ok(snapshot_message);
+                }
+                if message.type_ == MESSAGE_TYPE_EDIT {
+                    // 保存编辑前的内容
+                    let mut edit_history: Vec<MessageEdit> = snapshot_message
+                        .edit_history
+                        .clone()
+                        .and_then(|edit_history| serde_json::from_value(edit_history).ok())
+                        .unwrap_or_default();
+                    edit_history.push(MessageEdit {
+                        content: snapshot_message.content,
+                        timestamp: snapshot_message.edited_at.unwrap_or(snapshot_message.timestamp),
+                    });
+                    snapshot_message.content = message.content;
+                    snapshot_message.edit_history = Some(serde_json::to_value(edit_history).unwrap());
+                } else {
+                    // 撤回后只保留墓碑，不保留内容、编辑记录和表情回应
+                    snapshot_message.status = "deleted".to_string();
+                    snapshot_message.content = Value::Null;
+                    snapshot_message.edit_history = None;
+                    snapshot_message.reactions = Some(serde_json::to_value(Vec::<ReactionContent>::new()).unwrap());
+                }
+                snapshot_message.edited_at = Some(message.timestamp);
+                snapshot_message
+            }
             _ => {
                 return Err(anyhow::anyhow!("Unsupported message type"));
             }
         };
 
         //  insert message conflict on id and update if exists
+        //  撤回的消息不会再被覆盖，状态只会变为 deleted
         let result = query(
-            "INSERT INTO messages (id, user_id, conversation_id, content, type_, status, timestamp, reply_to, reactions) 
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?) 
+            "INSERT INTO messages (id, user_id, conversation_id, content, type_, status, timestamp, reply_to, reactions, edit_history, edited_at) 
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) 
             ON CONFLICT(id) DO UPDATE SET 
             content = excluded.content,
-            reactions = excluded.reactions
+            reactions = excluded.reactions,
+            edit_history = excluded.edit_history,
+            edited_at = excluded.edited_at,
+            status = CASE WHEN excluded.status = 'deleted' THEN 'deleted' ELSE messages.status END
+            WHERE messages.status != 'deleted'
             ",
         )
         .bind(&snapshot.id)
@@ -99,11 +151,14 @@ impl Repo {
         .bind(&snapshot.timestamp)
         .bind(&snapshot.reply_to)
         .bind(serde_json::to_value(snapshot.reactions.clone().unwrap_or_default()).unwrap())
+        .bind(&snapshot.edit_history)
+        .bind(&snapshot.edited_at)
         .execute(pool)
         .await?;
 
         if result.rows_affected() == 0 {
-            return Err(anyhow::anyhow!("Failed to insert message"));
+            // 消息已被撤回，返回墓碑
+            return self.get_message_by_id(snapshot.id).await;
         }else{
             let message = self.get_message_by_id(snapshot.id).await?;
             log::info!("message {:?} insert or update success", message);
@@ -111,6 +166,19 @@ impl Repo {
         Ok(snapshot)
     }
 
+    // 将会话中不晚于 read_up_to 的未读消息标记为已读
+    pub async fn mark_read(&self, conversation_id: Uuid, read_up_to: i64) -> anyhow::Result<u64> {
+        let pool = self.sqlx_pool.as_ref().unwrap();
+        let result = query(
+            "UPDATE messages SET status = 'read' WHERE conversation_id = ? AND id <= ? AND status = 'unread'",
+        )
+        .bind(conversation_id.to_string())
+        .bind(read_up_to)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
     pub async fn list_messages(&self, conversation_id: Uuid, forward_id: Option<i64>, backward_id: Option<i64>, limit: i64) -> anyhow::Result<Vec<MessageSnapshot>> {
         let pool = self.sqlx_pool.as_ref().unwrap();
         
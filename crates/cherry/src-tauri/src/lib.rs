@@ -12,12 +12,14 @@ use cherrycore::types::{FileUploadCompleteResponse, FileUploadCreateResponse};
 use cherrycore::{
     client::{
         cherry::CherryClient,
-        stream::{StreamClient, StreamRecordDecoderMachine},
+        stream::{StreamClient, StreamRecordDecoder, StreamRecordDecoderMachine},
         AuthCredentials,
     },
     types::{
-        Contact, Conversation, DataFormat, LoginResponse, Message, StreamEvent, StreamReadRequest,
-        UserInfo,
+        Contact, ContactRelationResponse, Conversation, DataFormat, JoinConversationResponse, JoinRequest, LoginResponse,
+        Message, PinnedMessage, PresenceStatus, SearchMessagesRequest, SearchResult, StarredMessage,
+        StreamControlRequest, StreamControlResponse, StreamEvent, StreamReadRequest, UpdateContactRequest,
+        UpdateConversationMetaRequest, UserInfo,
     },
 };
 use env_logger;
@@ -38,6 +40,11 @@ use tokio::sync::mpsc;
 use uuid;
 use uuid::Uuid;
 
+// consumer name of this client for the committed stream offsets
+const STREAM_CONSUMER: &str = "desktop";
+// 读取位置合并后按间隔提交，不必每个数据帧都提交一次
+const COMMIT_OFFSET_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
 #[derive(Debug, Serialize)]
 struct CommandError {
     message: String,
@@ -78,6 +85,10 @@ impl From<sqlx::Error> for CommandError {
 #[derive(Debug, Serialize, Deserialize)]
 pub enum CherryMessage {
     Message { message: MessageSnapshot },
+    Event { event: StreamEvent },
+    // 联系人的在线状态和会话成员的输入状态，不保存
+    Presence { user_id: Uuid, status: PresenceStatus },
+    Typing { user_id: Uuid, conversation_id: Uuid, typing: bool },
 }
 
 #[derive(Debug, Serialize)]
@@ -109,6 +120,63 @@ struct AppStateInner {
     user_info: Mutex<Option<UserInfo>>,
     conversation_read_position: Mutex<HashMap<Uuid, i64>>,
     read_stream_sender: Mutex<Option<mpsc::Sender<StreamReadRequest>>>,
+    // 通过读取流的 WebSocket 发布在线和输入状态，SSE 读取时为 None
+    presence_sender: Mutex<Option<mpsc::Sender<StreamControlRequest>>>,
+}
+
+// 从上次提交的位置继续读取
+async fn subscribe_stream(
+    stream_client: &StreamClient,
+    sender: &mpsc::Sender<StreamReadRequest>,
+    stream_id: StreamId,
+) -> Result<()> {
+    let offset = match stream_client
+        .get_committed_offset(stream_id, Some(STREAM_CONSUMER))
+        .await
+    {
+        Ok(offset) => offset.unwrap_or(0),
+        Err(e) => {
+            log::error!("get_committed_offset error: {:?}", e);
+            0
+        }
+    };
+    sender
+        .send(StreamReadRequest { stream_id, offset })
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to send StreamReadRequest: {}", e))
+}
+
+// 收集各个流最新的读取位置，每隔 COMMIT_OFFSET_INTERVAL 提交一次，发送端关闭时提交剩余的位置
+fn spawn_offset_committer(stream_client: StreamClient) -> mpsc::UnboundedSender<(StreamId, u64)> {
+    let (sender, mut receiver) = mpsc::unbounded_channel::<(StreamId, u64)>();
+    tokio::spawn(async move {
+        let mut pending: HashMap<StreamId, u64> = HashMap::new();
+        let mut interval = tokio::time::interval(COMMIT_OFFSET_INTERVAL);
+        loop {
+            let closed = tokio::select! {
+                received = receiver.recv() => match received {
+                    Some((stream_id, offset)) => {
+                        pending.insert(stream_id, offset);
+                        continue;
+                    }
+                    None => true,
+                },
+                _ = interval.tick() => false,
+            };
+            for (stream_id, offset) in pending.drain() {
+                if let Err(e) = stream_client
+                    .commit_offset(stream_id, Some(STREAM_CONSUMER), offset)
+                    .await
+                {
+                    log::error!("commit_offset error: {:?}", e);
+                }
+            }
+            if closed {
+                break;
+            }
+        }
+    });
+    sender
 }
 
 #[derive(Clone)]
@@ -148,6 +216,33 @@ impl AppState {
             .ok_or(anyhow::anyhow!("Not authenticated"))
     }
 
+    // 会话的已读位置，本地没有保存时使用服务器返回的位置
+    fn read_position(&self, conversation_id: Uuid) -> Option<i64> {
+        if let Some(read_up_to) = self.conversation_read_position.lock().unwrap().get(&conversation_id) {
+            return Some(*read_up_to);
+        }
+        self.conversations
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|c| c.conversation_id == conversation_id)
+            .and_then(|c| c.read_up_to)
+    }
+
+    async fn send_presence(&self, request: StreamControlRequest) -> Result<()> {
+        let sender = self
+            .presence_sender
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or(anyhow::anyhow!("Presence is not available"))?;
+        sender
+            .send(request)
+            .await
+            .map_err(|_| anyhow::anyhow!("Stream connection closed"))?;
+        Ok(())
+    }
+
     fn find_conversation_id(&self, stream_id: StreamId) -> Result<uuid::Uuid> {
         let guard = self.conversations.lock().unwrap();
         guard
@@ -219,17 +314,101 @@ impl AppState {
         Ok(())
     }
 
-    async fn start_receive_message(&self, on_event: Channel<CherryMessage>) -> Result<()> {
+    async fn find_notification_stream_id(&self) -> Result<StreamId> {
+        let user_id = self
+            .user_info
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|user_info| user_info.user_id)
+            .ok_or(anyhow::anyhow!("Not authenticated"))?;
+        let cherry_client = self.get_cherry_client()?;
+        let streams = cherry_client.get_streams(user_id).await?;
+        streams
+            .streams
+            .iter()
+            .find(|s| s.stream_type == "notification")
+            .map(|s| s.stream_id)
+            .ok_or_else(|| anyhow::anyhow!("Notification stream not found for user: {}", user_id))
+    }
+
+    // 收到会话事件后刷新会话列表，并订阅新加入会话的消息流
+    async fn refresh_conversations(
+        &self,
+        app: &tauri::AppHandle,
+        stream_client: &StreamClient,
+    ) -> Result<()> {
+        let known_streams = self
+            .conversations
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|c| c.stream_id)
+            .collect::<Vec<_>>();
+        self.emit_conversations_updated(app).await?;
+
+        let new_streams = self
+            .conversations
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|c| c.stream_id)
+            .filter(|stream_id| !known_streams.contains(stream_id))
+            .collect::<Vec<_>>();
+        let sender = self.read_stream_sender.lock().unwrap().clone();
+        if let Some(sender) = sender {
+            for stream_id in new_streams {
+                subscribe_stream(stream_client, &sender, stream_id).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn start_receive_message(
+        &self,
+        on_event: Channel<CherryMessage>,
+        app: tauri::AppHandle,
+    ) -> Result<()> {
         let state = self.clone();
         tokio::spawn(async move {
             let mut decoder_machine = StreamRecordDecoderMachine::new();
             // state.init(&app).await.map_err(CommandError::from)?;
             let stream_client = state.get_stream_client().unwrap();
-            let (sender, mut receiver) = stream_client
-                .open_stream()
+            let (sender, mut receiver, presence) = stream_client
+                .open_stream_with_presence()
                 .await
                 .context("Failed to open stream")
                 .unwrap();
+            if let Some(mut presence) = presence {
+                state
+                    .presence_sender
+                    .lock()
+                    .unwrap()
+                    .replace(presence.sender);
+                let on_presence = on_event.clone();
+                tokio::spawn(async move {
+                    while let Some(response) = presence.receiver.recv().await {
+                        let message = match response {
+                            StreamControlResponse::Presence { user_id, status } => {
+                                CherryMessage::Presence { user_id, status }
+                            }
+                            StreamControlResponse::Typing {
+                                user_id,
+                                conversation_id,
+                                typing,
+                            } => CherryMessage::Typing {
+                                user_id,
+                                conversation_id,
+                                typing,
+                            },
+                            _ => continue,
+                        };
+                        if let Err(e) = on_presence.send(message) {
+                            log::error!("send presence error: {:?}", e);
+                        }
+                    }
+                });
+            }
 
             let cherry_client = state.get_cherry_client().unwrap();
             let conversations = cherry_client
@@ -239,6 +418,7 @@ impl AppState {
                 .unwrap();
             log::info!("start_receive_message: conversations={:?}", conversations);
             *state.conversations.lock().unwrap() = conversations;
+            let own_user_id = state.user_info.lock().unwrap().as_ref().map(|u| u.user_id);
 
             // Collect conversations into a local variable to avoid holding MutexGuard across async boundary
             let conversations = state.conversations.lock().unwrap().clone();
@@ -249,17 +429,21 @@ impl AppState {
                     conversation.conversation_id,
                     stream_id
                 );
-                sender
-                    .send(StreamReadRequest {
-                        stream_id,
-                        offset: 0,
-                    })
+                subscribe_stream(&stream_client, &sender, stream_id)
                     .await
-                    .map_err(|e| anyhow::anyhow!("Failed to send StreamReadRequest: {}", e))
                     .unwrap();
             }
 
+            // 通知流携带会话的创建、成员变化、改名和删除事件，以及联系人关系变化
+            match state.find_notification_stream_id().await {
+                Ok(stream_id) => subscribe_stream(&stream_client, &sender, stream_id)
+                    .await
+                    .unwrap(),
+                Err(e) => log::error!("find notification stream error: {:?}", e),
+            }
+
             state.read_stream_sender.lock().unwrap().replace(sender);
+            let offset_committer = spawn_offset_committer(stream_client.clone());
 
             while let Some(response) = receiver.recv().await {
                 //log::info!("Received message: {:?}", response);
@@ -268,6 +452,10 @@ impl AppState {
                     response.offset,
                     response.data.as_slice(),
                 );
+                let mut conversations_changed = false;
+                let mut contacts_changed = false;
+                // 本批收到的最后一条消息，用于回执送达位置
+                let mut delivered: Option<(Uuid, i64)> = None;
                 if let Ok(Some(records)) = records {
                     for (record, offset) in records {
                         match record.meta.data_format {
@@ -282,7 +470,9 @@ impl AppState {
                                     decoded_message
                                 );
 
-                                match state.repo.receive_message(decoded_message).await {
+                                delivered = Some((decoded_message.conversation_id, decoded_message.id));
+                                let read_up_to = state.read_position(decoded_message.conversation_id);
+                                match state.repo.receive_message(decoded_message, read_up_to).await {
                                     Ok(snapshot) => {
                                         log::info!("receive_message: {:?}", snapshot);
                                         on_event
@@ -296,17 +486,66 @@ impl AppState {
                             }
                             DataFormat::JsonEvent => {
                                 let decoded_event: StreamEvent =
-                                    serde_json::from_slice(&record.content).unwrap();
+                                    match serde_json::from_slice(&record.content) {
+                                        Ok(event) => event,
+                                        Err(e) => {
+                                            log::error!("decode event error: {:?}", e);
+                                            continue;
+                                        }
+                                    };
                                 log::info!("Decoded event: {:?}", decoded_event);
-                                // on_event
-                                //     .send(CherryMessage::Event {
-                                //         event: decoded_event,
-                                //     })
-                                //     .unwrap();
+                                // 其他成员的回执、置顶和收藏不影响会话列表
+                                let only_forward = matches!(
+                                    decoded_event,
+                                    StreamEvent::ReadUpTo { user_id, .. } if Some(user_id) != own_user_id
+                                ) || matches!(
+                                    decoded_event,
+                                    StreamEvent::MessagePinned { .. }
+                                        | StreamEvent::MessageUnpinned { .. }
+                                        | StreamEvent::MessageStarred { .. }
+                                );
+                                if only_forward {
+                                    // 只转发给前端
+                                } else if decoded_event.conversation_id().is_some() {
+                                    conversations_changed = true;
+                                } else {
+                                    contacts_changed = true;
+                                }
+                                if let Err(e) = on_event.send(CherryMessage::Event {
+                                    event: decoded_event,
+                                }) {
+                                    log::error!("send event error: {:?}", e);
+                                }
                             }
                         }
                     }
                 }
+
+                if let Some((conversation_id, offset)) = delivered {
+                    if let Err(e) = cherry_client
+                        .update_receipt(conversation_id, None, Some(offset))
+                        .await
+                    {
+                        log::error!("update delivered receipt error: {:?}", e);
+                    }
+                }
+
+                // 一批事件只刷新一次会话列表
+                if conversations_changed {
+                    if let Err(e) = state.refresh_conversations(&app, &stream_client).await {
+                        log::error!("refresh conversations error: {:?}", e);
+                    }
+                }
+                if contacts_changed {
+                    if let Err(e) = state.emit_contacts_updated(&app).await {
+                        log::error!("refresh contacts error: {:?}", e);
+                    }
+                }
+
+                // 消息已保存到本地，记录读取位置，由提交任务合并后提交
+                if let Some(offset) = decoder_machine.decoded_offset(response.stream_id) {
+                    let _ = offset_committer.send((response.stream_id, offset));
+                }
             }
         });
 
@@ -353,8 +592,10 @@ async fn cmd_login(
 
     log::info!("login_response: {:?}", login_response);
 
-    let cherry_client =
-        cherry_client.with_auth((&login_response.user_info.user_id, &login_response.jwt_token));
+    // CherryClient 使用刷新令牌自动续期访问令牌
+    let cherry_client = cherry_client
+        .with_auth((&login_response.user_info.user_id, &login_response.jwt_token))
+        .with_refresh_token(login_response.refresh_token.clone());
     state.cherry_client.lock().unwrap().replace(cherry_client);
 
     let stream_client = StreamClient::new(
@@ -371,16 +612,16 @@ async fn cmd_login(
 
     // 登录成功后初始化数据并通知前端
 
+    // 克隆user_info以避免移动问题
+    let user_info = login_response.user_info.clone();
+    state.user_info.lock().unwrap().replace(user_info.clone());
+
     // 启动消息接收
-    match state.start_receive_message(on_event).await {
+    match state.start_receive_message(on_event, app.clone()).await {
         Ok(_) => log::info!("Message receiver started"),
         Err(e) => log::error!("Failed to start message receiver: {:?}", e),
     }
 
-    // 克隆user_info以避免移动问题
-    let user_info = login_response.user_info.clone();
-    state.user_info.lock().unwrap().replace(user_info.clone());
-
     // 返回包含jwt_token的完整响应
     let response = serde_json::json!({
         "user_id": user_info.user_id.to_string(),
@@ -486,6 +727,144 @@ async fn cmd_refresh_contacts(
     Ok(contacts)
 }
 
+// 好友请求、接受、拒绝、撤回以及拉黑/取消拉黑
+#[tauri::command]
+async fn cmd_contact_action(
+    target_id: Uuid,
+    action: String,
+    state: State<'_, AppState>,
+) -> Result<ContactRelationResponse, CommandError> {
+    let cherry_client = state.get_cherry_client()?;
+    let response = match action.as_str() {
+        "request" => cherry_client.send_friend_request(target_id).await,
+        "accept" => cherry_client.accept_friend_request(target_id).await,
+        "reject" => cherry_client.reject_friend_request(target_id).await,
+        "cancel" => cherry_client.cancel_friend_request(target_id).await,
+        "block" => cherry_client.block_contact(target_id).await,
+        "unblock" => cherry_client.unblock_contact(target_id).await,
+        _ => {
+            return Err(CommandError {
+                message: format!("Unknown contact action: {}", action),
+            });
+        }
+    }?;
+    // 联系人列表由通知流中的 ContactUpdated 事件刷新
+    Ok(response)
+}
+
+#[tauri::command]
+async fn cmd_update_contact(
+    target_id: Uuid,
+    update: UpdateContactRequest,
+    state: State<'_, AppState>,
+) -> Result<Contact, CommandError> {
+    let cherry_client = state.get_cherry_client()?;
+    let contact = cherry_client.update_contact(target_id, &update).await?;
+    Ok(contact)
+}
+
+// 通过邀请码入群，需要审批时返回 pending
+#[tauri::command]
+async fn cmd_join_conversation(
+    code: String,
+    message: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<JoinConversationResponse, CommandError> {
+    let cherry_client = state.get_cherry_client()?;
+    let response = cherry_client
+        .join_conversation(&code, message.as_deref())
+        .await?;
+    // 加入后会话列表由通知流中的 ConversationMemberAdded 事件刷新
+    Ok(response)
+}
+
+#[tauri::command]
+async fn cmd_review_join_request(
+    request_id: Uuid,
+    approve: bool,
+    state: State<'_, AppState>,
+) -> Result<JoinRequest, CommandError> {
+    let cherry_client = state.get_cherry_client()?;
+    let request = if approve {
+        cherry_client.approve_join_request(request_id).await?
+    } else {
+        cherry_client.reject_join_request(request_id).await?
+    };
+    Ok(request)
+}
+
+// 修改群资料，avatar_url/topic 传空字符串删除，会话列表由会话流中的事件刷新
+#[tauri::command]
+async fn cmd_update_conversation_meta(
+    conversation_id: Uuid,
+    name: Option<String>,
+    avatar_url: Option<String>,
+    topic: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<Conversation, CommandError> {
+    let cherry_client = state.get_cherry_client()?;
+    let request = UpdateConversationMetaRequest {
+        conversation_id,
+        name,
+        avatar_url: avatar_url.map(Some),
+        topic: topic.map(Some),
+    };
+    let conversation = cherry_client.update_conversation_meta(&request).await?;
+    Ok(conversation)
+}
+
+#[tauri::command]
+async fn cmd_pin_message(
+    conversation_id: Uuid,
+    message_id: i64,
+    pinned: bool,
+    state: State<'_, AppState>,
+) -> Result<Vec<PinnedMessage>, CommandError> {
+    let cherry_client = state.get_cherry_client()?;
+    let pins = if pinned {
+        cherry_client.pin_message(conversation_id, message_id).await?
+    } else {
+        cherry_client.unpin_message(conversation_id, message_id).await?
+    };
+    Ok(pins)
+}
+
+#[tauri::command]
+async fn cmd_list_pinned_messages(
+    conversation_id: Uuid,
+    state: State<'_, AppState>,
+) -> Result<Vec<PinnedMessage>, CommandError> {
+    let cherry_client = state.get_cherry_client()?;
+    let pins = cherry_client.list_pinned_messages(conversation_id).await?;
+    Ok(pins)
+}
+
+#[tauri::command]
+async fn cmd_star_message(
+    conversation_id: Uuid,
+    message_id: i64,
+    starred: bool,
+    state: State<'_, AppState>,
+) -> Result<(), CommandError> {
+    let cherry_client = state.get_cherry_client()?;
+    if starred {
+        cherry_client.star_message(conversation_id, message_id).await?;
+    } else {
+        cherry_client.unstar_message(conversation_id, message_id).await?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn cmd_list_starred_messages(
+    conversation_id: Option<Uuid>,
+    state: State<'_, AppState>,
+) -> Result<Vec<StarredMessage>, CommandError> {
+    let cherry_client = state.get_cherry_client()?;
+    let messages = cherry_client.list_starred_messages(conversation_id).await?;
+    Ok(messages)
+}
+
 #[tauri::command]
 async fn cmd_send_message(
     conversation_id: String,
@@ -523,9 +902,9 @@ async fn cmd_send_message(
         user_info.as_ref().unwrap().user_id
     };
 
-    // 创建消息
+    // 创建消息，id、user_id 和 timestamp 由服务器填写
     let message = Message {
-        id: 0, // 服务器会分配ID
+        id: 0,
         user_id,
         content,
         reply_to,
@@ -583,6 +962,24 @@ fn greet(name: &str) -> String {
     format!("Hello, {}! You've been greeted from Rust!", name)
 }
 
+#[tauri::command]
+async fn cmd_logout(state: State<'_, AppState>) -> Result<(), CommandError> {
+    log::info!("cmd_logout");
+    let cherry_client = state.get_cherry_client()?;
+    if let Err(e) = cherry_client.logout().await {
+        log::error!("logout error: {:?}", e);
+    }
+
+    state.cherry_client.lock().unwrap().take();
+    state.stream_client.lock().unwrap().take();
+    state.file_client.lock().unwrap().take();
+    state.read_stream_sender.lock().unwrap().take();
+    state.presence_sender.lock().unwrap().take();
+    state.user_info.lock().unwrap().take();
+    state.conversations.lock().unwrap().clear();
+    Ok(())
+}
+
 #[tauri::command]
 async fn cmd_validate_token(
     token: String,
@@ -624,8 +1021,6 @@ async fn cmd_save_read_position(
         user_info.as_ref().unwrap().user_id
     };
 
-    // 这里应该调用后端API保存读取位置
-    // 暂时只记录日志
     log::info!(
         "Saving read position for user {} in conversation {}: message_id {}",
         user_id,
@@ -633,16 +1028,80 @@ async fn cmd_save_read_position(
         last_read_message_id
     );
 
-    let mut conversation_read_position = state.conversation_read_position.lock().unwrap();
-    conversation_read_position.insert(conversation_uuid, last_read_message_id);
+    state
+        .conversation_read_position
+        .lock()
+        .unwrap()
+        .insert(conversation_uuid, last_read_message_id);
+    state.repo.mark_read(conversation_uuid, last_read_message_id).await?;
 
-    // TODO: 实现实际的API调用
-    // let cherry_client = state.get_cherry_client()?;
-    // cherry_client.save_read_position(conversation_uuid, last_read_message_id).await?;
+    // 同步到服务器，其他成员会收到已读回执
+    let cherry_client = state.get_cherry_client()?;
+    cherry_client
+        .update_receipt(conversation_uuid, Some(last_read_message_id), None)
+        .await?;
 
     Ok(())
 }
 
+// 发布在线状态（online/away），断开连接后服务器自动设为 offline
+#[tauri::command]
+async fn cmd_set_presence(
+    status: PresenceStatus,
+    state: State<'_, AppState>,
+) -> Result<(), CommandError> {
+    log::info!("cmd_set_presence: status={:?}", status);
+    state
+        .send_presence(StreamControlRequest::Presence { status })
+        .await?;
+    Ok(())
+}
+
+// 输入状态只发给在线的会话成员，前端在停止输入或发送消息后发送 typing=false
+#[tauri::command]
+async fn cmd_send_typing(
+    conversation_id: String,
+    typing: bool,
+    state: State<'_, AppState>,
+) -> Result<(), CommandError> {
+    let conversation_id = Uuid::parse_str(&conversation_id).map_err(|e| CommandError {
+        message: format!("Invalid conversation ID format: {}", e),
+    })?;
+    state
+        .send_presence(StreamControlRequest::Typing {
+            conversation_id,
+            typing,
+        })
+        .await?;
+    Ok(())
+}
+
+// 服务器端搜索，只返回用户所在会话的文本消息，按时间从新到旧
+#[tauri::command]
+async fn cmd_search_messages(
+    query: String,
+    conversation_id: Option<Uuid>,
+    before: Option<chrono::DateTime<chrono::Utc>>,
+    limit: Option<i64>,
+    state: State<'_, AppState>,
+) -> Result<Vec<SearchResult>, CommandError> {
+    log::info!(
+        "cmd_search_messages: query={}, conversation_id={:?}, before={:?}",
+        query,
+        conversation_id,
+        before
+    );
+    let cherry_client = state.get_cherry_client()?;
+    let request = SearchMessagesRequest {
+        q: query,
+        conversation_id,
+        before,
+        limit,
+    };
+    let response = cherry_client.search_messages(&request).await?;
+    Ok(response.results)
+}
+
 #[tauri::command]
 async fn cmd_get_read_position(
     conversation_id: String,
@@ -661,24 +1120,16 @@ async fn cmd_get_read_position(
         user_info.as_ref().unwrap().user_id
     };
 
-    // 这里应该调用后端API获取读取位置
-    // 暂时返回None
+    // 本地没有保存时使用服务器返回的已读位置
     log::info!(
         "Getting read position for user {} in conversation {}",
         user_id,
         conversation_uuid
     );
 
-    let conversation_read_position = state.conversation_read_position.lock().unwrap();
-    let read_position = conversation_read_position.get(&conversation_uuid);
+    let read_position = state.read_position(conversation_uuid);
     log::info!("read_position: {:?}", read_position);
-    Ok(read_position.cloned())
-
-    // TODO: 实现实际的API调用
-    // let cherry_client = state.get_cherry_client()?;
-    // let position = cherry_client.get_read_position(conversation_uuid).await?;
-
-    // Ok(None)
+    Ok(read_position)
 }
 
 #[tauri::command]
@@ -795,6 +1246,69 @@ async fn cmd_list_messages(
     Ok(messages)
 }
 
+// 按需从 streamserver 拉取历史消息，offset 必须是消息的起始位置(消息 id)
+#[tauri::command]
+async fn cmd_fetch_history(
+    conversation_id: Uuid,
+    offset: u64,
+    limit: u64,
+    state: State<'_, AppState>,
+) -> Result<serde_json::Value, CommandError> {
+    log::info!(
+        "cmd_fetch_history: conversation_id={}, offset={}, limit={}",
+        conversation_id,
+        offset,
+        limit
+    );
+    let stream_id = {
+        let conversations = state.conversations.lock().unwrap();
+        conversations
+            .iter()
+            .find(|c| c.conversation_id == conversation_id)
+            .map(|c| c.stream_id)
+            .ok_or_else(|| CommandError {
+                message: format!("Conversation not found: {}", conversation_id),
+            })?
+    };
+
+    let stream_client = state.get_stream_client()?;
+    let response = match stream_client
+        .read_stream_range(stream_id, offset, limit, true)
+        .await?
+    {
+        Some(response) => response,
+        None => {
+            return Ok(serde_json::json!({ "messages": [], "next_offset": null }));
+        }
+    };
+    let next_offset = response.offset + response.data.len() as u64;
+
+    let mut messages = Vec::new();
+    let mut decoder = StreamRecordDecoder::new(stream_id, response.offset, response.data);
+    if let Some(records) = decoder.decode_all()? {
+        for (record, offset) in records {
+            if !matches!(record.meta.data_format, DataFormat::JsonMessage) {
+                continue;
+            }
+            let mut message: Message = match serde_json::from_slice(&record.content) {
+                Ok(message) => message,
+                Err(e) => {
+                    log::error!("decode history message error: {:?}", e);
+                    continue;
+                }
+            };
+            message.id = offset as i64;
+            let read_up_to = state.read_position(conversation_id);
+            messages.push(state.repo.receive_message(message, read_up_to).await?);
+        }
+    }
+
+    Ok(serde_json::json!({
+        "messages": messages,
+        "next_offset": next_offset,
+    }))
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub async fn run() {
     // set rust_log to use the environment variable RUST_LOG
@@ -834,6 +1348,7 @@ pub async fn run() {
                 user_info: Mutex::new(None),
                 conversation_read_position: Mutex::new(HashMap::new()),
                 read_stream_sender: Mutex::new(None),
+                presence_sender: Mutex::new(None),
                 repo: Repo::new(db_path.to_str().unwrap()).await,
             }),
         })
@@ -862,15 +1377,29 @@ pub async fn run() {
             cmd_conversation_list_all,
             cmd_create_conversation,
             cmd_refresh_contacts,
+            cmd_contact_action,
+            cmd_update_contact,
+            cmd_join_conversation,
+            cmd_review_join_request,
+            cmd_update_conversation_meta,
+            cmd_pin_message,
+            cmd_list_pinned_messages,
+            cmd_star_message,
+            cmd_list_starred_messages,
             cmd_refresh_conversations,
             cmd_send_message,
             cmd_validate_token,
+            cmd_logout,
             cmd_save_read_position,
             cmd_get_read_position,
+            cmd_set_presence,
+            cmd_send_typing,
+            cmd_search_messages,
             cmd_upload_file,
             cmd_download_file,
             cmd_get_file_info,
             cmd_list_messages,
+            cmd_fetch_history,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -1,12 +1,16 @@
 // src/hooks/useMessageReceiver.ts
 import { useEffect, useState } from 'react';
 import { getEventService } from '../services/eventService';
-import type { CherryMessage, Message, StreamEvent } from '../types';
+import type { CherryMessage, Message, PresenceStatus, StreamEvent } from '../types';
 
 export const useMessageReceiver = () => {
   const [messages, setMessages] = useState<Message[]>([]);
   const [streamEvents, setStreamEvents] = useState<StreamEvent[]>([]);
   const [isListening, setIsListening] = useState(false);
+  // 联系人的在线状态，按用户 id
+  const [presences, setPresences] = useState<Record<string, PresenceStatus>>({});
+  // 各会话中正在输入的成员
+  const [typingUsers, setTypingUsers] = useState<Record<string, string[]>>({});
 
   useEffect(() => {
     let unlisten: (() => void) | undefined;
@@ -26,6 +30,16 @@ export const useMessageReceiver = () => {
             const newEvent = msg.event;
             setStreamEvents(prev => [...prev, newEvent]);
             console.log('Received stream event:', newEvent);
+          } else if ('status' in msg) {
+            setPresences(prev => ({ ...prev, [msg.user_id]: msg.status }));
+          } else if ('typing' in msg) {
+            setTypingUsers(prev => {
+              const users = (prev[msg.conversation_id] || []).filter(id => id !== msg.user_id);
+              return {
+                ...prev,
+                [msg.conversation_id]: msg.typing ? [...users, msg.user_id] : users,
+              };
+            });
           } else {
             console.warn('Unknown message structure:', msg);
           }
@@ -51,6 +65,8 @@ export const useMessageReceiver = () => {
   return {
     messages,       // 所有普通消息
     streamEvents,   // 所有流事件
+    presences,      // 联系人的在线状态
+    typingUsers,    // 各会话中正在输入的成员
     isListening,    // 监听状态
     clearMessages: () => setMessages([]),
     clearEvents: () => setStreamEvents([]),
@@ -1,8 +1,12 @@
 import type { ConversationService } from './types';
 import type { ConversationBase } from '@/types/models/conversation';
+import type { PinnedMessage } from '@/types';
 import type { Contact } from '@/types/models/contact';
 import { conversationDb, defaultConversationDb, initializeConversationDb } from './data/db';
 
+// 模拟环境的置顶消息只保存在内存中
+const mockPins = new Map<string, PinnedMessage[]>();
+
 // 确保只初始化一次
 let isInitialized = false;
 
@@ -60,5 +64,48 @@ export const mockConversationService: ConversationService = {
     await conversationDb.write(data);
     
     return { conversation_id: newConversation.conversation_id };
+  },
+
+  updateConversationMeta: async (conversationId, update) => {
+    await initOnce();
+    const data = await conversationDb.read() || defaultConversationDb;
+    const conversation = data.conversations.find(conv => conv.conversation_id === conversationId);
+    if (!conversation) {
+      throw new Error(`Conversation ${conversationId} not found`);
+    }
+    const meta = { ...conversation.meta };
+    if (update.name !== undefined) {
+      meta.name = update.name.trim();
+    }
+    for (const key of ['avatar_url', 'topic'] as const) {
+      const value = update[key];
+      if (value === null || value === '') {
+        delete meta[key];
+      } else if (value !== undefined) {
+        meta[key] = value;
+      }
+    }
+    conversation.meta = meta;
+    conversation.updated_at = new Date().toISOString();
+    await conversationDb.write(data);
+    return conversation;
+  },
+
+  pinMessage: async (conversationId, messageId, pinned) => {
+    const pins = (mockPins.get(conversationId) || []).filter(pin => pin.message_id !== messageId);
+    if (pinned) {
+      pins.unshift({
+        conversation_id: conversationId,
+        message_id: messageId,
+        pinned_by: 'mock-user-id',
+        pinned_at: new Date().toISOString()
+      });
+    }
+    mockPins.set(conversationId, pins);
+    return pins;
+  },
+
+  listPinnedMessages: async (conversationId) => {
+    return mockPins.get(conversationId) || [];
   }
 }; 
\ No newline at end of file
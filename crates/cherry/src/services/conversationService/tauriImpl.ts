@@ -1,6 +1,6 @@
 import { invoke } from '@tauri-apps/api/core';
 import type { ConversationService } from './types';
-import type { ConversationBase, Contact } from '@/types';
+import type { ConversationBase, Contact, PinnedMessage } from '@/types';
 
 export const tauriConversationService: ConversationService = {
   listAllConversations: async () => {
@@ -14,5 +14,19 @@ export const tauriConversationService: ConversationService = {
       conversationType: 'direct',
       members: [targetUserId],
     });
+  },
+  updateConversationMeta: async (conversationId, update) => {
+    return await invoke<ConversationBase>('cmd_update_conversation_meta', {
+      conversationId,
+      name: update.name,
+      avatarUrl: update.avatar_url,
+      topic: update.topic
+    });
+  },
+  pinMessage: async (conversationId, messageId, pinned) => {
+    return await invoke<PinnedMessage[]>('cmd_pin_message', { conversationId, messageId, pinned });
+  },
+  listPinnedMessages: async (conversationId) => {
+    return await invoke<PinnedMessage[]>('cmd_list_pinned_messages', { conversationId });
   }
 }; 
\ No newline at end of file
@@ -1,10 +1,21 @@
-import type { ConversationBase } from '@/types';
+import type { ConversationBase, PinnedMessage } from '@/types';
 import type { Contact } from '@/types';
 
+// 群资料修改，不传的字段不变，avatar_url/topic 传 null 删除
+export interface ConversationMetaUpdate {
+  name?: string;
+  avatar_url?: string | null;
+  topic?: string | null;
+}
+
 export interface ConversationService {
   listAllConversations(): Promise<ConversationBase[]>;
   listAllContacts(): Promise<Contact[]>;
   createConversation(targetUserId: string): Promise<{ conversation_id: string }>;
+  updateConversationMeta(conversationId: string, update: ConversationMetaUpdate): Promise<ConversationBase>;
+  // 置顶或取消置顶，返回会话当前的置顶消息
+  pinMessage(conversationId: string, messageId: number, pinned: boolean): Promise<PinnedMessage[]>;
+  listPinnedMessages(conversationId: string): Promise<PinnedMessage[]>;
 }
 
 export class ServiceError extends Error {
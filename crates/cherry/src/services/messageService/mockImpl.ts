@@ -1,9 +1,12 @@
 import type { MessageService } from './types';
-import type { Message, ReactionContent } from '@/types';
+import type { Message, ReactionContent, SearchResult, StarredMessage } from '@/types';
 import { messageDb, defaultMessageDb } from './data/db';
 import { listenerService } from '../listenService';
 import { makeNewMessageEvent as NewMessageEvent } from '@/types/events';
 
+// 模拟环境的收藏只保存在内存中
+let mockStars: StarredMessage[] = [];
+
 // 声明window全局变量类型
 
 // 初始化全局变量
@@ -127,5 +130,47 @@ export const mockMessageService: MessageService = {
       result = messages.slice(start, end);
     }
     return result;
+  },
+  sendTyping: async (conversationId, typing) => {
+    // 模拟环境没有其他在线成员
+    console.log('sendTyping', conversationId, typing);
+  },
+  searchMessages: async (query, conversationId, before, limit = 20) => {
+    const data = await messageDb.read();
+    const keyword = query.trim().toLowerCase();
+    if (!data || !keyword) {
+      return [];
+    }
+    const conversationIds = conversationId ? [conversationId] : Object.keys(data.messagesMap);
+    const results: SearchResult[] = conversationIds
+      .flatMap((id) => (data.messagesMap[id] || []) as Message[])
+      .filter((msg) => ['text', 'quill', 'code'].includes(msg.type_))
+      .map((msg) => ({
+        conversation_id: msg.conversation_id,
+        message_id: msg.id,
+        user_id: msg.user_id,
+        type: msg.type_,
+        content: typeof msg.content === 'string' ? msg.content : JSON.stringify(msg.content),
+        timestamp: msg.timestamp
+      }))
+      .filter((result) => result.content.toLowerCase().includes(keyword))
+      .filter((result) => !before || result.timestamp < before);
+    results.sort((a, b) => b.timestamp.localeCompare(a.timestamp));
+    return results.slice(0, limit);
+  },
+  starMessage: async (conversationId, messageId, starred) => {
+    mockStars = mockStars.filter(
+      (star) => star.conversation_id !== conversationId || star.message_id !== messageId
+    );
+    if (starred) {
+      mockStars.unshift({
+        conversation_id: conversationId,
+        message_id: messageId,
+        starred_at: new Date().toISOString()
+      });
+    }
+  },
+  listStarredMessages: async (conversationId) => {
+    return mockStars.filter((star) => !conversationId || star.conversation_id === conversationId);
   }
 }; 
\ No newline at end of file
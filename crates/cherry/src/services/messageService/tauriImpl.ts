@@ -1,6 +1,6 @@
 import { invoke } from '@tauri-apps/api/core';
 import type { MessageService } from './types';
-import type { Message } from '@/types';
+import type { Message, SearchResult, StarredMessage } from '@/types';
 
 export const tauriMessageService: MessageService = {
   sendMessage: async (conversationId, content, messageType = 'text', replyTo) => {
@@ -18,5 +18,22 @@ export const tauriMessageService: MessageService = {
       backwardId: direction === 'backward' ? messageId : undefined,
       limit
     });
+  },
+  sendTyping: async (conversationId, typing) => {
+    await invoke('cmd_send_typing', { conversationId, typing });
+  },
+  searchMessages: async (query, conversationId, before, limit) => {
+    return await invoke<SearchResult[]>('cmd_search_messages', {
+      query,
+      conversationId,
+      before,
+      limit
+    });
+  },
+  starMessage: async (conversationId, messageId, starred) => {
+    await invoke('cmd_star_message', { conversationId, messageId, starred });
+  },
+  listStarredMessages: async (conversationId) => {
+    return await invoke<StarredMessage[]>('cmd_list_starred_messages', { conversationId });
   }
 }; 
\ No newline at end of file
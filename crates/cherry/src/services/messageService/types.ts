@@ -1,10 +1,10 @@
-import type { Message, MessageContentType } from '@/types';
+import type { Message, MessageAmendmentType, MessageContentType, SearchResult, StarredMessage } from '@/types';
 
 export interface MessageService {
   sendMessage(
     conversationId: string,
     content: string,
-    messageType: MessageContentType,
+    messageType: MessageContentType | MessageAmendmentType,
     replyTo?: number
   ): Promise<void>;
 
@@ -14,6 +14,21 @@ export interface MessageService {
     direction: 'forward' | 'backward',
     limit: number
   ): Promise<Message[]>;
+
+  // 通知会话的其他成员正在输入，停止输入或发送后传 false
+  sendTyping(conversationId: string, typing: boolean): Promise<void>;
+
+  // 搜索所在会话的消息，不传 conversationId 时搜索全部会话，before 用于翻页
+  searchMessages(
+    query: string,
+    conversationId?: string,
+    before?: string,
+    limit?: number
+  ): Promise<SearchResult[]>;
+
+  // 收藏只对自己可见，会同步到自己的其他设备
+  starMessage(conversationId: string, messageId: number, starred: boolean): Promise<void>;
+  listStarredMessages(conversationId?: string): Promise<StarredMessage[]>;
 }
 
 export class ServiceError extends Error {
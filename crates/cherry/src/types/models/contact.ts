@@ -12,6 +12,16 @@ export interface Contact {
     tags: any[];
     is_favorite: boolean;
     mute_settings: any;
+    custom_fields?: Record<string, any>;
+  }
+
+  // 联系人的部分更新，未给出的字段保持不变
+  export interface UpdateContactRequest {
+    remark_name?: string | null;
+    tags?: string[];
+    is_favorite?: boolean;
+    mute_settings?: { muted: boolean; expire_at?: string | null };
+    custom_fields?: Record<string, any>;
   }
 
   // 群组类型
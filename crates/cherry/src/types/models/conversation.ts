@@ -10,10 +10,15 @@ export interface ConversationBase{
       name: string;
       description: string;
       avatar: string;
+      // 群资料，由 /api/v1/conversations/meta 修改
+      avatar_url?: string;
+      topic?: string;
     };
     stream_id: number;
     created_at: string;
     updated_at: string;
+    read_up_to?: number | null;
+    unread_count?: number | null; // 最多 999
   }
   
   export interface Conversation {
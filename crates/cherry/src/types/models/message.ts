@@ -125,6 +125,15 @@ export type MessageContent =
 
 export type MessageContentType = 'text' | 'image' | 'audio' | 'video' | 'file' | 'system' | 'emoji' | 'code' | 'location' | 'contact' | 'event' | 'custom' | 'reaction' | 'quill'
 
+// 编辑和撤回消息，reply_to 指向被修改的消息，只有作者可以发送
+export type MessageAmendmentType = 'edit' | 'delete';
+
+// 编辑前的内容
+export interface MessageEdit {
+    content: MessageContent;
+    timestamp: string;
+}
+
 export interface Message {
     id: number;
     conversation_id: string;
@@ -136,6 +145,9 @@ export interface Message {
     replyToMessage?: Message;
     isReply?: boolean;
     reactions?: Reaction[];
+    status?: 'unread' | 'read' | 'deleted'; // deleted 表示已撤回，content 为 null
+    edit_history?: MessageEdit[];
+    edited_at?: string;
 }
 
 // 解析后的消息内容类型
@@ -175,6 +187,14 @@ export interface ParsedMessageContent {
 
 
 
+// 群权限，all 表示所有成员，admins 表示群主和管理员
+export interface ConversationPermissions {
+    post: 'all' | 'admins';
+    invite: 'all' | 'admins';
+    edit_info: 'all' | 'admins';
+    pin: 'all' | 'admins';
+}
+
 // 流事件类型
 export interface StreamEvent {
     ConversationCreated?: {
@@ -188,9 +208,104 @@ export interface StreamEvent {
         conversation_id: string;
         member_id: string;
     };
+    ConversationRenamed?: {
+        conversation_id: string;
+        name: string;
+    };
+    ConversationDeleted?: {
+        conversation_id: string;
+    };
+    ConversationMemberRoleChanged?: {
+        conversation_id: string;
+        member_id: string;
+        role: 'owner' | 'admin' | 'member';
+    };
+    ConversationMemberMuted?: {
+        conversation_id: string;
+        member_id: string;
+        muted_until: string | null;
+    };
+    ConversationPermissionsChanged?: {
+        conversation_id: string;
+        permissions: ConversationPermissions;
+    };
+    ConversationJoinRequested?: {
+        conversation_id: string;
+        request_id: string;
+        user_id: string;
+    };
+    ConversationJoinRequestReviewed?: {
+        conversation_id: string;
+        request_id: string;
+        user_id: string;
+        status: 'approved' | 'rejected';
+    };
+    // 成员的已读/送达位置前进，为消息 id
+    ReadUpTo?: {
+        conversation_id: string;
+        user_id: string;
+        read_up_to: number | null;
+        delivered_up_to: number | null;
+    };
+    ContactUpdated?: {
+        target_id: string;
+        relation_type: 'friend' | 'blocked' | 'pending_outgoing' | 'pending_incoming' | null;
+    };
+    // 以下事件记录在会话的消息流中，meta 为修改后的完整群资料
+    ConversationMetaUpdated?: {
+        conversation_id: string;
+        user_id: string;
+        meta: Record<string, unknown>;
+    };
+    MessagePinned?: {
+        conversation_id: string;
+        message_id: number;
+        user_id: string;
+    };
+    MessageUnpinned?: {
+        conversation_id: string;
+        message_id: number;
+        user_id: string;
+    };
+    // 只发给自己，用于同步其他设备的收藏
+    MessageStarred?: {
+        conversation_id: string;
+        message_id: number;
+        starred: boolean;
+    };
 }
 
-// Cherry消息类型 - 更新为匹配后端格式
+// 会话的置顶消息，message_id 是消息在会话流中的位置
+export interface PinnedMessage {
+    conversation_id: string;
+    message_id: number;
+    pinned_by: string | null;
+    pinned_at: string;
+}
+
+// 自己收藏的消息
+export interface StarredMessage {
+    conversation_id: string;
+    message_id: number;
+    starred_at: string;
+}
+
+// 服务器搜索结果，message_id 是消息在会话流中的位置
+export interface SearchResult {
+    conversation_id: string;
+    message_id: number;
+    user_id: string;
+    type: string;
+    content: string;
+    timestamp: string;
+}
+
+// 在线状态，断开连接后由服务器设为 offline
+export type PresenceStatus = 'online' | 'away' | 'offline';
+
+// Cherry消息类型 - 在线状态和输入状态不保存，只在连接时收到
 export type CherryMessage =
     | { message: Message }
-    | { event: StreamEvent };
+    | { event: StreamEvent }
+    | { user_id: string; status: PresenceStatus }
+    | { user_id: string; conversation_id: string; typing: boolean };
@@ -1,7 +1,9 @@
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 use std::time::Duration;
 
 use anyhow::{Context, Result};
+use base64::Engine;
+use jsonwebtoken::jwk::JwkSet;
 use reqwest::{
     Client, ClientBuilder,
     header::{AUTHORIZATION, CONTENT_TYPE, HeaderMap, HeaderValue},
@@ -11,7 +13,7 @@ use streamstore::StreamId;
 use uuid::Uuid;
 
 use crate::types::{
-    CheckAclRequest, CheckAclResponse, Contact, Conversation, CreateConversationRequest, CreateConversationResponse, ListConversationsResponse, ListStreamRequest, ListStreamResponse, LoginRequest, LoginResponse, ResponseError, User
+    AclAction, ChangePasswordRequest, ChangePasswordResponse, CheckAclRequest, CheckAclResponse, Contact, ContactRelationResponse, ContactTargetRequest, Conversation, ConversationInvite, ConversationMember, ConversationMembersRequest, ConversationMessageRequest, ConversationRequest, ConversationResponse, CreateConversationRequest, CreateConversationResponse, CreateInviteRequest, InviteRequest, JoinConversationRequest, JoinConversationResponse, JoinRequest, JoinRequestAction, ListContactsRequest, ListConversationMembersResponse, ListConversationsResponse, ListInvitesResponse, ListJoinRequestsResponse, ListPinnedMessagesResponse, ListStarredMessagesRequest, ListStarredMessagesResponse, ListStreamRequest, ListStreamResponse, LoginRequest, LoginResponse, LogoutRequest, LogoutResponse, MuteMemberRequest, OidcAuthorizeResponse, OidcCallbackRequest, PinnedMessage, PresenceAudienceRequest, PresenceAudienceResponse, RefreshTokenRequest, RefreshTokenResponse, RegisterRequest, RenameConversationRequest, ResponseError, RevokedTokensRequest, RevokedTokensResponse, SearchMessagesRequest, SearchMessagesResponse, SetMemberRoleRequest, StarMessageResponse, StarredMessage, TransferOwnershipRequest, UpdateContactRequest, UpdateConversationMetaRequest, UpdatePermissionsRequest, UpdatePresenceRequest, UpdateReceiptRequest, User
 };
 
 use super::{ClientConfig, AuthCredentials};
@@ -22,11 +24,26 @@ pub struct CherryClient {
     inner: Arc<CherryClientInner>,
 }
 
-#[derive(Clone)]
 pub struct CherryClientInner {
     config: ClientConfig,
     client: Client,
-    auth: Option<AuthCredentials>,
+    auth: RwLock<Option<AuthCredentials>>,
+    refresh_token: RwLock<Option<String>>,
+    // one refresh at a time, the refresh token is rotated on every use
+    refresh_lock: tokio::sync::Mutex<()>,
+}
+
+/// Access tokens expiring within this many seconds are refreshed before a request.
+const REFRESH_MARGIN_SECONDS: i64 = 60;
+
+// `exp` of a JWT, read without verifying the signature
+fn token_exp(jwt_token: &str) -> Option<i64> {
+    let payload = jwt_token.split('.').nth(1)?;
+    let payload = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(payload)
+        .ok()?;
+    let claims: serde_json::Value = serde_json::from_slice(&payload).ok()?;
+    claims.get("exp")?.as_i64()
 }
 
 impl std::ops::Deref for CherryClient {
@@ -54,13 +71,24 @@ impl CherryClient {
             .build()
             .context("Failed to create HTTP client")?;
 
-        Ok(Self {
+        Ok(Self::from_parts(config, client, None, None))
+    }
+
+    fn from_parts(
+        config: ClientConfig,
+        client: Client,
+        auth: Option<AuthCredentials>,
+        refresh_token: Option<String>,
+    ) -> Self {
+        Self {
             inner: Arc::new(CherryClientInner {
                 config,
                 client,
-                auth: None,
+                auth: RwLock::new(auth),
+                refresh_token: RwLock::new(refresh_token),
+                refresh_lock: tokio::sync::Mutex::new(()),
             }),
-        })
+        }
     }
 
     pub fn new_with_base_url(base_url: String) -> Result<Self> {
@@ -73,14 +101,31 @@ impl CherryClient {
 
     /// Set authentication credentials
     pub fn with_auth(self, auth: impl Into<AuthCredentials>) -> Self {
-        let inner = CherryClientInner {
-            auth: Some(auth.into()),
-            client: self.inner.client.clone(),
-            config: self.inner.config.clone(),
-        };
-        Self {
-            inner: Arc::new(inner),
-        }
+        Self::from_parts(
+            self.config.clone(),
+            self.client.clone(),
+            Some(auth.into()),
+            self.refresh_token(),
+        )
+    }
+
+    /// Set the refresh token used to renew the access token when it expires
+    pub fn with_refresh_token(self, refresh_token: Option<String>) -> Self {
+        Self::from_parts(
+            self.config.clone(),
+            self.client.clone(),
+            self.auth(),
+            refresh_token,
+        )
+    }
+
+    /// Current credentials, they change when the access token is refreshed
+    pub fn auth(&self) -> Option<AuthCredentials> {
+        self.inner.auth.read().unwrap().clone()
+    }
+
+    pub fn refresh_token(&self) -> Option<String> {
+        self.inner.refresh_token.read().unwrap().clone()
     }
 
     /// Create authenticated headers
@@ -91,7 +136,7 @@ impl CherryClient {
         headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
 
         // Set authorization if available
-        if let Some(auth) = &self.auth {
+        if let Some(auth) = self.auth() {
             let auth_value = HeaderValue::from_str(&format!("Bearer {}", auth.jwt_token))
                 .context("Invalid JWT token format")?;
             headers.insert(AUTHORIZATION, auth_value);
@@ -100,28 +145,105 @@ impl CherryClient {
         Ok(headers)
     }
 
-    /// Make an authenticated request
-    async fn request<T, Q>(&self, method: reqwest::Method, endpoint: &str, query: Option<&Q>) -> Result<T>
-    where
-        T: for<'de> Deserialize<'de>,
-        Q: Serialize,
-    {
-        let url = format!("{}{}", self.config.base_url, endpoint);
-        let headers = self.create_headers()?;
+    /// Rotate the tokens with the refresh token, `stale_token` is the access
+    /// token the caller saw, nothing is done if another request already replaced it.
+    async fn refresh_auth(&self, stale_token: &str) -> Result<()> {
+        let _guard = self.refresh_lock.lock().await;
+        let Some(auth) = self.auth() else {
+            return Ok(());
+        };
+        if auth.jwt_token != stale_token {
+            return Ok(());
+        }
+        let refresh_token = self
+            .refresh_token()
+            .ok_or_else(|| anyhow::anyhow!("No refresh token"))?;
 
-        log::info!("request: url={}, headers={:?}", url, headers);
+        let url = format!("{}/api/v1/auth/refresh", self.config.base_url);
+        let response = self
+            .client
+            .post(&url)
+            .json(&RefreshTokenRequest { refresh_token })
+            .send()
+            .await
+            .context("Refresh token request failed")?;
+        if !response.status().is_success() {
+            let status = response.status();
+            // the refresh token is dead, keep it from being retried
+            self.inner.refresh_token.write().unwrap().take();
+            return Err(anyhow::anyhow!("HTTP {}: refresh token rejected", status));
+        }
+        let response = response
+            .json::<RefreshTokenResponse>()
+            .await
+            .context("Failed to deserialize refresh response")?;
+
+        log::info!("access token refreshed, user_id={}", auth.user_id);
+        self.inner
+            .auth
+            .write()
+            .unwrap()
+            .replace(AuthCredentials::new(auth.user_id, response.jwt_token));
+        self.inner
+            .refresh_token
+            .write()
+            .unwrap()
+            .replace(response.refresh_token);
+        Ok(())
+    }
 
-        let req = self.client.request(method, &url).headers(headers);
-        let req = if let Some(q) = query {
-            req.query(&q)
-        } else {
-            req
+    // Refresh ahead of time when the access token is about to expire.
+    async fn refresh_if_expiring(&self) -> Result<()> {
+        let Some(auth) = self.auth() else {
+            return Ok(());
         };
-        let response = req
+        if self.refresh_token().is_none() {
+            return Ok(());
+        }
+        let expiring = token_exp(&auth.jwt_token).is_some_and(|exp| {
+            exp - chrono::Utc::now().timestamp() < REFRESH_MARGIN_SECONDS
+        });
+        if expiring {
+            self.refresh_auth(&auth.jwt_token).await?;
+        }
+        Ok(())
+    }
+
+    /// Send a request built by `build` from the headers, refreshing the access
+    /// token and retrying once when the server answers 401.
+    async fn send<F>(&self, build: F) -> Result<reqwest::Response>
+    where
+        F: Fn(HeaderMap) -> reqwest::RequestBuilder,
+    {
+        if let Err(e) = self.refresh_if_expiring().await {
+            log::warn!("refresh access token failed: {}", e);
+        }
+
+        let token = self.auth().map(|auth| auth.jwt_token);
+        let response = build(self.create_headers()?)
             .send()
             .await
             .context("Request failed")?;
+        if response.status() != reqwest::StatusCode::UNAUTHORIZED || self.refresh_token().is_none() {
+            return Ok(response);
+        }
+        let Some(token) = token else {
+            return Ok(response);
+        };
+        if let Err(e) = self.refresh_auth(&token).await {
+            log::warn!("refresh access token failed: {}", e);
+            return Ok(response);
+        }
+        build(self.create_headers()?)
+            .send()
+            .await
+            .context("Request failed")
+    }
 
+    async fn parse_response<T>(response: reqwest::Response) -> Result<T>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
         if !response.status().is_success() {
             let status = response.status();
             let error_text = response
@@ -137,6 +259,28 @@ impl CherryClient {
             .context("Failed to deserialize response")
     }
 
+    /// Make an authenticated request
+    async fn request<T, Q>(&self, method: reqwest::Method, endpoint: &str, query: Option<&Q>) -> Result<T>
+    where
+        T: for<'de> Deserialize<'de>,
+        Q: Serialize,
+    {
+        let url = format!("{}{}", self.config.base_url, endpoint);
+        log::info!("request: url={}", url);
+
+        let response = self
+            .send(|headers| {
+                let req = self.client.request(method.clone(), &url).headers(headers);
+                if let Some(q) = query {
+                    req.query(&q)
+                } else {
+                    req
+                }
+            })
+            .await?;
+        Self::parse_response(response).await
+    }
+
     /// Make a POST request with JSON body
     async fn request_with_body<T, U>(
         &self,
@@ -149,32 +293,17 @@ impl CherryClient {
         U: for<'de> Deserialize<'de>,
     {
         let url = format!("{}{}", self.config.base_url, endpoint);
-        let headers = self.create_headers()?;
-
-        log::info!("request: url={}, headers={:?}", url, headers);
+        log::info!("request: url={}", url);
 
         let response = self
-            .client
-            .request(method, &url)
-            .headers(headers)
-            .json(body)
-            .send()
-            .await
-            .context("Request failed")?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(anyhow::anyhow!("HTTP {}: {}", status, error_text));
-        }
-
-        response
-            .json::<U>()
-            .await
-            .context("Failed to deserialize response")
+            .send(|headers| {
+                self.client
+                    .request(method.clone(), &url)
+                    .headers(headers)
+                    .json(body)
+            })
+            .await?;
+        Self::parse_response(response).await
     }
 
     /// Login and get authentication credentials
@@ -196,12 +325,73 @@ impl CherryClient {
         Ok(login_response)
     }
 
+    /// Create an account, the response logs the new user in
+    pub async fn register(&self, username: &str, email: &str, password: &str) -> Result<LoginResponse> {
+        let request = RegisterRequest {
+            username: username.to_string(),
+            email: email.to_string(),
+            password: password.to_string(),
+        };
+        self.request_with_body::<RegisterRequest, LoginResponse>(reqwest::Method::POST, "/api/v1/auth/register", &request).await
+    }
+
+    /// Change the password of the authenticated user
+    pub async fn change_password(&self, old_password: &str, new_password: &str) -> Result<ChangePasswordResponse> {
+        let request = ChangePasswordRequest {
+            old_password: old_password.to_string(),
+            new_password: new_password.to_string(),
+        };
+        self.request_with_body::<ChangePasswordRequest, ChangePasswordResponse>(reqwest::Method::POST, "/api/v1/auth/password", &request).await
+    }
+
     /// Get all contacts for the authenticated user
     pub async fn get_contacts(&self) -> Result<Vec<Contact>> {
         self.request::<Vec<Contact>, ()>(reqwest::Method::GET, "/api/v1/contract/list", None)
             .await
     }
 
+    async fn contact_action(&self, endpoint: &str, target_id: Uuid) -> Result<ContactRelationResponse> {
+        let request = ContactTargetRequest { target_id };
+        self.request_with_body::<ContactTargetRequest, ContactRelationResponse>(reqwest::Method::POST, endpoint, &request).await
+    }
+
+    /// Send a friend request, accepts the pending request of the target if any
+    pub async fn send_friend_request(&self, target_id: Uuid) -> Result<ContactRelationResponse> {
+        self.contact_action("/api/v1/contacts/request", target_id).await
+    }
+
+    pub async fn accept_friend_request(&self, target_id: Uuid) -> Result<ContactRelationResponse> {
+        self.contact_action("/api/v1/contacts/accept", target_id).await
+    }
+
+    pub async fn reject_friend_request(&self, target_id: Uuid) -> Result<ContactRelationResponse> {
+        self.contact_action("/api/v1/contacts/reject", target_id).await
+    }
+
+    pub async fn cancel_friend_request(&self, target_id: Uuid) -> Result<ContactRelationResponse> {
+        self.contact_action("/api/v1/contacts/cancel", target_id).await
+    }
+
+    pub async fn block_contact(&self, target_id: Uuid) -> Result<ContactRelationResponse> {
+        self.contact_action("/api/v1/contacts/block", target_id).await
+    }
+
+    pub async fn unblock_contact(&self, target_id: Uuid) -> Result<ContactRelationResponse> {
+        self.contact_action("/api/v1/contacts/unblock", target_id).await
+    }
+
+    /// Contacts matching the filters
+    pub async fn list_contacts(&self, filter: &ListContactsRequest) -> Result<Vec<Contact>> {
+        self.request::<Vec<Contact>, ListContactsRequest>(reqwest::Method::GET, "/api/v1/contacts/list", Some(filter))
+            .await
+    }
+
+    /// Update the remark, tags, favorite, mute settings or custom fields of a contact
+    pub async fn update_contact(&self, target_id: Uuid, update: &UpdateContactRequest) -> Result<Contact> {
+        let endpoint = format!("/api/v1/contacts/{}", target_id);
+        self.request_with_body::<UpdateContactRequest, Contact>(reqwest::Method::PATCH, &endpoint, update).await
+    }
+
     /// Get user by ID
     pub async fn get_user(&self, user_id: Uuid) -> Result<User> {
         self.request::<User, ()>(reqwest::Method::GET, &format!("/api/v1/users/{}", user_id), None)
@@ -209,7 +399,7 @@ impl CherryClient {
     }
 
     pub async fn check_acl(&self, user_id: Uuid, stream_id: Option<StreamId>, conversation_id: Option<Uuid>) -> Result<bool> {
-        let request = CheckAclRequest { user_id, stream_id, conversation_id };
+        let request = CheckAclRequest { user_id, stream_id, conversation_id, action: AclAction::Read };
         let response = self.request::<CheckAclResponse, CheckAclRequest>(reqwest::Method::GET, "/api/v1/acl/check", Some(&request)).await?;
         Ok(response.allowed)
     }
@@ -228,11 +418,173 @@ impl CherryClient {
             members: response.members.iter().map(|m| m.to_string()).collect::<Vec<String>>().into(),
             meta: response.meta,
             stream_id: response.stream_id,
+            permissions: Default::default(),
             created_at: response.created_at,
             updated_at: response.created_at,
+            read_up_to: None,
+            unread_count: None,
         })
     }
 
+    /// Add members to a group conversation
+    pub async fn add_conversation_members(&self, conversation_id: Uuid, members: &[Uuid]) -> Result<Conversation> {
+        let request = ConversationMembersRequest { conversation_id, members: members.to_vec() };
+        self.request_with_body::<ConversationMembersRequest, Conversation>(reqwest::Method::POST, "/api/v1/conversations/members/add", &request).await
+    }
+
+    /// Remove members from a group conversation
+    pub async fn remove_conversation_members(&self, conversation_id: Uuid, members: &[Uuid]) -> Result<Conversation> {
+        let request = ConversationMembersRequest { conversation_id, members: members.to_vec() };
+        self.request_with_body::<ConversationMembersRequest, Conversation>(reqwest::Method::POST, "/api/v1/conversations/members/remove", &request).await
+    }
+
+    /// List the members of a conversation with their roles
+    pub async fn list_conversation_members(&self, conversation_id: Uuid) -> Result<Vec<ConversationMember>> {
+        let request = ConversationRequest { conversation_id };
+        let response = self.request::<ListConversationMembersResponse, ConversationRequest>(reqwest::Method::GET, "/api/v1/conversations/members/list", Some(&request)).await?;
+        Ok(response.members)
+    }
+
+    /// Make a member an admin or a plain member, owner only
+    pub async fn set_member_role(&self, conversation_id: Uuid, member_id: Uuid, role: &str) -> Result<ConversationMember> {
+        let request = SetMemberRoleRequest { conversation_id, member_id, role: role.to_string() };
+        self.request_with_body::<SetMemberRoleRequest, ConversationMember>(reqwest::Method::POST, "/api/v1/conversations/members/role", &request).await
+    }
+
+    /// Mute a member until `muted_until`, None unmutes
+    pub async fn mute_member(&self, conversation_id: Uuid, member_id: Uuid, muted_until: Option<chrono::DateTime<chrono::Utc>>) -> Result<ConversationMember> {
+        let request = MuteMemberRequest { conversation_id, member_id, muted_until };
+        self.request_with_body::<MuteMemberRequest, ConversationMember>(reqwest::Method::POST, "/api/v1/conversations/members/mute", &request).await
+    }
+
+    /// Hand the group over to another member, the caller becomes an admin
+    pub async fn transfer_ownership(&self, conversation_id: Uuid, member_id: Uuid) -> Result<ConversationResponse> {
+        let request = TransferOwnershipRequest { conversation_id, member_id };
+        self.request_with_body::<TransferOwnershipRequest, ConversationResponse>(reqwest::Method::POST, "/api/v1/conversations/transfer", &request).await
+    }
+
+    /// Change who may post and invite in a group
+    pub async fn update_conversation_permissions(&self, request: &UpdatePermissionsRequest) -> Result<Conversation> {
+        self.request_with_body::<UpdatePermissionsRequest, Conversation>(reqwest::Method::POST, "/api/v1/conversations/permissions", request).await
+    }
+
+    /// Create an invite code for a group
+    pub async fn create_invite(&self, request: &CreateInviteRequest) -> Result<ConversationInvite> {
+        self.request_with_body::<CreateInviteRequest, ConversationInvite>(reqwest::Method::POST, "/api/v1/conversations/invites/create", request).await
+    }
+
+    /// List the invites of a group, revoked and used up ones included
+    pub async fn list_invites(&self, conversation_id: Uuid) -> Result<Vec<ConversationInvite>> {
+        let request = ConversationRequest { conversation_id };
+        let response = self.request::<ListInvitesResponse, ConversationRequest>(reqwest::Method::GET, "/api/v1/conversations/invites/list", Some(&request)).await?;
+        Ok(response.invites)
+    }
+
+    /// Revoke an invite, it can no longer be used to join
+    pub async fn revoke_invite(&self, invite_id: Uuid) -> Result<ConversationInvite> {
+        let request = InviteRequest { invite_id };
+        self.request_with_body::<InviteRequest, ConversationInvite>(reqwest::Method::POST, "/api/v1/conversations/invites/revoke", &request).await
+    }
+
+    /// Join a group with an invite code, or ask to join when the invite requires approval
+    pub async fn join_conversation(&self, code: &str, message: Option<&str>) -> Result<JoinConversationResponse> {
+        let request = JoinConversationRequest { code: code.to_string(), message: message.map(str::to_string) };
+        self.request_with_body::<JoinConversationRequest, JoinConversationResponse>(reqwest::Method::POST, "/api/v1/conversations/join", &request).await
+    }
+
+    /// List the pending join requests of a group, admins only
+    pub async fn list_join_requests(&self, conversation_id: Uuid) -> Result<Vec<JoinRequest>> {
+        let request = ConversationRequest { conversation_id };
+        let response = self.request::<ListJoinRequestsResponse, ConversationRequest>(reqwest::Method::GET, "/api/v1/conversations/join_requests/list", Some(&request)).await?;
+        Ok(response.requests)
+    }
+
+    /// Approve a join request, the requester becomes a member
+    pub async fn approve_join_request(&self, request_id: Uuid) -> Result<JoinRequest> {
+        let request = JoinRequestAction { request_id };
+        self.request_with_body::<JoinRequestAction, JoinRequest>(reqwest::Method::POST, "/api/v1/conversations/join_requests/approve", &request).await
+    }
+
+    /// Reject a join request
+    pub async fn reject_join_request(&self, request_id: Uuid) -> Result<JoinRequest> {
+        let request = JoinRequestAction { request_id };
+        self.request_with_body::<JoinRequestAction, JoinRequest>(reqwest::Method::POST, "/api/v1/conversations/join_requests/reject", &request).await
+    }
+
+    /// Move the read and/or delivery position of the caller in a conversation forward
+    pub async fn update_receipt(&self, conversation_id: Uuid, read_up_to: Option<i64>, delivered_up_to: Option<i64>) -> Result<ConversationMember> {
+        let request = UpdateReceiptRequest { conversation_id, read_up_to, delivered_up_to };
+        self.request_with_body::<UpdateReceiptRequest, ConversationMember>(reqwest::Method::POST, "/api/v1/conversations/receipt", &request).await
+    }
+
+    /// Search the messages of the conversations of the authenticated user
+    pub async fn search_messages(&self, request: &SearchMessagesRequest) -> Result<SearchMessagesResponse> {
+        self.request::<SearchMessagesResponse, SearchMessagesRequest>(reqwest::Method::GET, "/api/v1/search", Some(request)).await
+    }
+
+    /// Leave a group conversation
+    pub async fn leave_conversation(&self, conversation_id: Uuid) -> Result<ConversationResponse> {
+        let request = ConversationRequest { conversation_id };
+        self.request_with_body::<ConversationRequest, ConversationResponse>(reqwest::Method::POST, "/api/v1/conversations/leave", &request).await
+    }
+
+    /// Rename a group conversation
+    pub async fn rename_conversation(&self, conversation_id: Uuid, name: &str) -> Result<Conversation> {
+        let request = RenameConversationRequest { conversation_id, name: name.to_string() };
+        self.request_with_body::<RenameConversationRequest, Conversation>(reqwest::Method::POST, "/api/v1/conversations/rename", &request).await
+    }
+
+    /// Update the name, avatar or topic of a group
+    pub async fn update_conversation_meta(&self, request: &UpdateConversationMetaRequest) -> Result<Conversation> {
+        self.request_with_body::<UpdateConversationMetaRequest, Conversation>(reqwest::Method::POST, "/api/v1/conversations/meta", request).await
+    }
+
+    /// Pin a message of a conversation, returns the pinned messages
+    pub async fn pin_message(&self, conversation_id: Uuid, message_id: i64) -> Result<Vec<PinnedMessage>> {
+        let request = ConversationMessageRequest { conversation_id, message_id };
+        let response = self.request_with_body::<ConversationMessageRequest, ListPinnedMessagesResponse>(reqwest::Method::POST, "/api/v1/conversations/pins/add", &request).await?;
+        Ok(response.pins)
+    }
+
+    /// Unpin a message of a conversation, returns the pinned messages
+    pub async fn unpin_message(&self, conversation_id: Uuid, message_id: i64) -> Result<Vec<PinnedMessage>> {
+        let request = ConversationMessageRequest { conversation_id, message_id };
+        let response = self.request_with_body::<ConversationMessageRequest, ListPinnedMessagesResponse>(reqwest::Method::POST, "/api/v1/conversations/pins/remove", &request).await?;
+        Ok(response.pins)
+    }
+
+    /// List the pinned messages of a conversation
+    pub async fn list_pinned_messages(&self, conversation_id: Uuid) -> Result<Vec<PinnedMessage>> {
+        let request = ConversationRequest { conversation_id };
+        let response = self.request::<ListPinnedMessagesResponse, ConversationRequest>(reqwest::Method::GET, "/api/v1/conversations/pins/list", Some(&request)).await?;
+        Ok(response.pins)
+    }
+
+    /// Star a message for the authenticated user
+    pub async fn star_message(&self, conversation_id: Uuid, message_id: i64) -> Result<StarMessageResponse> {
+        let request = ConversationMessageRequest { conversation_id, message_id };
+        self.request_with_body::<ConversationMessageRequest, StarMessageResponse>(reqwest::Method::POST, "/api/v1/messages/star", &request).await
+    }
+
+    /// Unstar a message for the authenticated user
+    pub async fn unstar_message(&self, conversation_id: Uuid, message_id: i64) -> Result<StarMessageResponse> {
+        let request = ConversationMessageRequest { conversation_id, message_id };
+        self.request_with_body::<ConversationMessageRequest, StarMessageResponse>(reqwest::Method::POST, "/api/v1/messages/unstar", &request).await
+    }
+
+    /// List the starred messages of the authenticated user
+    pub async fn list_starred_messages(&self, conversation_id: Option<Uuid>) -> Result<Vec<StarredMessage>> {
+        let request = ListStarredMessagesRequest { conversation_id };
+        let response = self.request::<ListStarredMessagesResponse, ListStarredMessagesRequest>(reqwest::Method::GET, "/api/v1/messages/starred", Some(&request)).await?;
+        Ok(response.messages)
+    }
+
+    /// Delete a conversation
+    pub async fn delete_conversation(&self, conversation_id: Uuid) -> Result<ConversationResponse> {
+        let request = ConversationRequest { conversation_id };
+        self.request_with_body::<ConversationRequest, ConversationResponse>(reqwest::Method::POST, "/api/v1/conversations/delete", &request).await
+    }
+
     /// Get all conversations for the authenticated user
     pub async fn get_conversations(&self) -> Result<Vec<Conversation>> {
         let response = self
@@ -249,31 +601,68 @@ impl CherryClient {
     pub async fn get_streams(&self, user_id: Uuid) -> Result<ListStreamResponse> {
         let request = ListStreamRequest { user_id };
 
-        let url = format!("{}/api/v1/streams/list", self.config.base_url);
-        let headers = self.create_headers()?;
+        self.request::<ListStreamResponse, ListStreamRequest>(reqwest::Method::GET, "/api/v1/streams/list", Some(&request))
+            .await
+    }
+
+    /// Renew the access token with the refresh token now
+    pub async fn refresh(&self) -> Result<AuthCredentials> {
+        let auth = self.auth().ok_or_else(|| anyhow::anyhow!("Not authenticated"))?;
+        self.refresh_auth(&auth.jwt_token).await?;
+        self.auth().ok_or_else(|| anyhow::anyhow!("Not authenticated"))
+    }
 
+    /// Revoke the access token and the refresh token of this client
+    pub async fn logout(&self) -> Result<LogoutResponse> {
+        let request = LogoutRequest {
+            refresh_token: self.refresh_token(),
+        };
         let response = self
-            .client
-            .get(&url)
-            .headers(headers)
-            .query(&request)
-            .send()
+            .request_with_body::<LogoutRequest, LogoutResponse>(reqwest::Method::POST, "/api/v1/auth/logout", &request)
+            .await?;
+        self.inner.refresh_token.write().unwrap().take();
+        Ok(response)
+    }
+
+    /// Start an OpenID Connect login
+    pub async fn oidc_authorize(&self) -> Result<OidcAuthorizeResponse> {
+        self.request::<OidcAuthorizeResponse, ()>(reqwest::Method::GET, "/api/v1/auth/oidc/authorize", None)
             .await
-            .context("Failed to get streams")?;
+    }
 
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(anyhow::anyhow!("HTTP {}: {}", status, error_text));
-        }
+    /// Finish an OpenID Connect login with the `code` and `state` of the redirect
+    pub async fn oidc_login(&self, code: &str, state: &str) -> Result<LoginResponse> {
+        let request = OidcCallbackRequest {
+            code: code.to_string(),
+            state: state.to_string(),
+        };
+        self.request::<LoginResponse, OidcCallbackRequest>(reqwest::Method::GET, "/api/v1/auth/oidc/callback", Some(&request))
+            .await
+    }
 
-        response
-            .json::<ListStreamResponse>()
+    /// Public keys the user tokens are signed with
+    pub async fn jwks(&self) -> Result<JwkSet> {
+        self.request::<JwkSet, ()>(reqwest::Method::GET, "/.well-known/jwks.json", None)
+            .await
+    }
+
+    /// Access tokens revoked since `since` (milliseconds), needs a service token
+    pub async fn list_revoked_tokens(&self, since: i64) -> Result<RevokedTokensResponse> {
+        let request = RevokedTokensRequest { since };
+        self.request::<RevokedTokensResponse, RevokedTokensRequest>(reqwest::Method::GET, "/api/v1/auth/revoked", Some(&request))
+            .await
+    }
+
+    /// Store the presence of a user, returns the contacts to notify. Needs a service token
+    pub async fn update_presence(&self, request: &UpdatePresenceRequest) -> Result<PresenceAudienceResponse> {
+        self.request_with_body::<UpdatePresenceRequest, PresenceAudienceResponse>(reqwest::Method::POST, "/api/v1/presence", request)
+            .await
+    }
+
+    /// Users the presence of a user is sent to, needs a service token
+    pub async fn presence_audience(&self, request: &PresenceAudienceRequest) -> Result<PresenceAudienceResponse> {
+        self.request::<PresenceAudienceResponse, PresenceAudienceRequest>(reqwest::Method::GET, "/api/v1/presence/audience", Some(request))
             .await
-            .context("Failed to deserialize streams response")
     }
 }
 
@@ -325,3 +714,16 @@ impl Default for CherryClientBuilder {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_exp() {
+        let payload = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .encode(r#"{"user_id":"00000000-0000-0000-0000-000000000000","exp":1700000000}"#);
+        assert_eq!(token_exp(&format!("header.{}.signature", payload)), Some(1700000000));
+        assert_eq!(token_exp("not a token"), None);
+    }
+}
@@ -1,14 +1,22 @@
 use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use crate::{client::{AuthCredentials, ClientConfig}, types::{
-    Message as CherryMessage, StreamAppendBatchRequest, StreamAppendBatchResponse, StreamAppendRequest, StreamAppendResponse, StreamReadRequest, StreamReadResponse, StreamRecord, StreamRecordMeta, MESSAGE_RECORD_META_SIZE
+    AclInvalidateRequest, AclInvalidateResponse, Message as CherryMessage, StreamAppendBatchRequest, StreamAppendBatchResponse, StreamAppendRequest, StreamAppendResponse, StreamCommitOffsetRequest, StreamCommittedOffsetResponse, StreamControlRequest, StreamControlResponse, StreamPollRequest, StreamRangeRequest, StreamReadEvent, StreamReadRequest, StreamReadResponse, StreamRecord, StreamRecordMeta, StreamUnreadQuery, StreamUnreadRequest, StreamUnreadResponse, MESSAGE_RECORD_META_SIZE, STREAM_READ_BINARY_PROTOCOL, STREAM_SSE_DATA_EVENT, STREAM_SSE_ERROR_EVENT
 }};
 use anyhow::Result;
-use async_tungstenite::tungstenite::{Message, client::IntoClientRequest};
+use async_tungstenite::{
+    WebSocketStream,
+    tokio::{ConnectStream, connect_async},
+    tungstenite::{Error as WsError, Message, client::IntoClientRequest, error::ProtocolError},
+};
 use futures_util::StreamExt;
 use streamstore::StreamId;
 use tokio::select;
 
+// first and max wait before reconnecting a broken SSE read, doubled on every failure
+const SSE_RETRY_DELAY: Duration = Duration::from_secs(1);
+const SSE_MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
 #[derive(Clone)]
 pub struct StreamClient {
     inner: Arc<StreamClientInner>,
@@ -51,6 +59,17 @@ impl StreamClient {
         }
     }
 
+    /// Same connection pool with other credentials, e.g. a renewed service token.
+    pub fn with_auth(&self, auth: impl Into<AuthCredentials>) -> Self {
+        Self {
+            inner: Arc::new(StreamClientInner {
+                config: self.config.clone(),
+                client: self.client.clone(),
+                auth: Some(auth.into()),
+            }),
+        }
+    }
+
     pub async fn append_stream(
         &self,
         stream_id: StreamId,
@@ -92,17 +111,93 @@ impl StreamClient {
         if let Some(auth) = &self.auth {
             req = req.header("Authorization", format!("Bearer {}", auth.jwt_token));
         }
-        let resp = req.json(&request).send().await?;
+        let resp = req.json(&request).send().await?.error_for_status()?;
         let response = resp.json::<StreamAppendBatchResponse>().await?;
         Ok(response)
     }
 
-    pub async fn open_stream(
+    // Drop the ACL decisions streamserver cached for the user and/or the stream.
+    pub async fn invalidate_acl(
         &self,
-    ) -> Result<(
-        tokio::sync::mpsc::Sender<StreamReadRequest>,
-        tokio::sync::mpsc::Receiver<StreamReadResponse>,
-    )> {
+        user_id: Option<uuid::Uuid>,
+        stream_id: Option<StreamId>,
+    ) -> Result<AclInvalidateResponse, anyhow::Error> {
+        let url = format!("{}/api/v1/acl/invalidate", self.config.base_url);
+        let request = AclInvalidateRequest { user_id, stream_id };
+
+        let mut req = self.client.post(url);
+        if let Some(auth) = &self.auth {
+            req = req.header("Authorization", format!("Bearer {}", auth.jwt_token));
+        }
+        let resp = req.json(&request).send().await?.error_for_status()?;
+        let response = resp.json::<AclInvalidateResponse>().await?;
+        Ok(response)
+    }
+
+    // Count the unread messages of a user after the given offsets, services only.
+    pub async fn count_unread(
+        &self,
+        user_id: uuid::Uuid,
+        streams: Vec<StreamUnreadQuery>,
+    ) -> Result<StreamUnreadResponse, anyhow::Error> {
+        let url = format!("{}/api/v1/stream/unread", self.config.base_url);
+        let request = StreamUnreadRequest { user_id, streams };
+
+        let mut req = self.client.post(url);
+        if let Some(auth) = &self.auth {
+            req = req.header("Authorization", format!("Bearer {}", auth.jwt_token));
+        }
+        let resp = req.json(&request).send().await?.error_for_status()?;
+        let response = resp.json::<StreamUnreadResponse>().await?;
+        Ok(response)
+    }
+
+    pub async fn commit_offset(
+        &self,
+        stream_id: StreamId,
+        consumer: Option<&str>,
+        offset: u64,
+    ) -> Result<StreamCommittedOffsetResponse, anyhow::Error> {
+        let url = format!("{}/api/v1/stream/offset/commit", self.config.base_url);
+        let request = StreamCommitOffsetRequest {
+            stream_id,
+            consumer: consumer.map(|consumer| consumer.to_string()),
+            offset,
+        };
+
+        let mut req = self.client.post(url);
+        if let Some(auth) = &self.auth {
+            req = req.header("Authorization", format!("Bearer {}", auth.jwt_token));
+        }
+        let resp = req.json(&request).send().await?.error_for_status()?;
+        let response = resp.json::<StreamCommittedOffsetResponse>().await?;
+        Ok(response)
+    }
+
+    pub async fn get_committed_offset(
+        &self,
+        stream_id: StreamId,
+        consumer: Option<&str>,
+    ) -> Result<Option<u64>, anyhow::Error> {
+        let url = format!("{}/api/v1/stream/offset", self.config.base_url);
+        let mut query = vec![("stream_id", stream_id.to_string())];
+        if let Some(consumer) = consumer {
+            query.push(("consumer", consumer.to_string()));
+        }
+
+        let mut req = self.client.get(url).query(&query);
+        if let Some(auth) = &self.auth {
+            req = req.header("Authorization", format!("Bearer {}", auth.jwt_token));
+        }
+        let resp = req.send().await?.error_for_status()?;
+        let response = resp.json::<StreamCommittedOffsetResponse>().await?;
+        Ok(response.offset)
+    }
+
+    async fn connect_read_stream(
+        &self,
+        binary: bool,
+    ) -> Result<WebSocketStream<ConnectStream>, WsError> {
         // replace http with ws
         let url = format!(
             "{}/api/v1/stream/read",
@@ -110,7 +205,7 @@ impl StreamClient {
         );
 
         log::info!("Attempting WebSocket connection to: {}", url);
-        let mut request = url.into_client_request().unwrap();
+        let mut request = url.into_client_request()?;
         if let Some(auth) = &self.auth {
             let auth_header = format!("Bearer {}", auth.jwt_token);
             log::info!("Setting Authorization header: {}", auth_header);
@@ -120,12 +215,38 @@ impl StreamClient {
         } else {
             log::warn!("No JWT token provided for WebSocket connection");
         }
+        if binary {
+            request.headers_mut().insert(
+                "Sec-WebSocket-Protocol",
+                STREAM_READ_BINARY_PROTOCOL.parse().unwrap(),
+            );
+        }
 
-        let (mut ws_stream, _) = async_tungstenite::tokio::connect_async(request).await?;
+        let (ws_stream, _) = connect_async(request).await?;
+        Ok(ws_stream)
+    }
+
+    // Open a read socket speaking the subscription control protocol. Data and
+    // control responses are delivered in order on the same channel.
+    pub async fn open_subscriptions(
+        &self,
+    ) -> Result<(
+        tokio::sync::mpsc::Sender<StreamControlRequest>,
+        tokio::sync::mpsc::Receiver<StreamReadEvent>,
+    )> {
+        // prefer binary frames, fall back to JSON for servers without the subprotocol
+        let mut ws_stream = match self.connect_read_stream(true).await {
+            Ok(ws_stream) => ws_stream,
+            Err(WsError::Protocol(ProtocolError::SecWebSocketSubProtocolError(e))) => {
+                log::warn!("Binary stream protocol rejected ({}), using JSON", e);
+                self.connect_read_stream(false).await?
+            }
+            Err(e) => return Err(e.into()),
+        };
         log::info!("WebSocket connection established successfully");
 
         let (tx, msg_rx) = tokio::sync::mpsc::channel(100);
-        let (req_tx, mut req_rx) = tokio::sync::mpsc::channel::<StreamReadRequest>(100);
+        let (req_tx, mut req_rx) = tokio::sync::mpsc::channel::<StreamControlRequest>(100);
         tokio::spawn(async move {
             log::info!("WebSocket message handler started");
             loop {
@@ -133,8 +254,17 @@ impl StreamClient {
                     Some(msg) = ws_stream.next() => {
                         match msg {
                             Ok(Message::Text(text)) => {
-                                let msg: StreamReadResponse = serde_json::from_str(&text).unwrap();
-                                if let Err(e) = tx.send(msg).await {
+                                let event = match serde_json::from_str::<StreamControlResponse>(&text) {
+                                    Ok(response) => StreamReadEvent::Control(response),
+                                    Err(_) => match serde_json::from_str::<StreamReadResponse>(&text) {
+                                        Ok(response) => StreamReadEvent::Data(response),
+                                        Err(e) => {
+                                            log::error!("decode stream read message error: {:?}", e);
+                                            continue;
+                                        }
+                                    },
+                                };
+                                if let Err(e) = tx.send(event).await {
                                     log::error!("send stream read response error: {:?}", e);
                                     break;
                                 }
@@ -149,7 +279,17 @@ impl StreamClient {
                                 log::info!("close: {:?}", close);
                             }
                             Ok(Message::Binary(binary)) => {
-                                log::info!("binary: {:?}", binary);
+                                let msg = match StreamReadResponse::decode_binary(&binary) {
+                                    Ok(msg) => msg,
+                                    Err(e) => {
+                                        log::error!("decode stream read frame error: {:?}", e);
+                                        break;
+                                    }
+                                };
+                                if let Err(e) = tx.send(StreamReadEvent::Data(msg)).await {
+                                    log::error!("send stream read response error: {:?}", e);
+                                    break;
+                                }
                             }
                             Ok(Message::Frame(frame)) => {
                                 log::info!("frame: {:?}", frame);
@@ -176,6 +316,287 @@ impl StreamClient {
 
         Ok((req_tx, msg_rx))
     }
+
+    // Long-poll read of the data at `offset`, empty if nothing arrived within `wait_ms`.
+    pub async fn poll_stream(
+        &self,
+        stream_id: StreamId,
+        offset: u64,
+        wait_ms: u64,
+    ) -> Result<StreamReadResponse, anyhow::Error> {
+        let url = format!("{}/api/v1/stream/poll", self.config.base_url);
+        let query = StreamPollRequest {
+            stream_id,
+            offset,
+            wait_ms: Some(wait_ms),
+        };
+
+        let mut req = self.client.get(url).query(&query);
+        if let Some(auth) = &self.auth {
+            req = req.header("Authorization", format!("Bearer {}", auth.jwt_token));
+        }
+        let resp = req.send().await?.error_for_status()?;
+        let response = resp.json::<StreamReadResponse>().await?;
+        Ok(response)
+    }
+
+    // Read historical data of a stream over plain HTTP, None past the end of the stream.
+    // With `records` the data is cut after the last whole record.
+    pub async fn read_stream_range(
+        &self,
+        stream_id: StreamId,
+        offset: u64,
+        limit: u64,
+        records: bool,
+    ) -> Result<Option<StreamReadResponse>, anyhow::Error> {
+        let url = format!("{}/api/v1/stream/{}/data", self.config.base_url, stream_id);
+        let query = StreamRangeRequest {
+            offset: Some(offset),
+            limit: Some(limit),
+            records,
+        };
+
+        let mut req = self.client.get(url).query(&query);
+        if let Some(auth) = &self.auth {
+            req = req.header("Authorization", format!("Bearer {}", auth.jwt_token));
+        }
+        let resp = req.send().await?;
+        if resp.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+            return Ok(None);
+        }
+        let resp = resp.error_for_status()?;
+        let data = resp.bytes().await?.to_vec();
+        Ok(Some(StreamReadResponse {
+            stream_id,
+            offset,
+            data,
+        }))
+    }
+
+    // Read one stream over Server-Sent Events until the receiver is dropped,
+    // reconnecting from the last received offset when the connection breaks.
+    // The server ends the read after an `error` event (acl denied, offset out
+    // of range), reconnecting would only fail again, so the read stops there.
+    async fn read_stream_sse(
+        &self,
+        stream_id: StreamId,
+        mut offset: u64,
+        tx: tokio::sync::mpsc::Sender<StreamReadResponse>,
+    ) {
+        let url = format!("{}/api/v1/stream/sse", self.config.base_url);
+        let mut retry_delay = SSE_RETRY_DELAY;
+        while !tx.is_closed() {
+            let mut req = self
+                .client
+                .get(&url)
+                .query(&StreamReadRequest { stream_id, offset })
+                .header("Accept", "text/event-stream");
+            if let Some(auth) = &self.auth {
+                req = req.header("Authorization", format!("Bearer {}", auth.jwt_token));
+            }
+            let resp = match req.send().await.and_then(|resp| resp.error_for_status()) {
+                Ok(resp) => resp,
+                Err(e) if e.status().is_some_and(|status| {
+                    status.is_client_error() && status != reqwest::StatusCode::TOO_MANY_REQUESTS
+                }) =>
+                {
+                    log::error!("sse read rejected, stream_id: {}, error: {:?}", stream_id, e);
+                    return;
+                }
+                Err(e) => {
+                    log::error!("sse connect error, stream_id: {}, error: {:?}", stream_id, e);
+                    tokio::time::sleep(retry_delay).await;
+                    retry_delay = (retry_delay * 2).min(SSE_MAX_RETRY_DELAY);
+                    continue;
+                }
+            };
+
+            let mut parser = SseParser::default();
+            let mut body = resp.bytes_stream();
+            while let Some(chunk) = body.next().await {
+                let chunk = match chunk {
+                    Ok(chunk) => chunk,
+                    Err(e) => {
+                        log::error!("sse read error, stream_id: {}, error: {:?}", stream_id, e);
+                        break;
+                    }
+                };
+                for (event, data) in parser.feed(&chunk) {
+                    if event == STREAM_SSE_ERROR_EVENT {
+                        log::error!("sse stream error, stream_id: {}, error: {}", stream_id, data);
+                        return;
+                    }
+                    if event != STREAM_SSE_DATA_EVENT {
+                        continue;
+                    }
+                    let response = match serde_json::from_str::<StreamReadResponse>(&data) {
+                        Ok(response) => response,
+                        Err(e) => {
+                            log::error!("decode sse event error: {:?}", e);
+                            continue;
+                        }
+                    };
+                    offset = response.offset + response.data.len() as u64;
+                    retry_delay = SSE_RETRY_DELAY;
+                    if tx.send(response).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            tokio::time::sleep(retry_delay).await;
+            retry_delay = (retry_delay * 2).min(SSE_MAX_RETRY_DELAY);
+        }
+    }
+
+    // Same channels as `open_stream`, each request opens one SSE connection.
+    pub async fn open_stream_sse(
+        &self,
+    ) -> Result<(
+        tokio::sync::mpsc::Sender<StreamReadRequest>,
+        tokio::sync::mpsc::Receiver<StreamReadResponse>,
+    )> {
+        let (tx, msg_rx) = tokio::sync::mpsc::channel(100);
+        let (req_tx, mut req_rx) = tokio::sync::mpsc::channel::<StreamReadRequest>(100);
+        let client = self.clone();
+        tokio::spawn(async move {
+            while let Some(request) = req_rx.recv().await {
+                let client = client.clone();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    client
+                        .read_stream_sse(request.stream_id, request.offset, tx)
+                        .await;
+                });
+            }
+        });
+
+        Ok((req_tx, msg_rx))
+    }
+
+    pub async fn open_stream(
+        &self,
+    ) -> Result<(
+        tokio::sync::mpsc::Sender<StreamReadRequest>,
+        tokio::sync::mpsc::Receiver<StreamReadResponse>,
+    )> {
+        let (req_tx, msg_rx, _presence) = self.open_stream_with_presence().await?;
+        Ok((req_tx, msg_rx))
+    }
+
+    // Like `open_stream`, also returning the presence channel of the read
+    // socket. None when the stream is read with Server-Sent Events.
+    pub async fn open_stream_with_presence(
+        &self,
+    ) -> Result<(
+        tokio::sync::mpsc::Sender<StreamReadRequest>,
+        tokio::sync::mpsc::Receiver<StreamReadResponse>,
+        Option<PresenceChannel>,
+    )> {
+        // proxies that break WebSockets still pass Server-Sent Events
+        let (control_tx, mut event_rx) = match self.open_subscriptions().await {
+            Ok(channels) => channels,
+            Err(e) => {
+                log::warn!("WebSocket stream read failed ({}), using Server-Sent Events", e);
+                let (req_tx, msg_rx) = self.open_stream_sse().await?;
+                return Ok((req_tx, msg_rx, None));
+            }
+        };
+
+        let (tx, msg_rx) = tokio::sync::mpsc::channel(100);
+        let (req_tx, mut req_rx) = tokio::sync::mpsc::channel::<StreamReadRequest>(100);
+        let (presence_tx, presence_rx) = tokio::sync::mpsc::channel(100);
+        let presence = PresenceChannel {
+            sender: control_tx.clone(),
+            receiver: presence_rx,
+        };
+        tokio::spawn(async move {
+            while let Some(request) = req_rx.recv().await {
+                if control_tx.send(request.into()).await.is_err() {
+                    break;
+                }
+            }
+        });
+        tokio::spawn(async move {
+            while let Some(event) = event_rx.recv().await {
+                match event {
+                    StreamReadEvent::Data(response) => {
+                        if tx.send(response).await.is_err() {
+                            break;
+                        }
+                    }
+                    StreamReadEvent::Control(StreamControlResponse::Error {
+                        stream_id,
+                        error,
+                        ..
+                    }) => {
+                        log::error!("stream read error, stream_id: {:?}, error: {}", stream_id, error);
+                    }
+                    StreamReadEvent::Control(
+                        response @ (StreamControlResponse::Presence { .. }
+                        | StreamControlResponse::Typing { .. }),
+                    ) => {
+                        // presence is best effort, dropped when nobody reads it
+                        let _ = presence_tx.try_send(response);
+                    }
+                    StreamReadEvent::Control(_) => {}
+                }
+            }
+        });
+
+        Ok((req_tx, msg_rx, Some(presence)))
+    }
+}
+
+// Publish the presence of the user with `sender`, and receive the presence and
+// typing of the other users on `receiver`.
+pub struct PresenceChannel {
+    pub sender: tokio::sync::mpsc::Sender<StreamControlRequest>,
+    pub receiver: tokio::sync::mpsc::Receiver<StreamControlResponse>,
+}
+
+// Incremental parser of a text/event-stream body, yields (event, data) pairs.
+#[derive(Default)]
+struct SseParser {
+    buf: Vec<u8>,
+    event: String,
+    data: Vec<String>,
+}
+
+impl SseParser {
+    fn feed(&mut self, chunk: &[u8]) -> Vec<(String, String)> {
+        self.buf.extend_from_slice(chunk);
+        let mut events = Vec::new();
+        while let Some(pos) = self.buf.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buf.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+            if line.is_empty() {
+                // a blank line dispatches the event
+                if !self.data.is_empty() {
+                    let event = std::mem::take(&mut self.event);
+                    let event = if event.is_empty() { "message".to_string() } else { event };
+                    events.push((event, self.data.join("\n")));
+                }
+                self.event.clear();
+                self.data.clear();
+                continue;
+            }
+            if line.starts_with(':') {
+                // comment, e.g. keep alive
+                continue;
+            }
+            let (field, value) = match line.split_once(':') {
+                Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+                None => (line, ""),
+            };
+            match field {
+                "event" => self.event = value.to_string(),
+                "data" => self.data.push(value.to_string()),
+                _ => {}
+            }
+        }
+        events
+    }
 }
 
 pub struct StreamRecordDecoder {
@@ -265,4 +686,28 @@ impl StreamRecordDecoderMachine {
         let decoder = self.machines.get_mut(&stream_id).unwrap();
         decoder.decode_all()
     }
+
+    // end offset of the records decoded so far, the position to commit
+    pub fn decoded_offset(&self, stream_id: StreamId) -> Option<u64> {
+        self.machines.get(&stream_id).map(|decoder| decoder.offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sse_parser() {
+        let mut parser = SseParser::default();
+        assert!(parser.feed(b": keep-alive\n\nevent: data\nid: 5\nda").is_empty());
+        let events = parser.feed(b"ta: {\"a\":1}\r\n\ndata: x\ndata: y\n\n");
+        assert_eq!(
+            events,
+            vec![
+                ("data".to_string(), "{\"a\":1}".to_string()),
+                ("message".to_string(), "x\ny".to_string()),
+            ]
+        );
+    }
 }
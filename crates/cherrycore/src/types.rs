@@ -27,6 +27,25 @@ pub struct LoginRequest {
     pub password: Option<String>,
 }
 
+// 注册成功后直接返回 LoginResponse
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RegisterRequest {
+    pub username: String,
+    pub email: String,
+    pub password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChangePasswordRequest {
+    pub old_password: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChangePasswordResponse {
+    pub success: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct UserInfo {
     pub user_id: Uuid,
@@ -43,6 +62,68 @@ pub struct UserInfo {
 pub struct LoginResponse {
     pub user_info: UserInfo,
     pub jwt_token: String,
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+}
+
+/// Exchange a refresh token for a new access token. The refresh token is
+/// rotated: the one sent is revoked and a new one returned.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RefreshTokenRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RefreshTokenResponse {
+    pub jwt_token: String,
+    pub refresh_token: String,
+}
+
+/// Revokes the access token of the request and, if given, the refresh token.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LogoutRequest {
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LogoutResponse {
+    pub success: bool,
+}
+
+/// Start of an OpenID Connect login, the client opens `authorization_url`
+/// and passes the `code` and `state` of the redirect to the callback.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OidcAuthorizeResponse {
+    pub authorization_url: String,
+    pub state: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OidcCallbackRequest {
+    pub code: String,
+    pub state: String,
+}
+
+/// Query of the revoked access tokens, `since` is a `revoked_at` in milliseconds.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RevokedTokensRequest {
+    #[serde(default)]
+    pub since: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RevokedToken {
+    pub jti: Uuid,
+    /// expiration of the token, seconds since the epoch
+    pub exp: u64,
+    /// milliseconds since the epoch
+    pub revoked_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RevokedTokensResponse {
+    pub tokens: Vec<RevokedToken>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -57,8 +138,20 @@ pub struct StreamAppendBatchRequest {
     pub batch: Vec<StreamAppendRequest>,
 }
 
+/// Outcome of one item of a batch append, in the order of the request.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StreamAppendBatchResult {
+    pub stream_id: StreamId,
+    #[serde(default)]
+    pub offset: Option<u64>,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
-pub struct StreamAppendBatchResponse {}
+pub struct StreamAppendBatchResponse {
+    pub results: Vec<StreamAppendBatchResult>,
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct StreamReadRequest {
@@ -66,6 +159,227 @@ pub struct StreamReadRequest {
     pub offset: u64,
 }
 
+/// Control messages a client sends on the stream read WebSocket, one JSON text
+/// frame each. A bare `StreamReadRequest` is still accepted as `Subscribe`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum StreamControlRequest {
+    /// Start reading the stream from `offset`. Without `credit` the server
+    /// sends data frames as fast as it reads them.
+    Subscribe {
+        stream_id: StreamId,
+        offset: u64,
+        #[serde(default)]
+        credit: Option<u32>,
+    },
+    Unsubscribe {
+        stream_id: StreamId,
+    },
+    /// Move the read position of an existing subscription.
+    Seek {
+        stream_id: StreamId,
+        offset: u64,
+    },
+    Pause {
+        stream_id: StreamId,
+    },
+    Resume {
+        stream_id: StreamId,
+    },
+    /// Allow the server to send `credit` more data frames for the stream.
+    Credit {
+        stream_id: StreamId,
+        credit: u32,
+    },
+    /// Start reading the stream from the offset last committed by `consumer`,
+    /// or from the beginning of the stream when nothing was committed yet.
+    SubscribeCommitted {
+        stream_id: StreamId,
+        #[serde(default)]
+        consumer: Option<String>,
+        #[serde(default)]
+        credit: Option<u32>,
+    },
+    /// Commit the read position of `consumer` for the stream.
+    Commit {
+        stream_id: StreamId,
+        #[serde(default)]
+        consumer: Option<String>,
+        offset: u64,
+    },
+    /// Publish the presence of the user to their contacts. It is ephemeral,
+    /// never written to a stream.
+    Presence {
+        status: PresenceStatus,
+    },
+    /// Tell the other members of the conversation the user is (no longer) typing.
+    Typing {
+        conversation_id: Uuid,
+        #[serde(default = "default_typing")]
+        typing: bool,
+    },
+}
+
+fn default_typing() -> bool {
+    true
+}
+
+impl StreamControlRequest {
+    /// The stream of the request, None for presence and typing.
+    pub fn stream_id(&self) -> Option<StreamId> {
+        match self {
+            Self::Subscribe { stream_id, .. }
+            | Self::Unsubscribe { stream_id }
+            | Self::Seek { stream_id, .. }
+            | Self::Pause { stream_id }
+            | Self::Resume { stream_id }
+            | Self::Credit { stream_id, .. }
+            | Self::SubscribeCommitted { stream_id, .. }
+            | Self::Commit { stream_id, .. } => Some(*stream_id),
+            Self::Presence { .. } | Self::Typing { .. } => None,
+        }
+    }
+
+    pub fn op(&self) -> &'static str {
+        match self {
+            Self::Subscribe { .. } => "subscribe",
+            Self::Unsubscribe { .. } => "unsubscribe",
+            Self::Seek { .. } => "seek",
+            Self::Pause { .. } => "pause",
+            Self::Resume { .. } => "resume",
+            Self::Credit { .. } => "credit",
+            Self::SubscribeCommitted { .. } => "subscribe_committed",
+            Self::Commit { .. } => "commit",
+            Self::Presence { .. } => "presence",
+            Self::Typing { .. } => "typing",
+        }
+    }
+}
+
+impl From<StreamReadRequest> for StreamControlRequest {
+    fn from(request: StreamReadRequest) -> Self {
+        Self::Subscribe {
+            stream_id: request.stream_id,
+            offset: request.offset,
+            credit: None,
+        }
+    }
+}
+
+/// Presence of a user, `offline` once the last read WebSocket of the user is closed.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum PresenceStatus {
+    Online,
+    Away,
+    Offline,
+}
+
+impl PresenceStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PresenceStatus::Online => "online",
+            PresenceStatus::Away => "away",
+            PresenceStatus::Offline => "offline",
+        }
+    }
+}
+
+/// Acknowledgements and errors the server sends for control messages, and the
+/// presence of other users, as JSON text frames.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamControlResponse {
+    Ack {
+        stream_id: StreamId,
+        op: String,
+        /// read position of the subscription after the operation
+        offset: u64,
+    },
+    Error {
+        stream_id: Option<StreamId>,
+        op: Option<String>,
+        error: String,
+    },
+    /// A contact of the receiver changed their presence.
+    Presence {
+        user_id: Uuid,
+        status: PresenceStatus,
+    },
+    /// Another member of a conversation of the receiver started or stopped typing.
+    Typing {
+        user_id: Uuid,
+        conversation_id: Uuid,
+        typing: bool,
+    },
+}
+
+/// Query of the long-poll read: returns the data at `offset`, waiting up to
+/// `wait_ms` for new appends when the stream has nothing there yet.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StreamPollRequest {
+    pub stream_id: StreamId,
+    pub offset: u64,
+    #[serde(default)]
+    pub wait_ms: Option<u64>,
+}
+
+/// Query of the HTTP range read `GET /api/v1/stream/{stream_id}/data`. A
+/// `Range: bytes=` header takes precedence over `offset` and `limit`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct StreamRangeRequest {
+    #[serde(default)]
+    pub offset: Option<u64>,
+    #[serde(default)]
+    pub limit: Option<u64>,
+    /// cut the data after the last whole `StreamRecord`
+    #[serde(default)]
+    pub records: bool,
+}
+
+/// Unread counts stop at this value, clients show it as `999+`.
+pub const MAX_UNREAD_COUNT: u64 = 999;
+
+/// Count the unread messages of `user_id` in each stream, services only.
+/// Messages after `after` are counted, all of them when it is None, except
+/// the user's own messages, reactions, edits and deletes.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StreamUnreadRequest {
+    pub user_id: Uuid,
+    pub streams: Vec<StreamUnreadQuery>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StreamUnreadQuery {
+    pub stream_id: StreamId,
+    #[serde(default)]
+    pub after: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StreamUnreadResponse {
+    pub counts: Vec<StreamUnreadCount>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StreamUnreadCount {
+    pub stream_id: StreamId,
+    pub count: u64,
+}
+
+/// Server-Sent Event names of the SSE read. `data` events carry a JSON
+/// `StreamReadResponse` and their id is the offset to resume from.
+pub const STREAM_SSE_DATA_EVENT: &str = "data";
+/// `error` events carry a JSON `StreamControlResponse::Error`.
+pub const STREAM_SSE_ERROR_EVENT: &str = "error";
+
+/// Everything received on the stream read WebSocket.
+#[derive(Debug)]
+pub enum StreamReadEvent {
+    Data(StreamReadResponse),
+    Control(StreamControlResponse),
+}
+
 #[serde_as]
 #[derive(Serialize, Deserialize)]
 pub struct StreamReadResponse {
@@ -75,6 +389,51 @@ pub struct StreamReadResponse {
     pub data: Vec<u8>,
 }
 
+/// WebSocket subprotocol for `StreamReadResponse` sent as binary frames.
+pub const STREAM_READ_BINARY_PROTOCOL: &str = "cherry.stream.binary.v1";
+/// WebSocket subprotocol for `StreamReadResponse` sent as JSON text frames, the default.
+pub const STREAM_READ_JSON_PROTOCOL: &str = "cherry.stream.json.v1";
+/// stream_id(8) + offset(8) + length(4)
+pub const STREAM_READ_FRAME_HEADER_SIZE: usize = 20;
+
+impl StreamReadResponse {
+    /// Encode as a binary frame: stream_id, offset, payload length, payload (little endian).
+    pub fn encode_binary(&self) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(STREAM_READ_FRAME_HEADER_SIZE + self.data.len());
+        frame.extend_from_slice(&self.stream_id.to_le_bytes());
+        frame.extend_from_slice(&self.offset.to_le_bytes());
+        frame.extend_from_slice(&(self.data.len() as u32).to_le_bytes());
+        frame.extend_from_slice(&self.data);
+        frame
+    }
+
+    pub fn decode_binary(frame: &[u8]) -> Result<Self, anyhow::Error> {
+        if frame.len() < STREAM_READ_FRAME_HEADER_SIZE {
+            return Err(anyhow::anyhow!(
+                "Invalid frame length, expected at least: {}, got: {}",
+                STREAM_READ_FRAME_HEADER_SIZE,
+                frame.len()
+            ));
+        }
+        let stream_id = StreamId::from_le_bytes(frame[0..8].try_into()?);
+        let offset = u64::from_le_bytes(frame[8..16].try_into()?);
+        let length = u32::from_le_bytes(frame[16..20].try_into()?) as usize;
+        let data = &frame[STREAM_READ_FRAME_HEADER_SIZE..];
+        if data.len() != length {
+            return Err(anyhow::anyhow!(
+                "Invalid frame payload length, expected: {}, got: {}",
+                length,
+                data.len()
+            ));
+        }
+        Ok(StreamReadResponse {
+            stream_id,
+            offset,
+            data: data.to_vec(),
+        })
+    }
+}
+
 impl Debug for StreamReadResponse {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -93,16 +452,105 @@ pub struct StreamAppendResponse {
     pub offset: u64, // 偏移量
 }
 
+/// Consumer used when a client does not name one, e.g. a single device per user.
+pub const DEFAULT_STREAM_CONSUMER: &str = "default";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StreamCommitOffsetRequest {
+    pub stream_id: StreamId,
+    // device or consumer group of the user, `DEFAULT_STREAM_CONSUMER` if empty
+    #[serde(default)]
+    pub consumer: Option<String>,
+    pub offset: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StreamCommittedOffsetRequest {
+    pub stream_id: StreamId,
+    #[serde(default)]
+    pub consumer: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StreamCommittedOffsetResponse {
+    pub stream_id: StreamId,
+    pub consumer: String,
+    // None if the consumer never committed an offset for the stream
+    pub offset: Option<u64>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CheckAclRequest {
     pub user_id: Uuid,
     pub stream_id: Option<StreamId>,
     pub conversation_id: Option<Uuid>,
+    #[serde(default)]
+    pub action: AclAction,
+}
+
+/// What the user wants to do with the stream. Appends are also checked
+/// against the group permissions and the mute of the member.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AclAction {
+    #[default]
+    Read,
+    Append,
+}
+
+impl AclAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AclAction::Read => "read",
+            AclAction::Append => "append",
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CheckAclResponse {
     pub allowed: bool,
+    /// Conversation of the stream, set for allowed appends so streamserver
+    /// can check the `conversation_id` of the appended messages.
+    #[serde(default)]
+    pub conversation_id: Option<Uuid>,
+}
+
+/// Sent by cherryserver when the members of a stream change, so streamserver
+/// drops its cached ACL decisions. Both None drops everything.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AclInvalidateRequest {
+    #[serde(default)]
+    pub user_id: Option<Uuid>,
+    #[serde(default)]
+    pub stream_id: Option<StreamId>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AclInvalidateResponse {
+    pub invalidated: usize,
+}
+
+/// Sent by streamserver when a user connects, disconnects or publishes a new
+/// presence. cherryserver stores the status and `last_active` of the user.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdatePresenceRequest {
+    pub user_id: Uuid,
+    pub status: PresenceStatus,
+}
+
+/// Query of the users the presence of `user_id` is sent to: the other members
+/// of the conversation when set, the contacts of the user otherwise.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PresenceAudienceRequest {
+    pub user_id: Uuid,
+    #[serde(default)]
+    pub conversation_id: Option<Uuid>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PresenceAudienceResponse {
+    pub user_ids: Vec<Uuid>,
 }
 
 pub enum ResponseError {
@@ -114,7 +562,15 @@ pub enum ResponseError {
     DataInvalid,
     AccessDenied,
     StreamNotFound,
+    ConversationNotFound,
+    EmailAlreadyExists,
+    UsernameAlreadyExists,
+    UserNotFound,
+    InvalidRelation,
+    InviteInvalid,
+    JoinRequestNotFound,
     Forbidden,
+    TooManyRequests,
 }
 
 impl IntoResponse for ResponseError {
@@ -132,7 +588,29 @@ impl IntoResponse for ResponseError {
             Self::DataInvalid => (StatusCode::BAD_REQUEST, "data is invalid").into_response(),
             Self::AccessDenied => (StatusCode::FORBIDDEN, "access denied").into_response(),
             Self::StreamNotFound => (StatusCode::NOT_FOUND, "stream not found").into_response(),
+            Self::ConversationNotFound => {
+                (StatusCode::NOT_FOUND, "conversation not found").into_response()
+            }
+            Self::EmailAlreadyExists => {
+                (StatusCode::CONFLICT, "email already exists").into_response()
+            }
+            Self::UsernameAlreadyExists => {
+                (StatusCode::CONFLICT, "username already exists").into_response()
+            }
+            Self::UserNotFound => (StatusCode::NOT_FOUND, "user not found").into_response(),
+            Self::InvalidRelation => {
+                (StatusCode::CONFLICT, "not allowed by the contact relation").into_response()
+            }
+            Self::InviteInvalid => {
+                (StatusCode::GONE, "invite is revoked, expired or used up").into_response()
+            }
+            Self::JoinRequestNotFound => {
+                (StatusCode::NOT_FOUND, "join request not found").into_response()
+            }
             Self::Forbidden => (StatusCode::FORBIDDEN, "forbidden").into_response(),
+            Self::TooManyRequests => {
+                (StatusCode::TOO_MANY_REQUESTS, "too many requests").into_response()
+            }
         }
     }
 }
@@ -154,7 +632,15 @@ impl Display for ResponseError {
             Self::AccessDenied => write!(f, "Access denied"),
             Self::ClientConnectionError(error) => write!(f, "Client connection error: {}", error),
             Self::StreamNotFound => write!(f, "Stream not found"),
+            Self::ConversationNotFound => write!(f, "Conversation not found"),
+            Self::EmailAlreadyExists => write!(f, "Email already exists"),
+            Self::UsernameAlreadyExists => write!(f, "Username already exists"),
+            Self::UserNotFound => write!(f, "User not found"),
+            Self::InvalidRelation => write!(f, "Not allowed by the contact relation"),
+            Self::InviteInvalid => write!(f, "Invite is revoked, expired or used up"),
+            Self::JoinRequestNotFound => write!(f, "Join request not found"),
             Self::Forbidden => write!(f, "Forbidden"),
+            Self::TooManyRequests => write!(f, "Too many requests"),
         }
     }
 }
@@ -188,8 +674,80 @@ pub struct Conversation {
     pub members: Value,
     pub meta: Value,
     pub stream_id: StreamId,
+    #[serde(default)]
+    pub permissions: ConversationPermissions,
     pub created_at: DateTime<chrono::Utc>,
     pub updated_at: DateTime<chrono::Utc>,
+    /// Read position of the caller, set by `list_conversations`.
+    #[serde(default)]
+    pub read_up_to: Option<i64>,
+    /// Messages of the other members after `read_up_to`, at most
+    /// `MAX_UNREAD_COUNT`. Set by `list_conversations`, None when unknown.
+    #[serde(default)]
+    pub unread_count: Option<u64>,
+}
+
+/// Who may post, invite, edit the group info and pin messages in a group,
+/// `all` members or only `admins` (the owner and the admins).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConversationPermissions {
+    #[serde(default = "default_permission")]
+    pub post: String,
+    #[serde(default = "default_permission")]
+    pub invite: String,
+    #[serde(default = "default_permission")]
+    pub edit_info: String,
+    #[serde(default = "default_permission")]
+    pub pin: String,
+}
+
+fn default_permission() -> String {
+    "all".to_string()
+}
+
+impl Default for ConversationPermissions {
+    fn default() -> Self {
+        Self {
+            post: default_permission(),
+            invite: default_permission(),
+            edit_info: default_permission(),
+            pin: default_permission(),
+        }
+    }
+}
+
+/// A member of a conversation, `role` is `owner`, `admin` or `member`.
+/// `read_up_to` and `delivered_up_to` are the offsets of the last message the
+/// member read and received.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ConversationMember {
+    pub conversation_id: Uuid,
+    pub user_id: Uuid,
+    pub role: String,
+    pub joined_at: DateTime<chrono::Utc>,
+    pub muted_until: Option<DateTime<chrono::Utc>>,
+    #[serde(default)]
+    pub read_up_to: Option<i64>,
+    #[serde(default)]
+    pub delivered_up_to: Option<i64>,
+    #[serde(default)]
+    pub read_at: Option<DateTime<chrono::Utc>>,
+}
+
+/// Move the read and/or delivery position of the caller forward, positions
+/// never move back.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UpdateReceiptRequest {
+    pub conversation_id: Uuid,
+    #[serde(default)]
+    pub read_up_to: Option<i64>,
+    #[serde(default)]
+    pub delivered_up_to: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListConversationMembersResponse {
+    pub members: Vec<ConversationMember>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -233,6 +791,256 @@ pub struct CreateConversationResponse {
     pub is_new: bool, // 是否是新创建的会话（用于1对1重复检测）
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConversationMembersRequest {
+    pub conversation_id: Uuid,
+    pub members: Vec<Uuid>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RenameConversationRequest {
+    pub conversation_id: Uuid,
+    pub name: String,
+}
+
+/// Partial update of the group info kept in the `meta` of a conversation,
+/// absent fields are left unchanged. `avatar_url: null` and `topic: null`
+/// remove them.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UpdateConversationMetaRequest {
+    pub conversation_id: Uuid,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "::serde_with::rust::double_option"
+    )]
+    pub avatar_url: Option<Option<String>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "::serde_with::rust::double_option"
+    )]
+    pub topic: Option<Option<String>>,
+}
+
+/// Pinned messages of a conversation are limited to this many.
+pub const MAX_PINNED_MESSAGES: i64 = 50;
+/// Starred messages of a user are limited to this many.
+pub const MAX_STARRED_MESSAGES: i64 = 1000;
+
+/// A message of a conversation, `message_id` is its stream offset.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConversationMessageRequest {
+    pub conversation_id: Uuid,
+    pub message_id: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct PinnedMessage {
+    pub conversation_id: Uuid,
+    pub message_id: i64,
+    /// None once the user is deleted
+    pub pinned_by: Option<Uuid>,
+    pub pinned_at: DateTime<chrono::Utc>,
+}
+
+/// The pinned messages of a conversation, the latest pinned first.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ListPinnedMessagesResponse {
+    pub pins: Vec<PinnedMessage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct StarredMessage {
+    pub conversation_id: Uuid,
+    pub message_id: i64,
+    pub starred_at: DateTime<chrono::Utc>,
+}
+
+/// Starred messages of the caller, of all conversations without `conversation_id`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ListStarredMessagesRequest {
+    #[serde(default)]
+    pub conversation_id: Option<Uuid>,
+}
+
+/// The latest starred first.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ListStarredMessagesResponse {
+    pub messages: Vec<StarredMessage>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StarMessageResponse {
+    pub starred: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SetMemberRoleRequest {
+    pub conversation_id: Uuid,
+    pub member_id: Uuid,
+    // admin or member
+    pub role: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MuteMemberRequest {
+    pub conversation_id: Uuid,
+    pub member_id: Uuid,
+    // None unmutes the member
+    #[serde(default)]
+    pub muted_until: Option<DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransferOwnershipRequest {
+    pub conversation_id: Uuid,
+    pub member_id: Uuid,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UpdatePermissionsRequest {
+    pub conversation_id: Uuid,
+    #[serde(default)]
+    pub post: Option<String>,
+    #[serde(default)]
+    pub invite: Option<String>,
+    #[serde(default)]
+    pub edit_info: Option<String>,
+    #[serde(default)]
+    pub pin: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateInviteRequest {
+    pub conversation_id: Uuid,
+    // None never expires
+    #[serde(default)]
+    pub expires_in_seconds: Option<u64>,
+    // None allows any number of uses
+    #[serde(default)]
+    pub max_uses: Option<i32>,
+    #[serde(default)]
+    pub requires_approval: bool,
+}
+
+/// An invite code of a group, anyone with the code can join or ask to join.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ConversationInvite {
+    pub invite_id: Uuid,
+    pub conversation_id: Uuid,
+    pub code: String,
+    pub created_by: Uuid,
+    pub expires_at: Option<DateTime<chrono::Utc>>,
+    pub max_uses: Option<i32>,
+    pub use_count: i32,
+    pub requires_approval: bool,
+    pub revoked_at: Option<DateTime<chrono::Utc>>,
+    pub created_at: DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListInvitesResponse {
+    pub invites: Vec<ConversationInvite>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InviteRequest {
+    pub invite_id: Uuid,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JoinConversationRequest {
+    pub code: String,
+    // shown to the admins when the invite requires approval
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JoinConversationResponse {
+    pub conversation_id: Uuid,
+    // joined, or pending when the request waits for an admin
+    pub status: String,
+    pub request_id: Option<Uuid>,
+}
+
+/// A request to join a group through an invite that requires approval,
+/// `status` is `pending`, `approved` or `rejected`.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct JoinRequest {
+    pub request_id: Uuid,
+    pub conversation_id: Uuid,
+    pub user_id: Uuid,
+    pub invite_id: Option<Uuid>,
+    pub message: Option<String>,
+    pub status: String,
+    pub reviewed_by: Option<Uuid>,
+    pub created_at: DateTime<chrono::Utc>,
+    pub updated_at: DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListJoinRequestsResponse {
+    pub requests: Vec<JoinRequest>,
+}
+
+// approve and reject
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JoinRequestAction {
+    pub request_id: Uuid,
+}
+
+// leave, delete and list members
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConversationRequest {
+    pub conversation_id: Uuid,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConversationResponse {
+    pub conversation_id: Uuid,
+    pub success: bool,
+}
+
+/// Search limits of `GET /api/v1/search`.
+pub const DEFAULT_SEARCH_LIMIT: i64 = 20;
+pub const MAX_SEARCH_LIMIT: i64 = 100;
+
+/// Full text search over the messages of the caller's conversations, newest
+/// first. `before` pages back from the timestamp of the last result.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SearchMessagesRequest {
+    pub q: String,
+    #[serde(default)]
+    pub conversation_id: Option<Uuid>,
+    #[serde(default)]
+    pub before: Option<DateTime<chrono::Utc>>,
+    #[serde(default)]
+    pub limit: Option<i64>,
+}
+
+/// A message found by the search, `message_id` is its stream offset and
+/// `content` the indexed text of the message.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, sqlx::FromRow)]
+pub struct SearchResult {
+    pub conversation_id: Uuid,
+    pub message_id: i64,
+    pub user_id: Uuid,
+    #[serde(rename = "type")]
+    #[sqlx(rename = "message_type")]
+    pub type_: String,
+    pub content: String,
+    pub timestamp: DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchMessagesResponse {
+    pub results: Vec<SearchResult>,
+}
+
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
 pub struct User {
     pub id: i32,
@@ -256,6 +1064,64 @@ pub struct Contact {
     pub tags: Value,
     pub is_favorite: bool,
     pub mute_settings: Value,
+    /// `contact_details.custom_fields` of the owner.
+    #[serde(default)]
+    #[sqlx(default)]
+    pub custom_fields: Value,
+}
+
+/// Filters of the contact list, all optional.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ListContactsRequest {
+    #[serde(default)]
+    pub tag: Option<String>,
+    #[serde(default)]
+    pub favorite: Option<bool>,
+    #[serde(default)]
+    pub relation_type: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MuteSettings {
+    pub muted: bool,
+    /// Muted until then, forever if None.
+    #[serde(default)]
+    pub expire_at: Option<DateTime<chrono::Utc>>,
+}
+
+/// Partial update of a contact, absent fields are left unchanged.
+/// `remark_name: null` clears the remark, and `custom_fields` is merged
+/// into the stored fields, a null value removes the key.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UpdateContactRequest {
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "::serde_with::rust::double_option"
+    )]
+    pub remark_name: Option<Option<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub is_favorite: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mute_settings: Option<MuteSettings>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub custom_fields: Option<serde_json::Map<String, Value>>,
+}
+
+/// The other user of a friend request, block or unblock.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContactTargetRequest {
+    pub target_id: Uuid,
+}
+
+/// Relation of the caller to `target_id` after the change, None if the
+/// contact is gone.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContactRelationResponse {
+    pub target_id: Uuid,
+    pub relation_type: Option<String>,
 }
 
 pub enum StreamType {
@@ -319,6 +1185,104 @@ pub enum StreamEvent {
         conversation_id: Uuid,
         member_id: Uuid,
     },
+    ConversationRenamed {
+        conversation_id: Uuid,
+        name: String,
+    },
+    ConversationDeleted {
+        conversation_id: Uuid,
+    },
+    ConversationMemberRoleChanged {
+        conversation_id: Uuid,
+        member_id: Uuid,
+        role: String,
+    },
+    ConversationMemberMuted {
+        conversation_id: Uuid,
+        member_id: Uuid,
+        muted_until: Option<DateTime<chrono::Utc>>,
+    },
+    ConversationPermissionsChanged {
+        conversation_id: Uuid,
+        permissions: ConversationPermissions,
+    },
+    /// Sent to the owner and the admins.
+    ConversationJoinRequested {
+        conversation_id: Uuid,
+        request_id: Uuid,
+        user_id: Uuid,
+    },
+    /// Sent to the requester and the admins once the request is reviewed.
+    ConversationJoinRequestReviewed {
+        conversation_id: Uuid,
+        request_id: Uuid,
+        user_id: Uuid,
+        status: String,
+    },
+    /// The read or delivery position of a member moved forward.
+    ReadUpTo {
+        conversation_id: Uuid,
+        user_id: Uuid,
+        read_up_to: Option<i64>,
+        delivered_up_to: Option<i64>,
+    },
+    /// The contact of the receiver for `target_id` changed, `relation_type`
+    /// is the current relation, None when the contact was removed.
+    ContactUpdated {
+        target_id: Uuid,
+        relation_type: Option<String>,
+    },
+    /// The group info changed, `meta` is the whole new meta. Recorded in the
+    /// conversation stream.
+    ConversationMetaUpdated {
+        conversation_id: Uuid,
+        user_id: Uuid,
+        meta: Value,
+    },
+    /// Recorded in the conversation stream, `user_id` pinned the message.
+    MessagePinned {
+        conversation_id: Uuid,
+        message_id: i64,
+        user_id: Uuid,
+    },
+    /// Recorded in the conversation stream, `user_id` unpinned the message.
+    MessageUnpinned {
+        conversation_id: Uuid,
+        message_id: i64,
+        user_id: Uuid,
+    },
+    /// Sent to the receiver only, who (un)starred the message on another device.
+    MessageStarred {
+        conversation_id: Uuid,
+        message_id: i64,
+        starred: bool,
+    },
+}
+
+impl StreamEvent {
+    /// None for the events that are not about a conversation.
+    pub fn conversation_id(&self) -> Option<Uuid> {
+        match self {
+            StreamEvent::ConversationCreated { conversation_id }
+            | StreamEvent::ConversationMemberAdded { conversation_id, .. }
+            | StreamEvent::ConversationMemberRemoved { conversation_id, .. }
+            | StreamEvent::ConversationRenamed { conversation_id, .. }
+            | StreamEvent::ConversationDeleted { conversation_id }
+            | StreamEvent::ConversationMemberRoleChanged { conversation_id, .. }
+            | StreamEvent::ConversationMemberMuted { conversation_id, .. }
+            | StreamEvent::ConversationPermissionsChanged { conversation_id, .. }
+            | StreamEvent::ConversationJoinRequested { conversation_id, .. }
+            | StreamEvent::ConversationJoinRequestReviewed { conversation_id, .. }
+            | StreamEvent::ReadUpTo { conversation_id, .. }
+            | StreamEvent::ConversationMetaUpdated { conversation_id, .. }
+            | StreamEvent::MessagePinned { conversation_id, .. }
+            | StreamEvent::MessageUnpinned { conversation_id, .. }
+            | StreamEvent::MessageStarred { conversation_id, .. } => {
+                Some(*conversation_id)
+            }
+            StreamEvent::ContactUpdated { .. } => None,
+        }
+    }
 }
 
 impl StreamEvent {
@@ -362,7 +1326,27 @@ pub struct ReactionContent {
 
 }
 
+/// Type of a message replacing the content of the message of `reply_to`.
+/// Its content is the new content.
+pub const MESSAGE_TYPE_EDIT: &str = "edit";
+/// Type of a message recalling the message of `reply_to`, which becomes a
+/// tombstone. Only possible within the recall window of the streamserver.
+pub const MESSAGE_TYPE_DELETE: &str = "delete";
+
+/// A previous content of an edited message, kept by the clients.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageEdit {
+    pub content: Value,
+    pub timestamp: DateTime<chrono::Utc>,
+}
+
 impl Message {
+    /// `edit` and `delete` messages amend the message of `reply_to`, only
+    /// its author may send them.
+    pub fn is_amendment(&self) -> bool {
+        self.type_ == MESSAGE_TYPE_EDIT || self.type_ == MESSAGE_TYPE_DELETE
+    }
+
     pub fn encode(&self) -> Result<Vec<u8>, anyhow::Error> {
         let content = serde_json::to_string(&self)
             .map_err(|e| anyhow::anyhow!("Failed to serialize message: {}", e))?;
@@ -480,6 +1464,20 @@ pub struct StreamRecord {
 }
 
 impl StreamRecord {
+    /// Length of the longest prefix of `data` made of whole records, `data`
+    /// starting at a record boundary.
+    pub fn complete_len(data: &[u8]) -> usize {
+        let mut len = 0;
+        while let Ok(meta) = StreamRecordMeta::decode(&data[len..]) {
+            let record_size = meta.content_size as usize + MESSAGE_RECORD_META_SIZE * 2;
+            if data.len() - len < record_size {
+                break;
+            }
+            len += record_size;
+        }
+        len
+    }
+
     pub fn encode(&self) -> Result<Vec<u8>, anyhow::Error> {
         use std::io::Write;
 
@@ -596,3 +1594,201 @@ pub struct ImageUploadCompleteResponse {
     pub thumbnail_url: String,
     pub message_id: Option<i64>, // 如果直接发送消息，返回消息ID
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_read_response_binary_roundtrip() {
+        let response = StreamReadResponse {
+            stream_id: 42,
+            offset: 1 << 40,
+            data: b"hello".to_vec(),
+        };
+        let frame = response.encode_binary();
+        assert_eq!(frame.len(), STREAM_READ_FRAME_HEADER_SIZE + 5);
+
+        let decoded = StreamReadResponse::decode_binary(&frame).unwrap();
+        assert_eq!(decoded.stream_id, 42);
+        assert_eq!(decoded.offset, 1 << 40);
+        assert_eq!(decoded.data, b"hello");
+    }
+
+    #[test]
+    fn test_stream_control_request_json() {
+        let request: StreamControlRequest =
+            serde_json::from_str(r#"{"op":"subscribe","stream_id":7,"offset":3}"#).unwrap();
+        assert_eq!(
+            request,
+            StreamControlRequest::Subscribe {
+                stream_id: 7,
+                offset: 3,
+                credit: None
+            }
+        );
+
+        let request = StreamControlRequest::Credit {
+            stream_id: 7,
+            credit: 16,
+        };
+        let json = serde_json::to_string(&request).unwrap();
+        assert_eq!(json, r#"{"op":"credit","stream_id":7,"credit":16}"#);
+        assert_eq!(request.op(), "credit");
+        assert_eq!(request.stream_id(), Some(7));
+
+        let request: StreamControlRequest =
+            serde_json::from_str(r#"{"op":"subscribe_committed","stream_id":7,"consumer":"desktop"}"#).unwrap();
+        assert_eq!(
+            request,
+            StreamControlRequest::SubscribeCommitted {
+                stream_id: 7,
+                consumer: Some("desktop".to_string()),
+                credit: None
+            }
+        );
+        assert_eq!(request.op(), "subscribe_committed");
+
+        // a legacy read request is not a control message
+        assert!(serde_json::from_str::<StreamControlRequest>(r#"{"stream_id":7,"offset":0}"#).is_err());
+
+        let request: StreamControlRequest =
+            serde_json::from_str(r#"{"op":"presence","status":"away"}"#).unwrap();
+        assert_eq!(request, StreamControlRequest::Presence { status: PresenceStatus::Away });
+        assert_eq!(request.stream_id(), None);
+        let conversation_id = Uuid::new_v4();
+        let request: StreamControlRequest = serde_json::from_str(&format!(
+            r#"{{"op":"typing","conversation_id":"{}"}}"#,
+            conversation_id
+        ))
+        .unwrap();
+        assert_eq!(request, StreamControlRequest::Typing { conversation_id, typing: true });
+        assert_eq!(request.op(), "typing");
+    }
+
+    #[test]
+    fn test_stream_control_response_json() {
+        let response = StreamControlResponse::Error {
+            stream_id: Some(7),
+            op: Some("seek".to_string()),
+            error: "offset out of range".to_string(),
+        };
+        let json = serde_json::to_string(&response).unwrap();
+        assert_eq!(
+            serde_json::from_str::<StreamControlResponse>(&json).unwrap(),
+            response
+        );
+
+        // data frames never parse as control responses
+        let data = serde_json::to_string(&StreamReadResponse {
+            stream_id: 7,
+            offset: 0,
+            data: b"x".to_vec(),
+        })
+        .unwrap();
+        assert!(serde_json::from_str::<StreamControlResponse>(&data).is_err());
+    }
+
+    #[test]
+    fn test_stream_record_complete_len() {
+        let event = StreamEvent::ConversationCreated {
+            conversation_id: Uuid::new_v4(),
+        };
+        let mut data = event.encode().unwrap();
+        let record_len = data.len();
+        data.extend(event.encode().unwrap());
+        assert_eq!(StreamRecord::complete_len(&data), record_len * 2);
+        assert_eq!(StreamRecord::complete_len(&data[..record_len * 2 - 1]), record_len);
+        assert_eq!(StreamRecord::complete_len(&data[..3]), 0);
+    }
+
+    #[test]
+    fn test_stream_event_roundtrip() {
+        let conversation_id = Uuid::new_v4();
+        let event = StreamEvent::ConversationRenamed {
+            conversation_id,
+            name: "cherry".to_string(),
+        };
+        let decoded = StreamEvent::decode(&event.encode().unwrap()).unwrap();
+        assert!(matches!(
+            &decoded,
+            StreamEvent::ConversationRenamed { name, .. } if name == "cherry"
+        ));
+        assert_eq!(decoded.conversation_id(), Some(conversation_id));
+
+        let event = StreamEvent::ContactUpdated {
+            target_id: Uuid::new_v4(),
+            relation_type: None,
+        };
+        let decoded = StreamEvent::decode(&event.encode().unwrap()).unwrap();
+        assert!(matches!(decoded, StreamEvent::ContactUpdated { relation_type: None, .. }));
+        assert_eq!(decoded.conversation_id(), None);
+
+        let event = StreamEvent::MessagePinned {
+            conversation_id,
+            message_id: 42,
+            user_id: Uuid::new_v4(),
+        };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["event_type"], "message_pinned");
+        let decoded = StreamEvent::decode(&event.encode().unwrap()).unwrap();
+        assert!(matches!(decoded, StreamEvent::MessagePinned { message_id: 42, .. }));
+        assert_eq!(decoded.conversation_id(), Some(conversation_id));
+    }
+
+    #[test]
+    fn test_update_conversation_meta_request_json() {
+        let request: UpdateConversationMetaRequest = serde_json::from_str(
+            r#"{"conversation_id": "00000000-0000-0000-0000-000000000000", "topic": null, "name": "cherry"}"#,
+        )
+        .unwrap();
+        assert_eq!(request.name.as_deref(), Some("cherry"));
+        assert_eq!(request.topic, Some(None));
+        assert_eq!(request.avatar_url, None);
+    }
+
+    #[test]
+    fn test_update_contact_request_json() {
+        let request: UpdateContactRequest = serde_json::from_str(r#"{"remark_name": null}"#).unwrap();
+        assert_eq!(request.remark_name, Some(None));
+        assert!(request.tags.is_none());
+
+        let request: UpdateContactRequest = serde_json::from_str(r#"{"is_favorite": true}"#).unwrap();
+        assert_eq!(request.remark_name, None);
+        assert_eq!(
+            serde_json::to_string(&request).unwrap(),
+            r#"{"is_favorite":true}"#
+        );
+    }
+
+    #[test]
+    fn test_conversation_permissions_default() {
+        let permissions: ConversationPermissions = serde_json::from_str("{}").unwrap();
+        assert_eq!(permissions, ConversationPermissions::default());
+        let permissions: ConversationPermissions =
+            serde_json::from_str(r#"{"post": "admins"}"#).unwrap();
+        assert_eq!(permissions.post, "admins");
+        assert_eq!(permissions.invite, "all");
+        assert_eq!(permissions.edit_info, "all");
+        assert_eq!(permissions.pin, "all");
+
+        // streamservers that predate the action only check reads
+        let request: CheckAclRequest =
+            serde_json::from_str(r#"{"user_id": "00000000-0000-0000-0000-000000000000", "stream_id": 1, "conversation_id": null}"#).unwrap();
+        assert_eq!(request.action, AclAction::Read);
+    }
+
+    #[test]
+    fn test_stream_read_response_binary_invalid() {
+        assert!(StreamReadResponse::decode_binary(&[0u8; 10]).is_err());
+
+        let mut frame = StreamReadResponse {
+            stream_id: 1,
+            offset: 0,
+            data: b"abc".to_vec(),
+        }
+        .encode_binary();
+        frame.pop();
+        assert!(StreamReadResponse::decode_binary(&frame).is_err());
+    }
+}
@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS conversation_members;
+ALTER TABLE conversations DROP COLUMN IF EXISTS permissions;
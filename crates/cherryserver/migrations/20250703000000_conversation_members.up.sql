@@ -0,0 +1,43 @@
+-- Add up migration script here
+
+-- 会话成员及其角色，conversations.members 保留为成员ID数组的副本
+CREATE TABLE IF NOT EXISTS conversation_members (
+    conversation_id UUID NOT NULL REFERENCES conversations(conversation_id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(user_id) ON DELETE CASCADE,
+    role VARCHAR(10) NOT NULL DEFAULT 'member' CHECK (role IN ('owner', 'admin', 'member')),
+    joined_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    muted_until TIMESTAMPTZ,
+    PRIMARY KEY (conversation_id, user_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_conversation_members_user ON conversation_members(user_id);
+
+-- 群权限 {"post": "all" | "admins", "invite": "all" | "admins"}
+ALTER TABLE conversations ADD COLUMN IF NOT EXISTS permissions JSONB NOT NULL DEFAULT '{}'::JSONB;
+
+-- 迁移已有成员，群聊中消息流的 owner 成为群主
+INSERT INTO conversation_members (conversation_id, user_id, role, joined_at)
+SELECT c.conversation_id,
+       u.user_id,
+       CASE WHEN c.conversation_type = 'group' AND s.owner_id = u.user_id THEN 'owner' ELSE 'member' END,
+       c.created_at
+FROM conversations c
+CROSS JOIN LATERAL jsonb_array_elements_text(c.members) AS m(member_id)
+JOIN users u ON u.user_id::text = m.member_id
+LEFT JOIN streams s ON s.stream_id = c.stream_id
+ON CONFLICT DO NOTHING;
+
+-- 创建者已不在群中的，由剩余成员中的第一个接任群主
+UPDATE conversation_members cm SET role = 'owner'
+FROM (
+    SELECT DISTINCT ON (m.conversation_id) m.conversation_id, m.user_id
+    FROM conversation_members m
+    JOIN conversations c ON c.conversation_id = m.conversation_id
+    WHERE c.conversation_type = 'group'
+    AND NOT EXISTS (
+        SELECT 1 FROM conversation_members o
+        WHERE o.conversation_id = m.conversation_id AND o.role = 'owner'
+    )
+    ORDER BY m.conversation_id, m.user_id
+) heir
+WHERE cm.conversation_id = heir.conversation_id AND cm.user_id = heir.user_id;
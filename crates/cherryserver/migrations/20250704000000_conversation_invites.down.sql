@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS conversation_join_requests;
+DROP TABLE IF EXISTS conversation_invites;
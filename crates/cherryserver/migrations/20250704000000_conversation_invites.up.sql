@@ -0,0 +1,34 @@
+-- Add up migration script here
+
+-- 群邀请码，expires_at / max_uses 为空表示不限制
+CREATE TABLE IF NOT EXISTS conversation_invites (
+    invite_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    conversation_id UUID NOT NULL REFERENCES conversations(conversation_id) ON DELETE CASCADE,
+    code VARCHAR(32) NOT NULL UNIQUE,
+    created_by UUID NOT NULL REFERENCES users(user_id) ON DELETE CASCADE,
+    expires_at TIMESTAMPTZ,
+    max_uses INTEGER,
+    use_count INTEGER NOT NULL DEFAULT 0,
+    requires_approval BOOLEAN NOT NULL DEFAULT false,
+    revoked_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_conversation_invites_conversation ON conversation_invites(conversation_id);
+
+-- 需要审批的入群申请
+CREATE TABLE IF NOT EXISTS conversation_join_requests (
+    request_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    conversation_id UUID NOT NULL REFERENCES conversations(conversation_id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(user_id) ON DELETE CASCADE,
+    invite_id UUID REFERENCES conversation_invites(invite_id) ON DELETE SET NULL,
+    message TEXT,
+    status VARCHAR(10) NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'approved', 'rejected')),
+    reviewed_by UUID REFERENCES users(user_id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- 每个用户在一个群中最多只有一个待审批的申请
+CREATE UNIQUE INDEX IF NOT EXISTS idx_conversation_join_requests_pending
+    ON conversation_join_requests(conversation_id, user_id) WHERE status = 'pending';
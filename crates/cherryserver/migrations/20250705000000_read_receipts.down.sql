@@ -0,0 +1,4 @@
+-- Add down migration script here
+ALTER TABLE conversation_members DROP COLUMN IF EXISTS read_at;
+ALTER TABLE conversation_members DROP COLUMN IF EXISTS delivered_up_to;
+ALTER TABLE conversation_members DROP COLUMN IF EXISTS read_up_to;
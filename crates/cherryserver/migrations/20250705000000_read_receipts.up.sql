@@ -0,0 +1,6 @@
+-- Add up migration script here
+
+-- 成员在会话中的已读/送达位置，为最后一条已读/已送达消息的 offset
+ALTER TABLE conversation_members ADD COLUMN IF NOT EXISTS read_up_to BIGINT;
+ALTER TABLE conversation_members ADD COLUMN IF NOT EXISTS delivered_up_to BIGINT;
+ALTER TABLE conversation_members ADD COLUMN IF NOT EXISTS read_at TIMESTAMPTZ;
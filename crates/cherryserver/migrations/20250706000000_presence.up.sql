@@ -0,0 +1,5 @@
+-- Add up migration script here
+
+-- 在线状态由 streamserver 在用户连接、断开时更新，之前的值没有意义
+ALTER TABLE users ALTER COLUMN status SET DEFAULT 'offline';
+UPDATE users SET status = 'offline';
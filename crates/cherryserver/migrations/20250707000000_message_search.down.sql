@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS message_search_offsets;
+DROP TABLE IF EXISTS message_search;
@@ -0,0 +1,27 @@
+-- Add up migration script here
+
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+-- 消息搜索索引，由 cherryserver 读取会话消息流生成
+CREATE TABLE IF NOT EXISTS message_search (
+    conversation_id UUID NOT NULL REFERENCES conversations(conversation_id) ON DELETE CASCADE,
+    message_id BIGINT NOT NULL, -- 消息的 stream offset
+    user_id UUID NOT NULL,
+    message_type VARCHAR(20) NOT NULL,
+    content TEXT NOT NULL,
+    content_tsv TSVECTOR GENERATED ALWAYS AS (to_tsvector('simple', content)) STORED,
+    timestamp TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (conversation_id, message_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_message_search_tsv ON message_search USING GIN (content_tsv);
+-- 中文等没有空格分词的文本按子串匹配
+CREATE INDEX IF NOT EXISTS idx_message_search_trgm ON message_search USING GIN (content gin_trgm_ops);
+CREATE INDEX IF NOT EXISTS idx_message_search_timestamp ON message_search (conversation_id, timestamp DESC);
+
+-- 每个消息流已索引到的位置
+CREATE TABLE IF NOT EXISTS message_search_offsets (
+    stream_id BIGINT PRIMARY KEY,
+    "offset" BIGINT NOT NULL DEFAULT 0,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS starred_messages;
+DROP TABLE IF EXISTS conversation_pins;
@@ -0,0 +1,21 @@
+-- Add up migration script here
+
+-- 会话的置顶消息，message_id 为消息在会话流中的位置
+CREATE TABLE IF NOT EXISTS conversation_pins (
+    conversation_id UUID NOT NULL REFERENCES conversations(conversation_id) ON DELETE CASCADE,
+    message_id BIGINT NOT NULL,
+    pinned_by UUID REFERENCES users(user_id) ON DELETE SET NULL,
+    pinned_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (conversation_id, message_id)
+);
+
+-- 用户收藏的消息，只有自己可见
+CREATE TABLE IF NOT EXISTS starred_messages (
+    user_id UUID NOT NULL REFERENCES users(user_id) ON DELETE CASCADE,
+    conversation_id UUID NOT NULL REFERENCES conversations(conversation_id) ON DELETE CASCADE,
+    message_id BIGINT NOT NULL,
+    starred_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, conversation_id, message_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_starred_messages_user ON starred_messages(user_id, starred_at DESC);
@@ -0,0 +1,254 @@
+use cherrycore::types::{ResponseError, UpdateContactRequest};
+
+// 好友关系在 contacts 表中双向保存，每个用户一行
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Relation {
+    Friend,
+    Blocked,
+    PendingOutgoing,
+    PendingIncoming,
+}
+
+impl Relation {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Relation::Friend => "friend",
+            Relation::Blocked => "blocked",
+            Relation::PendingOutgoing => "pending_outgoing",
+            Relation::PendingIncoming => "pending_incoming",
+        }
+    }
+
+    pub(crate) fn parse(relation_type: &str) -> Option<Self> {
+        match relation_type {
+            "friend" => Some(Relation::Friend),
+            "blocked" => Some(Relation::Blocked),
+            "pending_outgoing" => Some(Relation::PendingOutgoing),
+            "pending_incoming" => Some(Relation::PendingIncoming),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum ContactAction {
+    Request,
+    Accept,
+    Reject,
+    Cancel,
+    Block,
+    Unblock,
+}
+
+// (我方关系, 对方关系) 的变化，None 表示没有该行
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct RelationChange {
+    pub(crate) mine_before: Option<Relation>,
+    pub(crate) theirs_before: Option<Relation>,
+    pub(crate) mine: Option<Relation>,
+    pub(crate) theirs: Option<Relation>,
+}
+
+// 返回 None 表示当前关系下不允许该操作
+pub(crate) fn transition(
+    action: ContactAction,
+    mine: Option<Relation>,
+    theirs: Option<Relation>,
+) -> Option<RelationChange> {
+    use Relation::*;
+
+    let (new_mine, new_theirs) = match (action, mine) {
+        // 被对方拉黑时不能发送请求
+        (ContactAction::Request, _) if theirs == Some(Blocked) => return None,
+        (ContactAction::Request, None | Some(PendingOutgoing)) => {
+            (Some(PendingOutgoing), Some(PendingIncoming))
+        }
+        // 双方互相发送请求，直接成为好友
+        (ContactAction::Request, Some(PendingIncoming)) => (Some(Friend), Some(Friend)),
+        (ContactAction::Accept, Some(PendingIncoming)) if theirs != Some(Blocked) => {
+            (Some(Friend), Some(Friend))
+        }
+        (ContactAction::Reject, Some(PendingIncoming)) => {
+            (None, theirs.filter(|r| *r != PendingOutgoing))
+        }
+        (ContactAction::Cancel, Some(PendingOutgoing)) => {
+            (None, theirs.filter(|r| *r != PendingIncoming))
+        }
+        // 拉黑同时解除对方的好友关系，对方拉黑我方的记录保留
+        (ContactAction::Block, _) => (Some(Blocked), theirs.filter(|r| *r == Blocked)),
+        (ContactAction::Unblock, Some(Blocked)) => (None, theirs),
+        _ => return None,
+    };
+
+    Some(RelationChange {
+        mine_before: mine,
+        theirs_before: theirs,
+        mine: new_mine,
+        theirs: new_theirs,
+    })
+}
+
+const MAX_REMARK_LEN: usize = 100;
+const MAX_TAGS: usize = 20;
+const MAX_TAG_LEN: usize = 32;
+const MAX_CUSTOM_FIELDS: usize = 50;
+const MAX_CUSTOM_FIELDS_SIZE: usize = 4096;
+
+// 校验联系人的修改，去掉标签两端空白并去重
+pub(crate) fn validate_update(update: &mut UpdateContactRequest) -> Result<(), ResponseError> {
+    if let Some(Some(remark_name)) = update.remark_name.as_mut() {
+        *remark_name = remark_name.trim().to_string();
+        if remark_name.chars().count() > MAX_REMARK_LEN {
+            return Err(ResponseError::DataInvalid);
+        }
+    }
+
+    if let Some(tags) = update.tags.as_mut() {
+        let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+        for tag in tags.iter() {
+            let tag = tag.trim();
+            if tag.is_empty() || tag.chars().count() > MAX_TAG_LEN {
+                return Err(ResponseError::DataInvalid);
+            }
+            if !normalized.iter().any(|t| t == tag) {
+                normalized.push(tag.to_string());
+            }
+        }
+        if normalized.len() > MAX_TAGS {
+            return Err(ResponseError::DataInvalid);
+        }
+        *tags = normalized;
+    }
+
+    if let Some(mute_settings) = update.mute_settings.as_mut()
+        && !mute_settings.muted
+    {
+        mute_settings.expire_at = None;
+    }
+
+    if let Some(custom_fields) = update.custom_fields.as_ref() {
+        let size = serde_json::to_vec(custom_fields)
+            .map_err(|e| ResponseError::InternalError(e.into()))?
+            .len();
+        if custom_fields.len() > MAX_CUSTOM_FIELDS || size > MAX_CUSTOM_FIELDS_SIZE {
+            return Err(ResponseError::DataInvalid);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Relation::*;
+
+    fn apply(
+        action: ContactAction,
+        mine: Option<Relation>,
+        theirs: Option<Relation>,
+    ) -> Option<(Option<Relation>, Option<Relation>)> {
+        transition(action, mine, theirs).map(|change| (change.mine, change.theirs))
+    }
+
+    #[test]
+    fn test_friend_request_transitions() {
+        assert_eq!(
+            apply(ContactAction::Request, None, None),
+            Some((Some(PendingOutgoing), Some(PendingIncoming)))
+        );
+        assert_eq!(
+            apply(
+                ContactAction::Request,
+                Some(PendingIncoming),
+                Some(PendingOutgoing)
+            ),
+            Some((Some(Friend), Some(Friend)))
+        );
+        assert_eq!(apply(ContactAction::Request, None, Some(Blocked)), None);
+        assert_eq!(
+            apply(ContactAction::Request, Some(Friend), Some(Friend)),
+            None
+        );
+
+        assert_eq!(
+            apply(
+                ContactAction::Accept,
+                Some(PendingIncoming),
+                Some(PendingOutgoing)
+            ),
+            Some((Some(Friend), Some(Friend)))
+        );
+        assert_eq!(
+            apply(
+                ContactAction::Accept,
+                Some(PendingOutgoing),
+                Some(PendingIncoming)
+            ),
+            None
+        );
+        assert_eq!(
+            apply(
+                ContactAction::Reject,
+                Some(PendingIncoming),
+                Some(PendingOutgoing)
+            ),
+            Some((None, None))
+        );
+        assert_eq!(
+            apply(
+                ContactAction::Cancel,
+                Some(PendingOutgoing),
+                Some(PendingIncoming)
+            ),
+            Some((None, None))
+        );
+        assert_eq!(
+            apply(ContactAction::Cancel, Some(PendingIncoming), None),
+            None
+        );
+    }
+
+    #[test]
+    fn test_block_transitions() {
+        assert_eq!(
+            apply(ContactAction::Block, Some(Friend), Some(Friend)),
+            Some((Some(Blocked), None))
+        );
+        assert_eq!(
+            apply(ContactAction::Block, None, Some(Blocked)),
+            Some((Some(Blocked), Some(Blocked)))
+        );
+        assert_eq!(
+            apply(ContactAction::Unblock, Some(Blocked), Some(Blocked)),
+            Some((None, Some(Blocked)))
+        );
+        assert_eq!(
+            apply(ContactAction::Unblock, Some(Friend), Some(Friend)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_validate_update() {
+        let mut update = UpdateContactRequest {
+            remark_name: Some(Some("  Bob ".to_string())),
+            tags: Some(vec![" work ".to_string(), "work".to_string(), "family".to_string()]),
+            ..Default::default()
+        };
+        assert!(validate_update(&mut update).is_ok());
+        assert_eq!(update.remark_name, Some(Some("Bob".to_string())));
+        assert_eq!(update.tags, Some(vec!["work".to_string(), "family".to_string()]));
+
+        let mut update = UpdateContactRequest {
+            tags: Some(vec!["  ".to_string()]),
+            ..Default::default()
+        };
+        assert!(validate_update(&mut update).is_err());
+
+        let mut update = UpdateContactRequest {
+            remark_name: Some(Some("x".repeat(MAX_REMARK_LEN + 1))),
+            ..Default::default()
+        };
+        assert!(validate_update(&mut update).is_err());
+    }
+}
@@ -85,6 +85,7 @@ pub struct Stream {
 //     meta JSONB NOT NULL DEFAULT '{}'::JSONB,    -- 动态会话属性
 //     -- 消息流ID
 //     message_stream_id BIGINT NOT NULL,
+//     permissions JSONB NOT NULL DEFAULT '{}'::JSONB, -- 群权限
 //     created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
 //     updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
 // );
@@ -96,6 +97,147 @@ pub struct Conversation {
     pub members: JsonValue,
     pub meta: JsonValue,
     pub stream_id: i64,
+    pub permissions: JsonValue,
     pub created_at: DateTime<chrono::Utc>,
     pub updated_at: DateTime<chrono::Utc>,
-}
\ No newline at end of file
+}
+
+impl Conversation {
+    // members 存储为 uuid 字符串数组
+    pub fn member_ids(&self) -> Vec<Uuid> {
+        self.members
+            .as_array()
+            .map(|members| {
+                members
+                    .iter()
+                    .filter_map(|m| m.as_str().and_then(|m| Uuid::parse_str(m).ok()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    // 未设置的权限默认为 all
+    pub fn permissions(&self) -> cherrycore::types::ConversationPermissions {
+        serde_json::from_value(self.permissions.clone()).unwrap_or_default()
+    }
+}
+
+impl From<Conversation> for cherrycore::types::Conversation {
+    fn from(c: Conversation) -> Self {
+        let permissions = c.permissions();
+        Self {
+            conversation_id: c.conversation_id,
+            conversation_type: c.conversation_type,
+            members: c.members,
+            meta: c.meta,
+            stream_id: c.stream_id,
+            permissions,
+            created_at: c.created_at,
+            updated_at: c.updated_at,
+            read_up_to: None,
+            unread_count: None,
+        }
+    }
+}
+
+// CREATE TABLE IF NOT EXISTS conversation_members (
+//     conversation_id UUID NOT NULL REFERENCES conversations(conversation_id) ON DELETE CASCADE,
+//     user_id UUID NOT NULL REFERENCES users(user_id) ON DELETE CASCADE,
+//     role VARCHAR(10) NOT NULL DEFAULT 'member' CHECK (role IN ('owner', 'admin', 'member')),
+//     joined_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+//     muted_until TIMESTAMPTZ,
+//     read_up_to BIGINT,
+//     delivered_up_to BIGINT,
+//     read_at TIMESTAMPTZ,
+//     PRIMARY KEY (conversation_id, user_id)
+// );
+pub type ConversationMember = cherrycore::types::ConversationMember;
+
+// CREATE TABLE IF NOT EXISTS conversation_invites (
+//     invite_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+//     conversation_id UUID NOT NULL REFERENCES conversations(conversation_id) ON DELETE CASCADE,
+//     code VARCHAR(32) NOT NULL UNIQUE,
+//     created_by UUID NOT NULL REFERENCES users(user_id) ON DELETE CASCADE,
+//     expires_at TIMESTAMPTZ,
+//     max_uses INTEGER,
+//     use_count INTEGER NOT NULL DEFAULT 0,
+//     requires_approval BOOLEAN NOT NULL DEFAULT false,
+//     revoked_at TIMESTAMPTZ,
+//     created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+// );
+pub type ConversationInvite = cherrycore::types::ConversationInvite;
+
+// CREATE TABLE IF NOT EXISTS conversation_join_requests (
+//     request_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+//     conversation_id UUID NOT NULL REFERENCES conversations(conversation_id) ON DELETE CASCADE,
+//     user_id UUID NOT NULL REFERENCES users(user_id) ON DELETE CASCADE,
+//     invite_id UUID REFERENCES conversation_invites(invite_id) ON DELETE SET NULL,
+//     message TEXT,
+//     status VARCHAR(10) NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'approved', 'rejected')),
+//     reviewed_by UUID REFERENCES users(user_id) ON DELETE SET NULL,
+//     created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+//     updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+// );
+pub type JoinRequest = cherrycore::types::JoinRequest;
+
+// CREATE TABLE IF NOT EXISTS refresh_tokens (
+//     token_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+//     user_id UUID NOT NULL REFERENCES users(user_id) ON DELETE CASCADE,
+//     token_hash TEXT NOT NULL,
+//     expires_at TIMESTAMPTZ NOT NULL,
+//     revoked_at TIMESTAMPTZ,
+//     created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+// );
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
+pub struct RefreshToken {
+    pub token_id: Uuid,
+    pub user_id: Uuid,
+    pub token_hash: String,
+    pub expires_at: DateTime<chrono::Utc>,
+    pub revoked_at: Option<DateTime<chrono::Utc>>,
+    pub created_at: DateTime<chrono::Utc>,
+}
+
+// CREATE TABLE IF NOT EXISTS revoked_tokens (
+//     jti UUID PRIMARY KEY,
+//     user_id UUID NOT NULL REFERENCES users(user_id) ON DELETE CASCADE,
+//     expires_at TIMESTAMPTZ NOT NULL,
+//     revoked_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+// );
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
+pub struct RevokedToken {
+    pub jti: Uuid,
+    pub user_id: Uuid,
+    pub expires_at: DateTime<chrono::Utc>,
+    pub revoked_at: DateTime<chrono::Utc>,
+}
+
+// CREATE TABLE IF NOT EXISTS message_search (
+//     conversation_id UUID NOT NULL REFERENCES conversations(conversation_id) ON DELETE CASCADE,
+//     message_id BIGINT NOT NULL, -- 消息的 stream offset
+//     user_id UUID NOT NULL,
+//     message_type VARCHAR(20) NOT NULL,
+//     content TEXT NOT NULL,
+//     content_tsv TSVECTOR GENERATED ALWAYS AS (to_tsvector('simple', content)) STORED,
+//     timestamp TIMESTAMPTZ NOT NULL,
+//     PRIMARY KEY (conversation_id, message_id)
+// );
+pub type SearchResult = cherrycore::types::SearchResult;
+
+// CREATE TABLE IF NOT EXISTS conversation_pins (
+//     conversation_id UUID NOT NULL REFERENCES conversations(conversation_id) ON DELETE CASCADE,
+//     message_id BIGINT NOT NULL, -- 消息的 stream offset
+//     pinned_by UUID REFERENCES users(user_id) ON DELETE SET NULL,
+//     pinned_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+//     PRIMARY KEY (conversation_id, message_id)
+// );
+pub type PinnedMessage = cherrycore::types::PinnedMessage;
+
+// CREATE TABLE IF NOT EXISTS starred_messages (
+//     user_id UUID NOT NULL REFERENCES users(user_id) ON DELETE CASCADE,
+//     conversation_id UUID NOT NULL REFERENCES conversations(conversation_id) ON DELETE CASCADE,
+//     message_id BIGINT NOT NULL,
+//     starred_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+//     PRIMARY KEY (user_id, conversation_id, message_id)
+// );
+pub type StarredMessage = cherrycore::types::StarredMessage;
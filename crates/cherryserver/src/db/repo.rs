@@ -1,7 +1,10 @@
 use std::time::Duration;
 
 use anyhow::Result;
-use cherrycore::types::StreamType;
+use chrono::{DateTime, Utc};
+use cherrycore::types::{
+    ConversationPermissions, ListContactsRequest, StreamType, UpdateContactRequest,
+};
 use serde_json::json;
 use sqlx::{
     Pool,
@@ -10,7 +13,22 @@ use sqlx::{
     types::Uuid,
 };
 
-use crate::db::models::*;
+use crate::{
+    contacts::{self, ContactAction, Relation, RelationChange},
+    db::models::*,
+    invites::{self, Redemption},
+    password,
+    search::{IndexChange, SearchQuery},
+};
+
+const CONTACT_SELECT: &str = r#"
+    SELECT contacts.*, users.avatar_url, users.status,
+        COALESCE(contact_details.custom_fields, '{}'::JSONB) AS custom_fields
+    FROM contacts
+    LEFT JOIN users ON contacts.target_id = users.user_id
+    LEFT JOIN contact_details ON contact_details.contact_id = contacts.contact_id
+        AND contact_details.user_id = contacts.owner_id
+"#;
 
 #[derive(Clone)]
 pub struct Repo {
@@ -41,23 +59,452 @@ impl Repo {
         Ok(user)
     }
 
+    pub async fn user_get_by_id(&self, user_id: Uuid) -> Result<User> {
+        let user = query_as::<_, User>("SELECT * FROM users WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_one(&self.sqlx_pool)
+            .await?;
+        Ok(user)
+    }
+
     pub async fn check_password(&self, email: &str, password: &str) -> Result<bool> {
+        let Some(user) = query_as::<_, User>("SELECT * FROM users WHERE email = $1")
+            .bind(email)
+            .fetch_optional(&self.sqlx_pool)
+            .await?
+        else {
+            return Ok(false);
+        };
+
+        // 仅通过 OIDC 登录的账号没有密码
+        if user.password_hash.is_empty() {
+            return Ok(false);
+        }
+        if password::is_password_hash(&user.password_hash) {
+            return password::verify_password_async(password.to_string(), user.password_hash).await;
+        }
+
+        // 旧数据保存的是明文密码，校验通过后替换为哈希
+        if user.password_hash != password {
+            return Ok(false);
+        }
+        let password_hash = password::hash_password_async(password.to_string()).await?;
+        self.update_password(user.user_id, &password_hash).await?;
+        log::info!("migrated plaintext password of user {}", user.user_id);
+        Ok(true)
+    }
+
+    pub async fn user_find_by_email(&self, email: &str) -> Result<Option<User>> {
         let user = query_as::<_, User>("SELECT * FROM users WHERE email = $1")
             .bind(email)
-            .fetch_one(&self.sqlx_pool)
+            .fetch_optional(&self.sqlx_pool)
             .await?;
-        Ok(user.password_hash == password)
+        Ok(user)
+    }
+
+    pub async fn user_find_by_identity(&self, provider: &str, subject: &str) -> Result<Option<User>> {
+        let user = query_as::<_, User>(
+            r#"
+            SELECT users.* FROM users
+            JOIN user_identities ON user_identities.user_id = users.user_id
+            WHERE user_identities.provider = $1 AND user_identities.subject = $2
+            "#,
+        )
+        .bind(provider)
+        .bind(subject)
+        .fetch_optional(&self.sqlx_pool)
+        .await?;
+        Ok(user)
     }
 
-    pub async fn list_contacts(&self, user_id: Uuid) -> Result<Vec<Contact>> {
-        let contacts = query_as::<_, Contact>(
-            "SELECT contacts.*, users.avatar_url, users.status FROM contacts LEFT JOIN users ON contacts.target_id = users.user_id WHERE contacts.owner_id = $1")
+    pub async fn link_identity(
+        &self,
+        provider: &str,
+        subject: &str,
+        user_id: Uuid,
+        email: Option<&str>,
+    ) -> Result<()> {
+        query(
+            r#"
+            INSERT INTO user_identities (provider, subject, user_id, email)
+            VALUES ($1, $2, $3, $4)
+            "#,
+        )
+        .bind(provider)
+        .bind(subject)
+        .bind(user_id)
+        .bind(email)
+        .execute(&self.sqlx_pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn update_password(&self, user_id: Uuid, password_hash: &str) -> Result<()> {
+        query("UPDATE users SET password_hash = $1 WHERE user_id = $2")
+            .bind(password_hash)
             .bind(user_id)
-            .fetch_all(&self.sqlx_pool)
+            .execute(&self.sqlx_pool)
             .await?;
+        Ok(())
+    }
+
+    pub async fn create_refresh_token(
+        &self,
+        token_id: Uuid,
+        user_id: Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<()> {
+        query(
+            r#"
+            INSERT INTO refresh_tokens (token_id, user_id, token_hash, expires_at)
+            VALUES ($1, $2, $3, $4)
+            "#,
+        )
+        .bind(token_id)
+        .bind(user_id)
+        .bind(token_hash)
+        .bind(expires_at)
+        .execute(&self.sqlx_pool)
+        .await?;
+        Ok(())
+    }
+
+    // 刷新令牌只能使用一次，返回令牌所属用户。已使用的令牌再次出现说明可能被盗用，
+    // 吊销该用户的所有刷新令牌
+    pub async fn use_refresh_token(&self, token_id: Uuid, token_hash: &str) -> Result<Option<Uuid>> {
+        let mut tx = self.sqlx_pool.begin().await?;
+        let Some(token) = query_as::<_, RefreshToken>(
+            "SELECT * FROM refresh_tokens WHERE token_id = $1 FOR UPDATE",
+        )
+        .bind(token_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            return Ok(None);
+        };
+        if token.token_hash != token_hash {
+            return Ok(None);
+        }
+
+        if token.revoked_at.is_some() {
+            log::warn!(
+                "refresh token {} reused, revoking all refresh tokens of user {}",
+                token_id,
+                token.user_id
+            );
+            query("UPDATE refresh_tokens SET revoked_at = NOW() WHERE user_id = $1 AND revoked_at IS NULL")
+                .bind(token.user_id)
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+            return Ok(None);
+        }
+        if token.expires_at <= Utc::now() {
+            return Ok(None);
+        }
+
+        query("UPDATE refresh_tokens SET revoked_at = NOW() WHERE token_id = $1")
+            .bind(token_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(Some(token.user_id))
+    }
+
+    pub async fn revoke_refresh_token(&self, token_id: Uuid, user_id: Uuid) -> Result<bool> {
+        let result = query(
+            "UPDATE refresh_tokens SET revoked_at = NOW() WHERE token_id = $1 AND user_id = $2 AND revoked_at IS NULL",
+        )
+        .bind(token_id)
+        .bind(user_id)
+        .execute(&self.sqlx_pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn revoke_user_refresh_tokens(&self, user_id: Uuid) -> Result<u64> {
+        let result = query("UPDATE refresh_tokens SET revoked_at = NOW() WHERE user_id = $1 AND revoked_at IS NULL")
+            .bind(user_id)
+            .execute(&self.sqlx_pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    pub async fn revoke_access_token(
+        &self,
+        jti: Uuid,
+        user_id: Uuid,
+        expires_at: DateTime<Utc>,
+    ) -> Result<()> {
+        query(
+            r#"
+            INSERT INTO revoked_tokens (jti, user_id, expires_at)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (jti) DO NOTHING
+            "#,
+        )
+        .bind(jti)
+        .bind(user_id)
+        .bind(expires_at)
+        .execute(&self.sqlx_pool)
+        .await?;
+        Ok(())
+    }
+
+    // 未过期且在 since 之后吊销的访问令牌
+    pub async fn list_revoked_tokens(&self, since: DateTime<Utc>) -> Result<Vec<RevokedToken>> {
+        let tokens = query_as::<_, RevokedToken>(
+            r#"
+            SELECT * FROM revoked_tokens
+            WHERE expires_at > NOW() AND revoked_at >= $1
+            ORDER BY revoked_at
+            "#,
+        )
+        .bind(since)
+        .fetch_all(&self.sqlx_pool)
+        .await?;
+        Ok(tokens)
+    }
+
+    // 创建用户和对应的通知流（使用事务）
+    pub async fn create_user(
+        &self,
+        username: &str,
+        email: &str,
+        password_hash: &str,
+    ) -> Result<(User, Stream)> {
+        let mut tx = self.sqlx_pool.begin().await?;
+
+        let user = query_as::<_, User>(
+            r#"
+            INSERT INTO users (username, email, password_hash)
+            VALUES ($1, $2, $3)
+            RETURNING *
+            "#,
+        )
+        .bind(username)
+        .bind(email)
+        .bind(password_hash)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let stream = query_as::<_, Stream>(
+            r#"
+            INSERT INTO streams (
+                owner_id, stream_type, status, "offset", stream_meta,
+                created_at, updated_at
+            )
+            VALUES (
+                $1, $2, 'active', 0, '{}'::jsonb,
+                NOW(), NOW()
+            )
+            RETURNING *
+            "#,
+        )
+        .bind(user.user_id)
+        .bind(StreamType::Notification.to_string())
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok((user, stream))
+    }
+
+    pub async fn query_contacts(
+        &self,
+        user_id: Uuid,
+        filter: &ListContactsRequest,
+    ) -> Result<Vec<Contact>> {
+        let contacts = query_as::<_, Contact>(&format!(
+            r#"
+            {CONTACT_SELECT}
+            WHERE contacts.owner_id = $1
+                AND ($2::TEXT IS NULL OR contacts.tags ? $2)
+                AND ($3::BOOLEAN IS NULL OR contacts.is_favorite = $3)
+                AND ($4::TEXT IS NULL OR contacts.relation_type = $4)
+            "#
+        ))
+        .bind(user_id)
+        .bind(filter.tag.as_deref())
+        .bind(filter.favorite)
+        .bind(filter.relation_type.as_deref())
+        .fetch_all(&self.sqlx_pool)
+        .await?;
         Ok(contacts)
     }
 
+    pub async fn get_contact(&self, owner_id: Uuid, target_id: Uuid) -> Result<Option<Contact>> {
+        let contact = query_as::<_, Contact>(&format!(
+            "{CONTACT_SELECT} WHERE contacts.owner_id = $1 AND contacts.target_id = $2"
+        ))
+        .bind(owner_id)
+        .bind(target_id)
+        .fetch_optional(&self.sqlx_pool)
+        .await?;
+        Ok(contact)
+    }
+
+    // 只更新请求中给出的字段，联系人不存在时返回 None
+    pub async fn update_contact(
+        &self,
+        owner_id: Uuid,
+        target_id: Uuid,
+        update: &UpdateContactRequest,
+    ) -> Result<Option<Contact>> {
+        let mut tx = self.sqlx_pool.begin().await?;
+
+        let contact_id = query_scalar::<_, Uuid>(
+            r#"
+            UPDATE contacts SET
+                remark_name = CASE WHEN $3 THEN $4 ELSE remark_name END,
+                tags = COALESCE($5, tags),
+                is_favorite = COALESCE($6, is_favorite),
+                mute_settings = COALESCE($7, mute_settings),
+                updated_at = NOW()
+            WHERE owner_id = $1 AND target_id = $2
+            RETURNING contact_id
+            "#,
+        )
+        .bind(owner_id)
+        .bind(target_id)
+        .bind(update.remark_name.is_some())
+        .bind(update.remark_name.clone().flatten())
+        .bind(update.tags.as_ref().map(|tags| json!(tags)))
+        .bind(update.is_favorite)
+        .bind(update.mute_settings.as_ref().map(|settings| json!(settings)))
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(contact_id) = contact_id else {
+            return Ok(None);
+        };
+
+        if let Some(custom_fields) = update.custom_fields.as_ref() {
+            // 合并到已有字段，值为 null 的键被删除
+            query(
+                r#"
+                INSERT INTO contact_details (contact_id, user_id, custom_fields)
+                VALUES ($1, $2, jsonb_strip_nulls($3))
+                ON CONFLICT (contact_id, user_id) DO UPDATE SET custom_fields =
+                    jsonb_strip_nulls(COALESCE(contact_details.custom_fields, '{}'::JSONB) || $3)
+                "#,
+            )
+            .bind(contact_id)
+            .bind(owner_id)
+            .bind(json!(custom_fields))
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        self.get_contact(owner_id, target_id).await
+    }
+
+    pub async fn user_exists(&self, user_id: Uuid) -> Result<bool> {
+        let exists = query_scalar::<_, bool>("SELECT EXISTS(SELECT 1 FROM users WHERE user_id = $1)")
+            .bind(user_id)
+            .fetch_one(&self.sqlx_pool)
+            .await?;
+        Ok(exists)
+    }
+
+    // 更新用户的在线状态和最后活跃时间
+    pub async fn update_user_presence(&self, user_id: Uuid, status: &str) -> Result<()> {
+        query("UPDATE users SET status = $2, last_active = NOW() WHERE user_id = $1")
+            .bind(user_id)
+            .bind(status)
+            .execute(&self.sqlx_pool)
+            .await?;
+        Ok(())
+    }
+
+    // 把 user_id 加为好友的用户，拉黑时对方的好友记录已被删除
+    pub async fn list_presence_audience(&self, user_id: Uuid) -> Result<Vec<Uuid>> {
+        let user_ids = query_scalar::<_, Uuid>(
+            "SELECT owner_id FROM contacts WHERE target_id = $1 AND relation_type = 'friend'",
+        )
+        .bind(user_id)
+        .fetch_all(&self.sqlx_pool)
+        .await?;
+        Ok(user_ids)
+    }
+
+    // 锁定双方的记录后更新，当前关系不允许该操作时返回 None
+    pub async fn update_contact_relation(
+        &self,
+        user_id: Uuid,
+        target_id: Uuid,
+        action: ContactAction,
+    ) -> Result<Option<RelationChange>> {
+        let mut tx = self.sqlx_pool.begin().await?;
+
+        // 按 owner_id 排序加锁，避免双方同时操作时死锁
+        let rows = query_as::<_, (Uuid, String)>(
+            r#"
+            SELECT owner_id, relation_type FROM contacts
+            WHERE (owner_id = $1 AND target_id = $2) OR (owner_id = $2 AND target_id = $1)
+            ORDER BY owner_id
+            FOR UPDATE
+            "#,
+        )
+        .bind(user_id)
+        .bind(target_id)
+        .fetch_all(&mut *tx)
+        .await?;
+        let relation_of = |owner_id: Uuid| {
+            rows.iter()
+                .find(|(owner, _)| *owner == owner_id)
+                .and_then(|(_, relation_type)| Relation::parse(relation_type))
+        };
+
+        let Some(change) = contacts::transition(action, relation_of(user_id), relation_of(target_id))
+        else {
+            return Ok(None);
+        };
+        if change.mine != change.mine_before {
+            Self::set_contact_relation(&mut tx, user_id, target_id, change.mine).await?;
+        }
+        if change.theirs != change.theirs_before {
+            Self::set_contact_relation(&mut tx, target_id, user_id, change.theirs).await?;
+        }
+
+        tx.commit().await?;
+        Ok(Some(change))
+    }
+
+    async fn set_contact_relation(
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        owner_id: Uuid,
+        target_id: Uuid,
+        relation: Option<Relation>,
+    ) -> Result<()> {
+        match relation {
+            Some(relation) => {
+                query(
+                    r#"
+                    INSERT INTO contacts (owner_id, target_id, relation_type)
+                    VALUES ($1, $2, $3)
+                    ON CONFLICT (owner_id, target_id)
+                    DO UPDATE SET relation_type = EXCLUDED.relation_type, updated_at = NOW()
+                    "#,
+                )
+                .bind(owner_id)
+                .bind(target_id)
+                .bind(relation.as_str())
+                .execute(&mut **tx)
+                .await?;
+            }
+            None => {
+                query("DELETE FROM contacts WHERE owner_id = $1 AND target_id = $2")
+                    .bind(owner_id)
+                    .bind(target_id)
+                    .execute(&mut **tx)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
     pub async fn list_streams(&self, user_id: Uuid) -> Result<Vec<Stream>> {
         // Return stream IDs as i64 instead of full Stream objects to avoid schema issues
         let streams = query_as::<_, Stream>("SELECT * FROM streams WHERE owner_id = $1")
@@ -87,9 +534,9 @@ impl Repo {
     }
 
     pub async fn check_acl_by_conversation_id(&self, user_id: Uuid, conversation_id: Uuid) -> Result<bool> {
-        let count: Option<i64> = query_scalar("SELECT count(*) FROM conversations WHERE conversation_id = $1 AND members @> $2::jsonb")
+        let count: Option<i64> = query_scalar("SELECT count(*) FROM conversation_members WHERE conversation_id = $1 AND user_id = $2")
             .bind(conversation_id)
-            .bind(json!([user_id.to_string()]))
+            .bind(user_id)
             .fetch_one(&self.sqlx_pool)
             .await?;
         let count = count.unwrap_or(0);
@@ -97,11 +544,17 @@ impl Repo {
     }
 
     pub async fn check_acl(&self, user_id: Uuid, stream_id: i64) -> Result<bool> {
-        let count: Option<i64> = query_scalar("SELECT count(*) FROM conversations WHERE members @> $1::jsonb AND message_stream_id = $2")
-            .bind(json!([user_id.to_string()]))
-            .bind(stream_id)
-            .fetch_one(&self.sqlx_pool)
-            .await?;
+        let count: Option<i64> = query_scalar(
+            r#"
+            SELECT count(*) FROM conversation_members m
+            JOIN conversations c ON c.conversation_id = m.conversation_id
+            WHERE c.stream_id = $1 AND m.user_id = $2
+            "#,
+        )
+        .bind(stream_id)
+        .bind(user_id)
+        .fetch_one(&self.sqlx_pool)
+        .await?;
         let count = count.unwrap_or(0);
         Ok(count > 0)
     }
@@ -300,9 +753,809 @@ impl Repo {
         .fetch_one(&mut *tx)
         .await?;
 
+        // 群聊的创建者成为群主
+        let owner_id = (conversation_type == "group").then_some(creator_id);
+        Self::insert_conversation_members(&mut tx, conversation.conversation_id, members, owner_id)
+            .await?;
+
         // 提交事务
         tx.commit().await?;
 
         Ok((conversation, stream, true)) // true 表示是新创建的
     }
+
+    pub async fn get_conversation(&self, conversation_id: Uuid) -> Result<Option<Conversation>> {
+        let conversation =
+            query_as::<_, Conversation>("SELECT * FROM conversations WHERE conversation_id = $1")
+                .bind(conversation_id)
+                .fetch_optional(&self.sqlx_pool)
+                .await?;
+        Ok(conversation)
+    }
+
+    pub async fn get_conversation_by_stream(&self, stream_id: i64) -> Result<Option<Conversation>> {
+        let conversation =
+            query_as::<_, Conversation>("SELECT * FROM conversations WHERE stream_id = $1")
+                .bind(stream_id)
+                .fetch_optional(&self.sqlx_pool)
+                .await?;
+        Ok(conversation)
+    }
+
+    // 添加会话成员，返回更新后的会话和实际新增的成员
+    pub async fn add_conversation_members(
+        &self,
+        conversation_id: Uuid,
+        members: &[Uuid],
+    ) -> Result<Option<(Conversation, Vec<Uuid>)>> {
+        let mut tx = self.sqlx_pool.begin().await?;
+        let result = Self::add_members_in_tx(&mut tx, conversation_id, members).await?;
+        tx.commit().await?;
+        Ok(result)
+    }
+
+    async fn add_members_in_tx(
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        conversation_id: Uuid,
+        members: &[Uuid],
+    ) -> Result<Option<(Conversation, Vec<Uuid>)>> {
+        let Some(conversation) = query_as::<_, Conversation>(
+            "SELECT * FROM conversations WHERE conversation_id = $1 FOR UPDATE",
+        )
+        .bind(conversation_id)
+        .fetch_optional(&mut **tx)
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        let mut current = conversation.member_ids();
+        let mut added = Vec::new();
+        for member in members {
+            if !current.contains(member) && !added.contains(member) {
+                added.push(*member);
+            }
+        }
+        current.extend(added.iter().copied());
+
+        let conversation = Self::update_conversation_members(tx, conversation_id, &current).await?;
+        Self::insert_conversation_members(tx, conversation_id, &added, None).await?;
+        Ok(Some((conversation, added)))
+    }
+
+    // 移除会话成员，返回更新后的会话和实际移除的成员
+    pub async fn remove_conversation_members(
+        &self,
+        conversation_id: Uuid,
+        members: &[Uuid],
+    ) -> Result<Option<(Conversation, Vec<Uuid>)>> {
+        let mut tx = self.sqlx_pool.begin().await?;
+        let Some(conversation) = query_as::<_, Conversation>(
+            "SELECT * FROM conversations WHERE conversation_id = $1 FOR UPDATE",
+        )
+        .bind(conversation_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        let (removed, current): (Vec<Uuid>, Vec<Uuid>) = conversation
+            .member_ids()
+            .into_iter()
+            .partition(|member| members.contains(member));
+
+        let conversation = Self::update_conversation_members(&mut tx, conversation_id, &current).await?;
+        query("DELETE FROM conversation_members WHERE conversation_id = $1 AND user_id = ANY($2::uuid[])")
+            .bind(conversation_id)
+            .bind(&removed)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(Some((conversation, removed)))
+    }
+
+    async fn update_conversation_members(
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        conversation_id: Uuid,
+        members: &[Uuid],
+    ) -> Result<Conversation> {
+        let members_json = json!(
+            members
+                .iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<String>>()
+        );
+        let conversation = query_as::<_, Conversation>(
+            r#"
+            UPDATE conversations SET members = $1, updated_at = NOW()
+            WHERE conversation_id = $2
+            RETURNING *
+            "#,
+        )
+        .bind(&members_json)
+        .bind(conversation_id)
+        .fetch_one(&mut **tx)
+        .await?;
+        Ok(conversation)
+    }
+
+    async fn insert_conversation_members(
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        conversation_id: Uuid,
+        members: &[Uuid],
+        owner_id: Option<Uuid>,
+    ) -> Result<()> {
+        query(
+            r#"
+            INSERT INTO conversation_members (conversation_id, user_id, role)
+            SELECT $1, member_id, CASE WHEN member_id = $3 THEN 'owner' ELSE 'member' END
+            FROM UNNEST($2::uuid[]) AS member_id
+            ON CONFLICT DO NOTHING
+            "#,
+        )
+        .bind(conversation_id)
+        .bind(members)
+        .bind(owner_id)
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_conversation_member(
+        &self,
+        conversation_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<Option<ConversationMember>> {
+        let member = query_as::<_, ConversationMember>(
+            "SELECT * FROM conversation_members WHERE conversation_id = $1 AND user_id = $2",
+        )
+        .bind(conversation_id)
+        .bind(user_id)
+        .fetch_optional(&self.sqlx_pool)
+        .await?;
+        Ok(member)
+    }
+
+    pub async fn list_conversation_members(
+        &self,
+        conversation_id: Uuid,
+    ) -> Result<Vec<ConversationMember>> {
+        let members = query_as::<_, ConversationMember>(
+            "SELECT * FROM conversation_members WHERE conversation_id = $1 ORDER BY joined_at, user_id",
+        )
+        .bind(conversation_id)
+        .fetch_all(&self.sqlx_pool)
+        .await?;
+        Ok(members)
+    }
+
+    // 修改管理员/普通成员角色，群主只能通过转让变更
+    pub async fn set_member_role(
+        &self,
+        conversation_id: Uuid,
+        user_id: Uuid,
+        role: &str,
+    ) -> Result<Option<ConversationMember>> {
+        let member = query_as::<_, ConversationMember>(
+            r#"
+            UPDATE conversation_members SET role = $3
+            WHERE conversation_id = $1 AND user_id = $2 AND role <> 'owner'
+            RETURNING *
+            "#,
+        )
+        .bind(conversation_id)
+        .bind(user_id)
+        .bind(role)
+        .fetch_optional(&self.sqlx_pool)
+        .await?;
+        Ok(member)
+    }
+
+    pub async fn set_member_muted_until(
+        &self,
+        conversation_id: Uuid,
+        user_id: Uuid,
+        muted_until: Option<DateTime<Utc>>,
+    ) -> Result<Option<ConversationMember>> {
+        let member = query_as::<_, ConversationMember>(
+            r#"
+            UPDATE conversation_members SET muted_until = $3
+            WHERE conversation_id = $1 AND user_id = $2
+            RETURNING *
+            "#,
+        )
+        .bind(conversation_id)
+        .bind(user_id)
+        .bind(muted_until)
+        .fetch_optional(&self.sqlx_pool)
+        .await?;
+        Ok(member)
+    }
+
+    // 已读/送达位置只前进不后退，已读位置前进时更新已读时间
+    pub async fn update_member_receipt(
+        &self,
+        conversation_id: Uuid,
+        user_id: Uuid,
+        read_up_to: Option<i64>,
+        delivered_up_to: Option<i64>,
+    ) -> Result<Option<ConversationMember>> {
+        let member = query_as::<_, ConversationMember>(
+            r#"
+            UPDATE conversation_members SET
+                read_at = CASE WHEN $3::BIGINT > COALESCE(read_up_to, -1) THEN NOW() ELSE read_at END,
+                read_up_to = GREATEST(read_up_to, $3),
+                delivered_up_to = GREATEST(delivered_up_to, $4, $3)
+            WHERE conversation_id = $1 AND user_id = $2
+            RETURNING *
+            "#,
+        )
+        .bind(conversation_id)
+        .bind(user_id)
+        .bind(read_up_to)
+        .bind(delivered_up_to)
+        .fetch_optional(&self.sqlx_pool)
+        .await?;
+        Ok(member)
+    }
+
+    // 用户在各个会话中的已读位置
+    pub async fn list_read_positions(&self, user_id: Uuid) -> Result<Vec<(Uuid, Option<i64>)>> {
+        let positions = query_as::<_, (Uuid, Option<i64>)>(
+            "SELECT conversation_id, read_up_to FROM conversation_members WHERE user_id = $1",
+        )
+        .bind(user_id)
+        .fetch_all(&self.sqlx_pool)
+        .await?;
+        Ok(positions)
+    }
+
+    // 转让群主，原群主成为管理员。返回 false 表示 owner_id 不是群主或 member_id 不是成员
+    pub async fn transfer_ownership(
+        &self,
+        conversation_id: Uuid,
+        owner_id: Uuid,
+        member_id: Uuid,
+    ) -> Result<bool> {
+        let mut tx = self.sqlx_pool.begin().await?;
+        let demoted = query(
+            r#"
+            UPDATE conversation_members SET role = 'admin'
+            WHERE conversation_id = $1 AND user_id = $2 AND role = 'owner'
+            "#,
+        )
+        .bind(conversation_id)
+        .bind(owner_id)
+        .execute(&mut *tx)
+        .await?;
+        let promoted = query(
+            r#"
+            UPDATE conversation_members SET role = 'owner', muted_until = NULL
+            WHERE conversation_id = $1 AND user_id = $2 AND user_id <> $3
+            "#,
+        )
+        .bind(conversation_id)
+        .bind(member_id)
+        .bind(owner_id)
+        .execute(&mut *tx)
+        .await?;
+        if demoted.rows_affected() == 0 || promoted.rows_affected() == 0 {
+            return Ok(false);
+        }
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    pub async fn update_conversation_permissions(
+        &self,
+        conversation_id: Uuid,
+        permissions: &ConversationPermissions,
+    ) -> Result<Option<Conversation>> {
+        let conversation = query_as::<_, Conversation>(
+            r#"
+            UPDATE conversations SET permissions = $1, updated_at = NOW()
+            WHERE conversation_id = $2
+            RETURNING *
+            "#,
+        )
+        .bind(json!(permissions))
+        .bind(conversation_id)
+        .fetch_optional(&self.sqlx_pool)
+        .await?;
+        Ok(conversation)
+    }
+
+    // 群主和管理员
+    pub async fn list_conversation_admin_ids(&self, conversation_id: Uuid) -> Result<Vec<Uuid>> {
+        let admin_ids = query_scalar::<_, Uuid>(
+            "SELECT user_id FROM conversation_members WHERE conversation_id = $1 AND role IN ('owner', 'admin')",
+        )
+        .bind(conversation_id)
+        .fetch_all(&self.sqlx_pool)
+        .await?;
+        Ok(admin_ids)
+    }
+
+    pub async fn create_invite(
+        &self,
+        conversation_id: Uuid,
+        created_by: Uuid,
+        code: &str,
+        expires_at: Option<DateTime<Utc>>,
+        max_uses: Option<i32>,
+        requires_approval: bool,
+    ) -> Result<ConversationInvite> {
+        let invite = query_as::<_, ConversationInvite>(
+            r#"
+            INSERT INTO conversation_invites (
+                conversation_id, created_by, code, expires_at, max_uses, requires_approval
+            )
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING *
+            "#,
+        )
+        .bind(conversation_id)
+        .bind(created_by)
+        .bind(code)
+        .bind(expires_at)
+        .bind(max_uses)
+        .bind(requires_approval)
+        .fetch_one(&self.sqlx_pool)
+        .await?;
+        Ok(invite)
+    }
+
+    pub async fn list_invites(&self, conversation_id: Uuid) -> Result<Vec<ConversationInvite>> {
+        let invites = query_as::<_, ConversationInvite>(
+            "SELECT * FROM conversation_invites WHERE conversation_id = $1 ORDER BY created_at DESC",
+        )
+        .bind(conversation_id)
+        .fetch_all(&self.sqlx_pool)
+        .await?;
+        Ok(invites)
+    }
+
+    pub async fn get_invite(&self, invite_id: Uuid) -> Result<Option<ConversationInvite>> {
+        let invite = query_as::<_, ConversationInvite>(
+            "SELECT * FROM conversation_invites WHERE invite_id = $1",
+        )
+        .bind(invite_id)
+        .fetch_optional(&self.sqlx_pool)
+        .await?;
+        Ok(invite)
+    }
+
+    pub async fn revoke_invite(&self, invite_id: Uuid) -> Result<Option<ConversationInvite>> {
+        let invite = query_as::<_, ConversationInvite>(
+            r#"
+            UPDATE conversation_invites SET revoked_at = COALESCE(revoked_at, NOW())
+            WHERE invite_id = $1
+            RETURNING *
+            "#,
+        )
+        .bind(invite_id)
+        .fetch_optional(&self.sqlx_pool)
+        .await?;
+        Ok(invite)
+    }
+
+    // 使用邀请码入群，需要审批时创建入群申请。加入或新建申请都会消耗一次使用次数
+    pub async fn redeem_invite(
+        &self,
+        code: &str,
+        user_id: Uuid,
+        message: Option<&str>,
+    ) -> Result<Redemption> {
+        let mut tx = self.sqlx_pool.begin().await?;
+        let Some(invite) = query_as::<_, ConversationInvite>(
+            "SELECT * FROM conversation_invites WHERE code = $1 FOR UPDATE",
+        )
+        .bind(code)
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            return Ok(Redemption::Invalid);
+        };
+        if !invites::is_usable(&invite, Utc::now()) {
+            return Ok(Redemption::Invalid);
+        }
+
+        let is_member: bool = query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM conversation_members WHERE conversation_id = $1 AND user_id = $2)",
+        )
+        .bind(invite.conversation_id)
+        .bind(user_id)
+        .fetch_one(&mut *tx)
+        .await?;
+        if is_member {
+            return Ok(Redemption::AlreadyMember(invite.conversation_id));
+        }
+
+        let redemption = if invite.requires_approval {
+            let pending = query_as::<_, JoinRequest>(
+                r#"
+                SELECT * FROM conversation_join_requests
+                WHERE conversation_id = $1 AND user_id = $2 AND status = 'pending'
+                "#,
+            )
+            .bind(invite.conversation_id)
+            .bind(user_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+            if let Some(request) = pending {
+                return Ok(Redemption::Pending { request, created: false });
+            }
+
+            let request = query_as::<_, JoinRequest>(
+                r#"
+                INSERT INTO conversation_join_requests (conversation_id, user_id, invite_id, message)
+                VALUES ($1, $2, $3, $4)
+                RETURNING *
+                "#,
+            )
+            .bind(invite.conversation_id)
+            .bind(user_id)
+            .bind(invite.invite_id)
+            .bind(message)
+            .fetch_one(&mut *tx)
+            .await?;
+            Redemption::Pending { request, created: true }
+        } else {
+            let Some((conversation, _)) =
+                Self::add_members_in_tx(&mut tx, invite.conversation_id, &[user_id]).await?
+            else {
+                return Ok(Redemption::Invalid);
+            };
+            Redemption::Joined(conversation)
+        };
+
+        query("UPDATE conversation_invites SET use_count = use_count + 1 WHERE invite_id = $1")
+            .bind(invite.invite_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(redemption)
+    }
+
+    pub async fn list_join_requests(&self, conversation_id: Uuid) -> Result<Vec<JoinRequest>> {
+        let requests = query_as::<_, JoinRequest>(
+            r#"
+            SELECT * FROM conversation_join_requests
+            WHERE conversation_id = $1 AND status = 'pending'
+            ORDER BY created_at
+            "#,
+        )
+        .bind(conversation_id)
+        .fetch_all(&self.sqlx_pool)
+        .await?;
+        Ok(requests)
+    }
+
+    pub async fn get_join_request(&self, request_id: Uuid) -> Result<Option<JoinRequest>> {
+        let request = query_as::<_, JoinRequest>(
+            "SELECT * FROM conversation_join_requests WHERE request_id = $1",
+        )
+        .bind(request_id)
+        .fetch_optional(&self.sqlx_pool)
+        .await?;
+        Ok(request)
+    }
+
+    // 审批入群申请，通过时加入成员。返回 None 表示申请不存在或已审批
+    pub async fn review_join_request(
+        &self,
+        request_id: Uuid,
+        reviewer_id: Uuid,
+        approve: bool,
+    ) -> Result<Option<(JoinRequest, Option<Conversation>)>> {
+        let mut tx = self.sqlx_pool.begin().await?;
+        let Some(request) = query_as::<_, JoinRequest>(
+            r#"
+            UPDATE conversation_join_requests SET status = $3, reviewed_by = $2, updated_at = NOW()
+            WHERE request_id = $1 AND status = 'pending'
+            RETURNING *
+            "#,
+        )
+        .bind(request_id)
+        .bind(reviewer_id)
+        .bind(if approve { "approved" } else { "rejected" })
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        let mut conversation = None;
+        if approve {
+            conversation = Self::add_members_in_tx(&mut tx, request.conversation_id, &[request.user_id])
+                .await?
+                .map(|(conversation, _)| conversation);
+        }
+        tx.commit().await?;
+        Ok(Some((request, conversation)))
+    }
+
+    // 会话名称保存在 meta.name
+    // 群资料保存在 meta 中，整体替换
+    pub async fn update_conversation_meta(
+        &self,
+        conversation_id: Uuid,
+        meta: &serde_json::Value,
+    ) -> Result<Option<Conversation>> {
+        let conversation = query_as::<_, Conversation>(
+            r#"
+            UPDATE conversations SET meta = $1, updated_at = NOW()
+            WHERE conversation_id = $2
+            RETURNING *
+            "#,
+        )
+        .bind(meta)
+        .bind(conversation_id)
+        .fetch_optional(&self.sqlx_pool)
+        .await?;
+        Ok(conversation)
+    }
+
+    // 删除会话并归档对应的消息流
+    pub async fn delete_conversation(&self, conversation_id: Uuid) -> Result<Option<Conversation>> {
+        let mut tx = self.sqlx_pool.begin().await?;
+        let Some(conversation) = query_as::<_, Conversation>(
+            "DELETE FROM conversations WHERE conversation_id = $1 RETURNING *",
+        )
+        .bind(conversation_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        query("UPDATE streams SET status = 'archived', updated_at = NOW() WHERE stream_id = $1")
+            .bind(conversation.stream_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(Some(conversation))
+    }
+
+    // 所有会话的消息流和已索引到的位置
+    pub async fn list_search_streams(&self) -> Result<Vec<(Uuid, i64, i64)>> {
+        let streams = query_as::<_, (Uuid, i64, i64)>(
+            r#"
+            SELECT c.conversation_id, c.stream_id, COALESCE(o."offset", 0)
+            FROM conversations c
+            LEFT JOIN message_search_offsets o ON o.stream_id = c.stream_id
+            "#,
+        )
+        .fetch_all(&self.sqlx_pool)
+        .await?;
+        Ok(streams)
+    }
+
+    // 在同一个事务中修改索引并记录新的位置，重复索引同一段数据不会产生重复结果
+    pub async fn apply_search_changes(
+        &self,
+        stream_id: i64,
+        changes: &[IndexChange],
+        offset: i64,
+    ) -> Result<()> {
+        let mut tx = self.sqlx_pool.begin().await?;
+        for change in changes {
+            match change {
+                IndexChange::Insert(result) => {
+                    query(
+                        r#"
+                        INSERT INTO message_search (conversation_id, message_id, user_id, message_type, content, timestamp)
+                        VALUES ($1, $2, $3, $4, $5, $6)
+                        ON CONFLICT (conversation_id, message_id) DO NOTHING
+                        "#,
+                    )
+                    .bind(result.conversation_id)
+                    .bind(result.message_id)
+                    .bind(result.user_id)
+                    .bind(&result.type_)
+                    .bind(&result.content)
+                    .bind(result.timestamp)
+                    .execute(&mut *tx)
+                    .await?;
+                }
+                IndexChange::Edit {
+                    conversation_id,
+                    message_id,
+                    content,
+                } => {
+                    query("UPDATE message_search SET content = $3 WHERE conversation_id = $1 AND message_id = $2")
+                        .bind(conversation_id)
+                        .bind(message_id)
+                        .bind(content)
+                        .execute(&mut *tx)
+                        .await?;
+                }
+                IndexChange::Delete {
+                    conversation_id,
+                    message_id,
+                } => {
+                    query("DELETE FROM message_search WHERE conversation_id = $1 AND message_id = $2")
+                        .bind(conversation_id)
+                        .bind(message_id)
+                        .execute(&mut *tx)
+                        .await?;
+                }
+            }
+        }
+        query(
+            r#"
+            INSERT INTO message_search_offsets (stream_id, "offset") VALUES ($1, $2)
+            ON CONFLICT (stream_id) DO UPDATE SET "offset" = $2, updated_at = NOW()
+            "#,
+        )
+        .bind(stream_id)
+        .bind(offset)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    // 只搜索 user_id 所在的会话，按时间从新到旧
+    pub async fn search_messages(
+        &self,
+        user_id: Uuid,
+        search: &SearchQuery,
+        conversation_id: Option<Uuid>,
+        before: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> Result<Vec<SearchResult>> {
+        let results = query_as::<_, SearchResult>(
+            r#"
+            SELECT s.conversation_id, s.message_id, s.user_id, s.message_type, s.content, s.timestamp
+            FROM message_search s
+            JOIN conversation_members m ON m.conversation_id = s.conversation_id AND m.user_id = $1
+            WHERE (s.content_tsv @@ plainto_tsquery('simple', $2) OR s.content ILIKE $3)
+                AND ($4::UUID IS NULL OR s.conversation_id = $4)
+                AND ($5::TIMESTAMPTZ IS NULL OR s.timestamp < $5)
+            ORDER BY s.timestamp DESC, s.message_id DESC
+            LIMIT $6
+            "#,
+        )
+        .bind(user_id)
+        .bind(&search.text)
+        .bind(&search.pattern)
+        .bind(conversation_id)
+        .bind(before)
+        .bind(limit)
+        .fetch_all(&self.sqlx_pool)
+        .await?;
+        Ok(results)
+    }
+
+    // 置顶消息，已置顶或达到上限时返回 None
+    pub async fn pin_message(
+        &self,
+        conversation_id: Uuid,
+        message_id: i64,
+        user_id: Uuid,
+        max_pins: i64,
+    ) -> Result<Option<PinnedMessage>> {
+        let pin = query_as::<_, PinnedMessage>(
+            r#"
+            INSERT INTO conversation_pins (conversation_id, message_id, pinned_by)
+            SELECT $1, $2, $3
+            WHERE (SELECT COUNT(*) FROM conversation_pins WHERE conversation_id = $1) < $4
+            ON CONFLICT (conversation_id, message_id) DO NOTHING
+            RETURNING *
+            "#,
+        )
+        .bind(conversation_id)
+        .bind(message_id)
+        .bind(user_id)
+        .bind(max_pins)
+        .fetch_optional(&self.sqlx_pool)
+        .await?;
+        Ok(pin)
+    }
+
+    // 取消置顶，返回是否置顶过
+    pub async fn unpin_message(&self, conversation_id: Uuid, message_id: i64) -> Result<bool> {
+        let result =
+            query("DELETE FROM conversation_pins WHERE conversation_id = $1 AND message_id = $2")
+                .bind(conversation_id)
+                .bind(message_id)
+                .execute(&self.sqlx_pool)
+                .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn list_pinned_messages(&self, conversation_id: Uuid) -> Result<Vec<PinnedMessage>> {
+        let pins = query_as::<_, PinnedMessage>(
+            "SELECT * FROM conversation_pins WHERE conversation_id = $1 ORDER BY pinned_at DESC, message_id DESC",
+        )
+        .bind(conversation_id)
+        .fetch_all(&self.sqlx_pool)
+        .await?;
+        Ok(pins)
+    }
+
+    // 收藏消息，已收藏或达到上限时返回 None
+    pub async fn star_message(
+        &self,
+        user_id: Uuid,
+        conversation_id: Uuid,
+        message_id: i64,
+        max_stars: i64,
+    ) -> Result<Option<StarredMessage>> {
+        let star = query_as::<_, StarredMessage>(
+            r#"
+            INSERT INTO starred_messages (user_id, conversation_id, message_id)
+            SELECT $1, $2, $3
+            WHERE (SELECT COUNT(*) FROM starred_messages WHERE user_id = $1) < $4
+            ON CONFLICT (user_id, conversation_id, message_id) DO NOTHING
+            RETURNING conversation_id, message_id, starred_at
+            "#,
+        )
+        .bind(user_id)
+        .bind(conversation_id)
+        .bind(message_id)
+        .bind(max_stars)
+        .fetch_optional(&self.sqlx_pool)
+        .await?;
+        Ok(star)
+    }
+
+    // 取消收藏，返回是否收藏过
+    pub async fn unstar_message(
+        &self,
+        user_id: Uuid,
+        conversation_id: Uuid,
+        message_id: i64,
+    ) -> Result<bool> {
+        let result = query(
+            "DELETE FROM starred_messages WHERE user_id = $1 AND conversation_id = $2 AND message_id = $3",
+        )
+        .bind(user_id)
+        .bind(conversation_id)
+        .bind(message_id)
+        .execute(&self.sqlx_pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn is_message_starred(
+        &self,
+        user_id: Uuid,
+        conversation_id: Uuid,
+        message_id: i64,
+    ) -> Result<bool> {
+        let starred = query_scalar::<_, bool>(
+            "SELECT EXISTS (SELECT 1 FROM starred_messages WHERE user_id = $1 AND conversation_id = $2 AND message_id = $3)",
+        )
+        .bind(user_id)
+        .bind(conversation_id)
+        .bind(message_id)
+        .fetch_one(&self.sqlx_pool)
+        .await?;
+        Ok(starred)
+    }
+
+    // 只返回用户仍在其中的会话的收藏
+    pub async fn list_starred_messages(
+        &self,
+        user_id: Uuid,
+        conversation_id: Option<Uuid>,
+    ) -> Result<Vec<StarredMessage>> {
+        let messages = query_as::<_, StarredMessage>(
+            r#"
+            SELECT s.conversation_id, s.message_id, s.starred_at
+            FROM starred_messages s
+            JOIN conversation_members m ON m.conversation_id = s.conversation_id AND m.user_id = s.user_id
+            WHERE s.user_id = $1 AND ($2::UUID IS NULL OR s.conversation_id = $2)
+            ORDER BY s.starred_at DESC, s.message_id DESC
+            "#,
+        )
+        .bind(user_id)
+        .bind(conversation_id)
+        .fetch_all(&self.sqlx_pool)
+        .await?;
+        Ok(messages)
+    }
 }
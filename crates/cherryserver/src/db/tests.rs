@@ -223,11 +223,20 @@ mod tests {
         let repo = Repo::with_pool(pool.clone());
         
         // Test with correct password
-        let result = repo.check_password(&test_user.username, "test_password_hash").await?;
+        let result = repo.check_password(&test_user.email, "test_password_hash").await?;
         assert!(result);
+
+        // The plaintext password is replaced by its hash after a successful check
+        let user = repo.user_get_by_email(&test_user.email).await?;
+        assert!(user.password_hash.starts_with("$argon2"));
+        assert!(repo.check_password(&test_user.email, "test_password_hash").await?);
         
         // Test with incorrect password
-        let result = repo.check_password(&test_user.username, "wrong_password").await?;
+        let result = repo.check_password(&test_user.email, "wrong_password").await?;
+        assert!(!result);
+
+        // Unknown email
+        let result = repo.check_password("nobody@example.com", "test_password_hash").await?;
         assert!(!result);
         
         Ok(())
@@ -245,8 +254,10 @@ mod tests {
         // Create a repo with the test pool
         let repo = Repo::with_pool(pool.clone());
         
-        // Test the list_contacts method
-        let contacts = repo.list_contacts(owner.user_id).await?;
+        // Test the query_contacts method without filters
+        let contacts = repo
+            .query_contacts(owner.user_id, &cherrycore::types::ListContactsRequest::default())
+            .await?;
         
         assert_eq!(contacts.len(), 1);
         assert_eq!(contacts[0].owner_id, owner.user_id);
@@ -468,4 +479,467 @@ mod tests {
         Ok(())
     }
 
-}
\ No newline at end of file
+    #[tokio::test]
+    async fn test_create_user() -> Result<()> {
+        let pool = setup_test_db().await;
+        let repo = Repo::with_pool(pool.clone());
+
+        let (user, stream) = repo
+            .create_user("new_user", "new_user@example.com", "$argon2id$test")
+            .await?;
+        assert_eq!(stream.owner_id, user.user_id);
+        assert_eq!(stream.stream_type, "notification");
+        assert_eq!(
+            repo.get_notification_stream_ids(&[user.user_id]).await?,
+            vec![stream.stream_id]
+        );
+
+        // 重复的邮箱违反唯一约束
+        let err = repo
+            .create_user("other_user", "new_user@example.com", "$argon2id$test")
+            .await
+            .unwrap_err();
+        let db_err = err.downcast_ref::<sqlx::Error>().and_then(|e| e.as_database_error());
+        assert!(db_err.is_some_and(|e| e.is_unique_violation()));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_update_contact_relation() -> Result<()> {
+        use crate::contacts::{ContactAction, Relation};
+
+        let pool = setup_test_db().await;
+        let repo = Repo::with_pool(pool.clone());
+        let (alice, _) = repo.create_user("contact_a", "contact_a@example.com", "").await?;
+        let (bob, _) = repo.create_user("contact_b", "contact_b@example.com", "").await?;
+
+        let change = repo
+            .update_contact_relation(alice.user_id, bob.user_id, ContactAction::Request)
+            .await?
+            .unwrap();
+        assert_eq!(change.mine, Some(Relation::PendingOutgoing));
+        assert_eq!(change.theirs, Some(Relation::PendingIncoming));
+
+        // 只有收到请求的一方可以接受
+        assert!(
+            repo.update_contact_relation(alice.user_id, bob.user_id, ContactAction::Accept)
+                .await?
+                .is_none()
+        );
+        repo.update_contact_relation(bob.user_id, alice.user_id, ContactAction::Accept)
+            .await?
+            .unwrap();
+        let contacts = repo
+            .query_contacts(alice.user_id, &cherrycore::types::ListContactsRequest::default())
+            .await?;
+        assert_eq!(contacts.len(), 1);
+        assert_eq!(contacts[0].relation_type, "friend");
+
+        // 拉黑后对方的好友记录被删除
+        repo.update_contact_relation(bob.user_id, alice.user_id, ContactAction::Block)
+            .await?
+            .unwrap();
+        assert!(
+            repo.query_contacts(alice.user_id, &cherrycore::types::ListContactsRequest::default())
+                .await?
+                .is_empty()
+        );
+        assert!(
+            repo.update_contact_relation(alice.user_id, bob.user_id, ContactAction::Request)
+                .await?
+                .is_none()
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_update_contact() -> Result<()> {
+        use crate::contacts::ContactAction;
+        use cherrycore::types::{ListContactsRequest, UpdateContactRequest};
+
+        let pool = setup_test_db().await;
+        let repo = Repo::with_pool(pool.clone());
+        let (alice, _) = repo.create_user("update_a", "update_a@example.com", "").await?;
+        let (bob, _) = repo.create_user("update_b", "update_b@example.com", "").await?;
+        repo.update_contact_relation(alice.user_id, bob.user_id, ContactAction::Request)
+            .await?
+            .unwrap();
+
+        let update = UpdateContactRequest {
+            remark_name: Some(Some("Bobby".to_string())),
+            tags: Some(vec!["work".to_string()]),
+            is_favorite: Some(true),
+            custom_fields: serde_json::from_value(json!({"team": "infra", "floor": 3}))?,
+            ..Default::default()
+        };
+        let contact = repo.update_contact(alice.user_id, bob.user_id, &update).await?.unwrap();
+        assert_eq!(contact.remark_name.as_deref(), Some("Bobby"));
+        assert_eq!(contact.custom_fields, json!({"team": "infra", "floor": 3}));
+
+        // 未给出的字段保持不变，null 删除自定义字段
+        let update = UpdateContactRequest {
+            remark_name: Some(None),
+            custom_fields: serde_json::from_value(json!({"floor": null}))?,
+            ..Default::default()
+        };
+        let contact = repo.update_contact(alice.user_id, bob.user_id, &update).await?.unwrap();
+        assert_eq!(contact.remark_name, None);
+        assert!(contact.is_favorite);
+        assert_eq!(contact.custom_fields, json!({"team": "infra"}));
+
+        let filter = ListContactsRequest {
+            tag: Some("work".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(repo.query_contacts(alice.user_id, &filter).await?.len(), 1);
+        let filter = ListContactsRequest {
+            tag: Some("family".to_string()),
+            ..Default::default()
+        };
+        assert!(repo.query_contacts(alice.user_id, &filter).await?.is_empty());
+
+        assert!(
+            repo.update_contact(alice.user_id, Uuid::new_v4(), &UpdateContactRequest::default())
+                .await?
+                .is_none()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_conversation_roles() -> Result<()> {
+        use cherrycore::types::ConversationPermissions;
+
+        let pool = setup_test_db().await;
+        let repo = Repo::with_pool(pool.clone());
+        let (alice, _) = repo.create_user("roles_a", "roles_a@example.com", "").await?;
+        let (bob, _) = repo.create_user("roles_b", "roles_b@example.com", "").await?;
+        let (carol, _) = repo.create_user("roles_c", "roles_c@example.com", "").await?;
+
+        let members = vec![alice.user_id, bob.user_id];
+        let (conversation, _, _) = repo
+            .create_conversation_with_stream(alice.user_id, "group", &members, &json!({}))
+            .await?;
+        let conversation_id = conversation.conversation_id;
+        let roles = repo.list_conversation_members(conversation_id).await?;
+        assert_eq!(roles.len(), 2);
+        let role_of = |user_id: Uuid| {
+            roles.iter().find(|m| m.user_id == user_id).map(|m| m.role.clone())
+        };
+        assert_eq!(role_of(alice.user_id).as_deref(), Some("owner"));
+        assert_eq!(role_of(bob.user_id).as_deref(), Some("member"));
+        assert!(repo.check_acl(bob.user_id, conversation.stream_id).await?);
+
+        // 新成员是普通成员，移除后不能再访问
+        repo.add_conversation_members(conversation_id, &[carol.user_id]).await?.unwrap();
+        let member = repo.get_conversation_member(conversation_id, carol.user_id).await?.unwrap();
+        assert_eq!(member.role, "member");
+        repo.remove_conversation_members(conversation_id, &[carol.user_id]).await?.unwrap();
+        assert!(!repo.check_acl(carol.user_id, conversation.stream_id).await?);
+
+        // 群主不能通过修改角色变更
+        let member = repo.set_member_role(conversation_id, bob.user_id, "admin").await?.unwrap();
+        assert_eq!(member.role, "admin");
+        assert!(repo.set_member_role(conversation_id, alice.user_id, "member").await?.is_none());
+
+        assert!(!repo.transfer_ownership(conversation_id, bob.user_id, alice.user_id).await?);
+        assert!(repo.transfer_ownership(conversation_id, alice.user_id, bob.user_id).await?);
+        let member = repo.get_conversation_member(conversation_id, alice.user_id).await?.unwrap();
+        assert_eq!(member.role, "admin");
+        let member = repo.get_conversation_member(conversation_id, bob.user_id).await?.unwrap();
+        assert_eq!(member.role, "owner");
+
+        let muted_until = Utc::now() + chrono::Duration::minutes(10);
+        let member = repo
+            .set_member_muted_until(conversation_id, alice.user_id, Some(muted_until))
+            .await?
+            .unwrap();
+        assert!(member.muted_until.is_some());
+
+        let permissions = ConversationPermissions {
+            post: "admins".to_string(),
+            ..Default::default()
+        };
+        let conversation = repo
+            .update_conversation_permissions(conversation_id, &permissions)
+            .await?
+            .unwrap();
+        assert_eq!(conversation.permissions(), permissions);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_conversation_invites() -> Result<()> {
+        use crate::invites::Redemption;
+
+        let pool = setup_test_db().await;
+        let repo = Repo::with_pool(pool.clone());
+        let (alice, _) = repo.create_user("invite_a", "invite_a@example.com", "").await?;
+        let (bob, _) = repo.create_user("invite_b", "invite_b@example.com", "").await?;
+        let (carol, _) = repo.create_user("invite_c", "invite_c@example.com", "").await?;
+        let (dave, _) = repo.create_user("invite_d", "invite_d@example.com", "").await?;
+
+        let (conversation, _, _) = repo
+            .create_conversation_with_stream(alice.user_id, "group", &[alice.user_id], &json!({}))
+            .await?;
+        let conversation_id = conversation.conversation_id;
+
+        // 只能使用一次的邀请
+        let invite = repo
+            .create_invite(conversation_id, alice.user_id, "invite-once", None, Some(1), false)
+            .await?;
+        assert!(matches!(
+            repo.redeem_invite(&invite.code, bob.user_id, None).await?,
+            Redemption::Joined(_)
+        ));
+        assert!(repo.check_acl(bob.user_id, conversation.stream_id).await?);
+        assert!(matches!(
+            repo.redeem_invite(&invite.code, bob.user_id, None).await?,
+            Redemption::Invalid
+        ));
+        assert!(matches!(
+            repo.redeem_invite("no-such-code", carol.user_id, None).await?,
+            Redemption::Invalid
+        ));
+
+        // 需要审批的邀请，重复申请返回同一个申请
+        let invite = repo
+            .create_invite(conversation_id, alice.user_id, "invite-approval", None, None, true)
+            .await?;
+        let Redemption::Pending { request, created } =
+            repo.redeem_invite(&invite.code, carol.user_id, Some("hi")).await?
+        else {
+            panic!("expected a join request");
+        };
+        assert!(created);
+        assert!(!repo.check_acl(carol.user_id, conversation.stream_id).await?);
+        let Redemption::Pending { request: again, created } =
+            repo.redeem_invite(&invite.code, carol.user_id, None).await?
+        else {
+            panic!("expected a join request");
+        };
+        assert!(!created);
+        assert_eq!(again.request_id, request.request_id);
+        assert_eq!(repo.list_join_requests(conversation_id).await?.len(), 1);
+
+        let (request, joined) = repo
+            .review_join_request(request.request_id, alice.user_id, true)
+            .await?
+            .unwrap();
+        assert_eq!(request.status, "approved");
+        assert!(joined.is_some());
+        assert!(repo.check_acl(carol.user_id, conversation.stream_id).await?);
+        assert!(repo.review_join_request(request.request_id, alice.user_id, false).await?.is_none());
+
+        // 撤销后不能再使用
+        repo.revoke_invite(invite.invite_id).await?.unwrap();
+        assert!(matches!(
+            repo.redeem_invite(&invite.code, dave.user_id, None).await?,
+            Redemption::Invalid
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_member_receipts() -> Result<()> {
+        let pool = setup_test_db().await;
+        let repo = Repo::with_pool(pool.clone());
+        let (alice, _) = repo.create_user("receipts_a", "receipts_a@example.com", "").await?;
+        let (bob, _) = repo.create_user("receipts_b", "receipts_b@example.com", "").await?;
+
+        let members = vec![alice.user_id, bob.user_id];
+        let (conversation, _, _) = repo
+            .create_conversation_with_stream(alice.user_id, "direct", &members, &json!({}))
+            .await?;
+        let conversation_id = conversation.conversation_id;
+
+        let member = repo
+            .update_member_receipt(conversation_id, bob.user_id, None, Some(100))
+            .await?
+            .unwrap();
+        assert_eq!(member.read_up_to, None);
+        assert_eq!(member.delivered_up_to, Some(100));
+        assert!(member.read_at.is_none());
+
+        // 已读位置前进时送达位置跟着前进，不会后退
+        let member = repo
+            .update_member_receipt(conversation_id, bob.user_id, Some(200), None)
+            .await?
+            .unwrap();
+        assert_eq!(member.read_up_to, Some(200));
+        assert_eq!(member.delivered_up_to, Some(200));
+        assert!(member.read_at.is_some());
+        let member = repo
+            .update_member_receipt(conversation_id, bob.user_id, Some(50), Some(50))
+            .await?
+            .unwrap();
+        assert_eq!(member.read_up_to, Some(200));
+        assert_eq!(member.delivered_up_to, Some(200));
+
+        let positions = repo.list_read_positions(bob.user_id).await?;
+        assert!(positions.contains(&(conversation_id, Some(200))));
+        let positions = repo.list_read_positions(alice.user_id).await?;
+        assert!(positions.contains(&(conversation_id, None)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_user_presence() -> Result<()> {
+        use crate::contacts::ContactAction;
+
+        let pool = setup_test_db().await;
+        let repo = Repo::with_pool(pool.clone());
+        let (alice, _) = repo.create_user("presence_a", "presence_a@example.com", "").await?;
+        let (bob, _) = repo.create_user("presence_b", "presence_b@example.com", "").await?;
+        assert_eq!(alice.status, "offline");
+
+        repo.update_user_presence(alice.user_id, "away").await?;
+        let user = repo.user_get_by_id(alice.user_id).await?;
+        assert_eq!(user.status, "away");
+        assert!(user.last_active >= alice.last_active);
+
+        // 只有好友能收到在线状态
+        assert!(repo.list_presence_audience(alice.user_id).await?.is_empty());
+        repo.update_contact_relation(alice.user_id, bob.user_id, ContactAction::Request)
+            .await?
+            .unwrap();
+        assert!(repo.list_presence_audience(alice.user_id).await?.is_empty());
+        repo.update_contact_relation(bob.user_id, alice.user_id, ContactAction::Accept)
+            .await?
+            .unwrap();
+        assert_eq!(repo.list_presence_audience(alice.user_id).await?, vec![bob.user_id]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_message_search() -> Result<()> {
+        use crate::search::{IndexChange, SearchQuery};
+
+        let pool = setup_test_db().await;
+        let repo = Repo::with_pool(pool.clone());
+        let user1 = create_test_user(&pool).await;
+        let user2 = create_test_user(&pool).await;
+        let outsider = create_test_user(&pool).await;
+        let members = vec![user1.user_id, user2.user_id];
+        let (conversation, stream, _) = repo
+            .create_conversation_with_stream(user1.user_id, "group", &members, &json!({}))
+            .await?;
+        let conversation_id = conversation.conversation_id;
+
+        let result = |message_id: i64, content: &str| SearchResult {
+            conversation_id,
+            message_id,
+            user_id: user1.user_id,
+            type_: "text".to_string(),
+            content: content.to_string(),
+            timestamp: Utc::now() + chrono::Duration::seconds(message_id),
+        };
+        let changes = vec![
+            IndexChange::Insert(result(1, "hello cherry")),
+            IndexChange::Insert(result(2, "你好樱桃")),
+            IndexChange::Insert(result(3, "100% done")),
+        ];
+        repo.apply_search_changes(stream.stream_id, &changes, 100).await?;
+        // 重复索引不会产生重复结果
+        repo.apply_search_changes(stream.stream_id, &changes[..1], 100).await?;
+
+        let streams = repo.list_search_streams().await?;
+        assert!(streams.contains(&(conversation_id, stream.stream_id, 100)));
+
+        let search = |q: &str| SearchQuery::parse(q).ok().unwrap();
+        let results = repo
+            .search_messages(user2.user_id, &search("cherry"), None, None, 20)
+            .await?;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].message_id, 1);
+        // 中文按子串匹配
+        let results = repo
+            .search_messages(user2.user_id, &search("樱桃"), Some(conversation_id), None, 20)
+            .await?;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].message_id, 2);
+        // % 不是通配符
+        let results = repo
+            .search_messages(user2.user_id, &search("%"), None, None, 20)
+            .await?;
+        assert_eq!(results.iter().map(|r| r.message_id).collect::<Vec<_>>(), vec![3]);
+        // 非成员搜索不到
+        assert!(repo
+            .search_messages(outsider.user_id, &search("cherry"), None, None, 20)
+            .await?
+            .is_empty());
+
+        let changes = vec![
+            IndexChange::Edit {
+                conversation_id,
+                message_id: 1,
+                content: "goodbye".to_string(),
+            },
+            IndexChange::Delete {
+                conversation_id,
+                message_id: 2,
+            },
+        ];
+        repo.apply_search_changes(stream.stream_id, &changes, 200).await?;
+        assert!(repo
+            .search_messages(user2.user_id, &search("cherry"), None, None, 20)
+            .await?
+            .is_empty());
+        assert_eq!(
+            repo.search_messages(user2.user_id, &search("goodbye"), None, None, 20)
+                .await?
+                .len(),
+            1
+        );
+        assert!(repo
+            .search_messages(user2.user_id, &search("樱桃"), None, None, 20)
+            .await?
+            .is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pins_and_stars() -> Result<()> {
+        let pool = setup_test_db().await;
+        let repo = Repo::with_pool(pool.clone());
+        let user1 = create_test_user(&pool).await;
+        let user2 = create_test_user(&pool).await;
+        let members = vec![user1.user_id, user2.user_id];
+        let (conversation, _, _) = repo
+            .create_conversation_with_stream(user1.user_id, "group", &members, &json!({}))
+            .await?;
+        let conversation_id = conversation.conversation_id;
+
+        let meta = json!({ "name": "cherry", "topic": "hello" });
+        let conversation = repo.update_conversation_meta(conversation_id, &meta).await?.unwrap();
+        assert_eq!(conversation.meta, meta);
+
+        // 重复置顶和超过上限都返回 None
+        assert!(repo.pin_message(conversation_id, 10, user1.user_id, 2).await?.is_some());
+        assert!(repo.pin_message(conversation_id, 10, user2.user_id, 2).await?.is_none());
+        assert!(repo.pin_message(conversation_id, 20, user2.user_id, 2).await?.is_some());
+        assert!(repo.pin_message(conversation_id, 30, user2.user_id, 2).await?.is_none());
+        let pins = repo.list_pinned_messages(conversation_id).await?;
+        assert_eq!(pins.len(), 2);
+        assert_eq!(pins[1].pinned_by, Some(user1.user_id));
+        assert!(repo.unpin_message(conversation_id, 10).await?);
+        assert!(!repo.unpin_message(conversation_id, 10).await?);
+        assert_eq!(repo.list_pinned_messages(conversation_id).await?.len(), 1);
+
+        // 收藏只属于自己
+        assert!(repo.star_message(user1.user_id, conversation_id, 10, 1).await?.is_some());
+        assert!(repo.star_message(user1.user_id, conversation_id, 10, 1).await?.is_none());
+        assert!(repo.is_message_starred(user1.user_id, conversation_id, 10).await?);
+        assert!(repo.star_message(user1.user_id, conversation_id, 20, 1).await?.is_none());
+        assert!(!repo.is_message_starred(user1.user_id, conversation_id, 20).await?);
+        let starred = repo.list_starred_messages(user1.user_id, Some(conversation_id)).await?;
+        assert_eq!(starred.len(), 1);
+        assert_eq!(starred[0].message_id, 10);
+        assert!(repo.list_starred_messages(user2.user_id, None).await?.is_empty());
+        assert!(repo.unstar_message(user1.user_id, conversation_id, 10).await?);
+        assert!(repo.list_starred_messages(user1.user_id, None).await?.is_empty());
+        Ok(())
+    }
+}
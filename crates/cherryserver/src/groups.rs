@@ -0,0 +1,257 @@
+use chrono::{DateTime, Utc};
+use cherrycore::types::{
+    ConversationPermissions, UpdateConversationMetaRequest, UpdatePermissionsRequest,
+};
+use serde_json::Value;
+
+const MAX_CONVERSATION_NAME_LEN: usize = 100;
+const MAX_TOPIC_LEN: usize = 500;
+const MAX_AVATAR_URL_LEN: usize = 1024;
+
+// 群成员角色，owner 只有一个
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Role {
+    Member,
+    Admin,
+    Owner,
+}
+
+impl Role {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Role::Member => "member",
+            Role::Admin => "admin",
+            Role::Owner => "owner",
+        }
+    }
+
+    pub(crate) fn parse(role: &str) -> Option<Self> {
+        match role {
+            "member" => Some(Role::Member),
+            "admin" => Some(Role::Admin),
+            "owner" => Some(Role::Owner),
+            _ => None,
+        }
+    }
+}
+
+// 权限设置：all 表示所有成员，admins 表示群主和管理员
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Permission {
+    All,
+    Admins,
+}
+
+impl Permission {
+    pub(crate) fn parse(permission: &str) -> Option<Self> {
+        match permission {
+            "all" => Some(Permission::All),
+            "admins" => Some(Permission::Admins),
+            _ => None,
+        }
+    }
+
+    // 无法识别的设置按 admins 处理
+    fn allows(permission: &str, role: Role) -> bool {
+        match Permission::parse(permission) {
+            Some(Permission::All) => true,
+            _ => role >= Role::Admin,
+        }
+    }
+}
+
+pub(crate) fn can_post(
+    role: Role,
+    permissions: &ConversationPermissions,
+    muted_until: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> bool {
+    if muted_until.is_some_and(|muted_until| muted_until > now) {
+        return false;
+    }
+    Permission::allows(&permissions.post, role)
+}
+
+pub(crate) fn can_invite(role: Role, permissions: &ConversationPermissions) -> bool {
+    Permission::allows(&permissions.invite, role)
+}
+
+pub(crate) fn can_edit_info(role: Role, permissions: &ConversationPermissions) -> bool {
+    Permission::allows(&permissions.edit_info, role)
+}
+
+pub(crate) fn can_pin(role: Role, permissions: &ConversationPermissions) -> bool {
+    Permission::allows(&permissions.pin, role)
+}
+
+// 只能管理角色比自己低的成员
+pub(crate) fn can_manage(actor: Role, target: Role) -> bool {
+    actor >= Role::Admin && actor > target
+}
+
+// 合并权限修改，返回 None 表示设置无效
+pub(crate) fn apply_permissions(
+    current: &ConversationPermissions,
+    update: &UpdatePermissionsRequest,
+) -> Option<ConversationPermissions> {
+    let mut permissions = current.clone();
+    if let Some(post) = &update.post {
+        Permission::parse(post)?;
+        permissions.post = post.clone();
+    }
+    if let Some(invite) = &update.invite {
+        Permission::parse(invite)?;
+        permissions.invite = invite.clone();
+    }
+    if let Some(edit_info) = &update.edit_info {
+        Permission::parse(edit_info)?;
+        permissions.edit_info = edit_info.clone();
+    }
+    if let Some(pin) = &update.pin {
+        Permission::parse(pin)?;
+        permissions.pin = pin.clone();
+    }
+    Some(permissions)
+}
+
+// 合并群资料修改，返回新的 meta，None 表示资料无效
+pub(crate) fn apply_meta(current: &Value, update: &UpdateConversationMetaRequest) -> Option<Value> {
+    let mut meta = current.as_object().cloned().unwrap_or_default();
+    if let Some(name) = &update.name {
+        let name = name.trim();
+        if name.is_empty() || name.chars().count() > MAX_CONVERSATION_NAME_LEN {
+            return None;
+        }
+        meta.insert("name".to_string(), Value::from(name));
+    }
+    for (key, value, max_len) in [
+        ("avatar_url", &update.avatar_url, MAX_AVATAR_URL_LEN),
+        ("topic", &update.topic, MAX_TOPIC_LEN),
+    ] {
+        match value.as_ref().map(|value| value.as_deref().map(str::trim)) {
+            None => {}
+            // 空字符串和 null 一样删除
+            Some(None) | Some(Some("")) => {
+                meta.remove(key);
+            }
+            Some(Some(value)) if value.chars().count() > max_len => return None,
+            Some(Some(value)) => {
+                meta.insert(key.to_string(), Value::from(value));
+            }
+        }
+    }
+    Some(Value::Object(meta))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use uuid::Uuid;
+
+    fn permissions(post: &str, invite: &str) -> ConversationPermissions {
+        ConversationPermissions {
+            post: post.to_string(),
+            invite: invite.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_can_post() {
+        let now = Utc::now();
+        let all = permissions("all", "all");
+        let admins = permissions("admins", "all");
+
+        assert!(can_post(Role::Member, &all, None, now));
+        assert!(!can_post(Role::Member, &admins, None, now));
+        assert!(can_post(Role::Admin, &admins, None, now));
+        assert!(can_post(Role::Owner, &admins, None, now));
+
+        assert!(!can_post(Role::Member, &all, Some(now + Duration::minutes(5)), now));
+        assert!(can_post(Role::Member, &all, Some(now - Duration::minutes(5)), now));
+        assert!(!can_post(Role::Member, &permissions("nobody", "all"), None, now));
+    }
+
+    #[test]
+    fn test_can_manage() {
+        assert!(can_manage(Role::Owner, Role::Admin));
+        assert!(can_manage(Role::Admin, Role::Member));
+        assert!(!can_manage(Role::Admin, Role::Admin));
+        assert!(!can_manage(Role::Admin, Role::Owner));
+        assert!(!can_manage(Role::Member, Role::Member));
+
+        assert!(can_invite(Role::Member, &permissions("all", "all")));
+        assert!(!can_invite(Role::Member, &permissions("all", "admins")));
+
+        let admins = ConversationPermissions {
+            edit_info: "admins".to_string(),
+            pin: "admins".to_string(),
+            ..Default::default()
+        };
+        assert!(can_edit_info(Role::Member, &ConversationPermissions::default()));
+        assert!(!can_edit_info(Role::Member, &admins));
+        assert!(can_edit_info(Role::Admin, &admins));
+        assert!(!can_pin(Role::Member, &admins));
+        assert!(can_pin(Role::Owner, &admins));
+    }
+
+    #[test]
+    fn test_apply_permissions() {
+        let update = UpdatePermissionsRequest {
+            conversation_id: Uuid::new_v4(),
+            post: Some("admins".to_string()),
+            ..Default::default()
+        };
+        let permissions = apply_permissions(&ConversationPermissions::default(), &update).unwrap();
+        assert_eq!(permissions.post, "admins");
+        assert_eq!(permissions.invite, "all");
+
+        let update = UpdatePermissionsRequest {
+            conversation_id: Uuid::new_v4(),
+            invite: Some("everyone".to_string()),
+            ..Default::default()
+        };
+        assert!(apply_permissions(&permissions, &update).is_none());
+    }
+
+    #[test]
+    fn test_apply_meta() {
+        use serde_json::json;
+
+        let current = json!({ "name": "cherry", "topic": "old" });
+        let update = UpdateConversationMetaRequest {
+            name: Some(" cherry blossom ".to_string()),
+            avatar_url: Some(Some("https://example.com/a.png".to_string())),
+            topic: Some(None),
+            ..Default::default()
+        };
+        assert_eq!(
+            apply_meta(&current, &update),
+            Some(json!({ "name": "cherry blossom", "avatar_url": "https://example.com/a.png" }))
+        );
+
+        // 空字符串和 null 一样删除
+        let update = UpdateConversationMetaRequest {
+            topic: Some(Some(" ".to_string())),
+            ..Default::default()
+        };
+        assert_eq!(apply_meta(&current, &update), Some(json!({ "name": "cherry" })));
+        let update = UpdateConversationMetaRequest {
+            name: Some("cherry".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(apply_meta(&current, &update), Some(current.clone()));
+
+        let update = UpdateConversationMetaRequest {
+            name: Some(" ".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(apply_meta(&current, &update), None);
+        let update = UpdateConversationMetaRequest {
+            topic: Some(Some("a".repeat(MAX_TOPIC_LEN + 1))),
+            ..Default::default()
+        };
+        assert_eq!(apply_meta(&current, &update), None);
+    }
+}
@@ -0,0 +1,139 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::{DateTime, Utc};
+use cherrycore::types::{ConversationInvite, CreateInviteRequest, ResponseError};
+use uuid::Uuid;
+
+use crate::db::models::{Conversation, JoinRequest};
+
+// 邀请有效期最长 30 天
+const MAX_INVITE_EXPIRE_SECONDS: u64 = 30 * 24 * 3600;
+const MAX_INVITE_USES: i32 = 10000;
+const MAX_JOIN_MESSAGE_LEN: usize = 200;
+
+// 使用邀请码的结果
+pub(crate) enum Redemption {
+    // 邀请码不存在、已撤销、已过期或次数用完
+    Invalid,
+    AlreadyMember(Uuid),
+    Joined(Conversation),
+    // created 为 false 表示已有待审批的申请
+    Pending { request: JoinRequest, created: bool },
+}
+
+// 12 字节随机数，编码后 16 个字符，可以直接放进链接
+pub(crate) fn generate_code() -> String {
+    let mut code = [0u8; 12];
+    OsRng.fill_bytes(&mut code);
+    URL_SAFE_NO_PAD.encode(code)
+}
+
+// 校验创建参数，返回邀请的过期时间
+pub(crate) fn validate_create(
+    request: &CreateInviteRequest,
+    now: DateTime<Utc>,
+) -> Result<Option<DateTime<Utc>>, ResponseError> {
+    if request
+        .max_uses
+        .is_some_and(|max_uses| max_uses <= 0 || max_uses > MAX_INVITE_USES)
+    {
+        return Err(ResponseError::DataInvalid);
+    }
+    match request.expires_in_seconds {
+        None => Ok(None),
+        Some(0) => Err(ResponseError::DataInvalid),
+        Some(seconds) if seconds > MAX_INVITE_EXPIRE_SECONDS => Err(ResponseError::DataInvalid),
+        Some(seconds) => Ok(Some(now + chrono::Duration::seconds(seconds as i64))),
+    }
+}
+
+pub(crate) fn normalize_message(message: Option<&str>) -> Result<Option<String>, ResponseError> {
+    let Some(message) = message.map(str::trim).filter(|message| !message.is_empty()) else {
+        return Ok(None);
+    };
+    if message.chars().count() > MAX_JOIN_MESSAGE_LEN {
+        return Err(ResponseError::DataInvalid);
+    }
+    Ok(Some(message.to_string()))
+}
+
+// 未撤销、未过期且还有剩余次数的邀请才能使用
+pub(crate) fn is_usable(invite: &ConversationInvite, now: DateTime<Utc>) -> bool {
+    invite.revoked_at.is_none()
+        && invite.expires_at.is_none_or(|expires_at| expires_at > now)
+        && invite
+            .max_uses
+            .is_none_or(|max_uses| invite.use_count < max_uses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn invite(now: DateTime<Utc>) -> ConversationInvite {
+        ConversationInvite {
+            invite_id: Uuid::new_v4(),
+            conversation_id: Uuid::new_v4(),
+            code: generate_code(),
+            created_by: Uuid::new_v4(),
+            expires_at: Some(now + Duration::hours(1)),
+            max_uses: Some(2),
+            use_count: 0,
+            requires_approval: false,
+            revoked_at: None,
+            created_at: now,
+        }
+    }
+
+    #[test]
+    fn test_generate_code() {
+        let code = generate_code();
+        assert_eq!(code.len(), 16);
+        assert!(code.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+        assert_ne!(code, generate_code());
+    }
+
+    #[test]
+    fn test_is_usable() {
+        let now = Utc::now();
+        let mut invite = invite(now);
+        assert!(is_usable(&invite, now));
+
+        invite.use_count = 2;
+        assert!(!is_usable(&invite, now));
+        invite.max_uses = None;
+        assert!(is_usable(&invite, now));
+
+        assert!(!is_usable(&invite, now + Duration::hours(2)));
+        invite.expires_at = None;
+        assert!(is_usable(&invite, now + Duration::hours(2)));
+
+        invite.revoked_at = Some(now);
+        assert!(!is_usable(&invite, now));
+    }
+
+    #[test]
+    fn test_validate_create() {
+        let now = Utc::now();
+        let mut request = CreateInviteRequest {
+            conversation_id: Uuid::new_v4(),
+            expires_in_seconds: Some(3600),
+            max_uses: Some(10),
+            requires_approval: false,
+        };
+        assert_eq!(
+            validate_create(&request, now).ok(),
+            Some(Some(now + Duration::hours(1)))
+        );
+
+        request.max_uses = Some(0);
+        assert!(validate_create(&request, now).is_err());
+        request.max_uses = None;
+        request.expires_in_seconds = Some(MAX_INVITE_EXPIRE_SECONDS + 1);
+        assert!(validate_create(&request, now).is_err());
+
+        assert_eq!(normalize_message(Some("  ")).ok(), Some(None));
+        assert!(normalize_message(Some(&"x".repeat(MAX_JOIN_MESSAGE_LEN + 1))).is_err());
+    }
+}
@@ -1,4 +1,12 @@
+mod contacts;
 mod db;
+mod groups;
+mod invites;
+mod oidc;
+mod password;
+mod receipts;
+mod refresh_token;
+mod search;
 mod server;
 
 use clap::Parser;
@@ -39,11 +47,26 @@ async fn main() {
         })
         .init();
 
+    // 旧的 HS256 密钥，只用于校验切换前签发的令牌
     if let Some(jwt_secret) = config.jwt_secret.as_deref() {
-        // set jwt secret to env
-        unsafe {std::env::set_var("JWT_SECRET", jwt_secret)};
-    }else if std::env::var("JWT_SECRET").is_err() {
-        panic!("JWT_SECRET is not set");
+        cherrycore::jwt::set_secret(jwt_secret);
+    }
+    let signing_keys = config.signing_keys().unwrap();
+    if !signing_keys.is_empty() {
+        log::info!(
+            "signing user tokens with key {}, {} keys published",
+            signing_keys[0].kid(),
+            signing_keys.len()
+        );
+        cherrycore::jwt::set_signing_keys(signing_keys);
+    } else if config.jwt_secret.is_none() && std::env::var("JWT_SECRET").is_err() {
+        panic!("neither jwt_keys nor jwt_secret is set");
+    }
+
+    if let Some(service_secret) = config.service_secret.as_deref() {
+        cherrycore::jwt::set_service_secret(service_secret);
+    } else if !cherrycore::jwt::has_service_secret() {
+        panic!("SERVICE_JWT_SECRET is not set");
     }
 
     let server = server::CherryServer::new(config).await;
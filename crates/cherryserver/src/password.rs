@@ -0,0 +1,54 @@
+use anyhow::Result;
+use argon2::{
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+    password_hash::{SaltString, rand_core::OsRng},
+};
+
+pub(crate) const MIN_PASSWORD_LEN: usize = 8;
+pub(crate) const MAX_PASSWORD_LEN: usize = 128;
+
+// argon2 的 PHC 字符串，其余的视为迁移前的明文密码
+pub(crate) fn is_password_hash(password_hash: &str) -> bool {
+    password_hash.starts_with("$argon2")
+}
+
+pub(crate) fn hash_password(password: &str) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| anyhow::anyhow!("hash password failed: {}", e))?;
+    Ok(hash.to_string())
+}
+
+pub(crate) fn verify_password(password: &str, password_hash: &str) -> Result<bool> {
+    let hash = PasswordHash::new(password_hash)
+        .map_err(|e| anyhow::anyhow!("invalid password hash: {}", e))?;
+    Ok(Argon2::default()
+        .verify_password(password.as_bytes(), &hash)
+        .is_ok())
+}
+
+// argon2 计算较慢，放到阻塞线程中执行
+pub(crate) async fn hash_password_async(password: String) -> Result<String> {
+    tokio::task::spawn_blocking(move || hash_password(&password)).await?
+}
+
+pub(crate) async fn verify_password_async(password: String, password_hash: String) -> Result<bool> {
+    tokio::task::spawn_blocking(move || verify_password(&password, &password_hash)).await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_and_verify_password() {
+        let hash = hash_password("password123").unwrap();
+        assert!(is_password_hash(&hash));
+        assert!(!is_password_hash("password123"));
+        assert!(verify_password("password123", &hash).unwrap());
+        assert!(!verify_password("password124", &hash).unwrap());
+        // salted, the same password hashes differently
+        assert_ne!(hash, hash_password("password123").unwrap());
+    }
+}
@@ -0,0 +1,77 @@
+use cherrycore::types::ResponseError;
+
+use crate::db::models::ConversationMember;
+
+// 成员的已读和送达位置，为消息的 offset
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Receipt {
+    pub read_up_to: Option<i64>,
+    pub delivered_up_to: Option<i64>,
+}
+
+// 已读和送达位置只会前进，已读的消息一定已送达。没有变化时返回 None
+pub(crate) fn advance(
+    member: &ConversationMember,
+    read_up_to: Option<i64>,
+    delivered_up_to: Option<i64>,
+) -> Result<Option<Receipt>, ResponseError> {
+    if read_up_to.is_none() && delivered_up_to.is_none() {
+        return Err(ResponseError::DataInvalid);
+    }
+    if read_up_to.is_some_and(|offset| offset < 0)
+        || delivered_up_to.is_some_and(|offset| offset < 0)
+    {
+        return Err(ResponseError::DataInvalid);
+    }
+    let read = member.read_up_to.max(read_up_to);
+    let delivered = member.delivered_up_to.max(delivered_up_to).max(read);
+    if read == member.read_up_to && delivered == member.delivered_up_to {
+        return Ok(None);
+    }
+    Ok(Some(Receipt {
+        read_up_to: read,
+        delivered_up_to: delivered,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    fn member(read_up_to: Option<i64>, delivered_up_to: Option<i64>) -> ConversationMember {
+        ConversationMember {
+            conversation_id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            role: "member".to_string(),
+            joined_at: Utc::now(),
+            muted_until: None,
+            read_up_to,
+            delivered_up_to,
+            read_at: None,
+        }
+    }
+
+    fn receipt(read_up_to: Option<i64>, delivered_up_to: Option<i64>) -> Option<Receipt> {
+        Some(Receipt {
+            read_up_to,
+            delivered_up_to,
+        })
+    }
+
+    #[test]
+    fn test_advance() {
+        let fresh = member(None, None);
+        assert_eq!(advance(&fresh, Some(10), None).ok(), Some(receipt(Some(10), Some(10))));
+        assert_eq!(advance(&fresh, None, Some(20)).ok(), Some(receipt(None, Some(20))));
+
+        let member = member(Some(10), Some(20));
+        assert_eq!(advance(&member, Some(5), Some(15)).ok(), Some(None));
+        assert_eq!(advance(&member, Some(30), None).ok(), Some(receipt(Some(30), Some(30))));
+        assert_eq!(advance(&member, Some(15), None).ok(), Some(receipt(Some(15), Some(20))));
+
+        assert!(advance(&member, None, None).is_err());
+        assert!(advance(&member, Some(-1), None).is_err());
+    }
+}
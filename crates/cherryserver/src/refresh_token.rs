@@ -0,0 +1,53 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+// 刷新令牌格式为 "{token_id}.{secret}"，数据库只保存 secret 的哈希
+pub(crate) struct RefreshToken {
+    pub(crate) token_id: Uuid,
+    pub(crate) secret: String,
+}
+
+impl RefreshToken {
+    pub(crate) fn generate() -> Self {
+        let mut secret = [0u8; 32];
+        OsRng.fill_bytes(&mut secret);
+        Self {
+            token_id: Uuid::new_v4(),
+            secret: hex::encode(secret),
+        }
+    }
+
+    pub(crate) fn parse(token: &str) -> Option<Self> {
+        let (token_id, secret) = token.split_once('.')?;
+        Some(Self {
+            token_id: Uuid::parse_str(token_id).ok()?,
+            secret: secret.to_string(),
+        })
+    }
+
+    pub(crate) fn secret_hash(&self) -> String {
+        hex::encode(Sha256::digest(self.secret.as_bytes()))
+    }
+
+    pub(crate) fn to_token(&self) -> String {
+        format!("{}.{}", self.token_id, self.secret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_refresh_token_roundtrip() {
+        let token = RefreshToken::generate();
+        let parsed = RefreshToken::parse(&token.to_token()).unwrap();
+        assert_eq!(parsed.token_id, token.token_id);
+        assert_eq!(parsed.secret_hash(), token.secret_hash());
+        assert_ne!(token.secret_hash(), RefreshToken::generate().secret_hash());
+
+        assert!(RefreshToken::parse("no-separator").is_none());
+        assert!(RefreshToken::parse("not-a-uuid.secret").is_none());
+    }
+}
@@ -0,0 +1,256 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use cherrycore::{
+    client::stream::{StreamClient, StreamRecordDecoder},
+    types::{
+        DataFormat, MESSAGE_TYPE_DELETE, MESSAGE_TYPE_EDIT, Message, ResponseError, SearchResult,
+    },
+};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::db::repo::Repo;
+
+// 只索引文本类的消息，图片、文件等消息的内容只有链接
+const SEARCHABLE_TYPES: &[&str] = &["text", "quill", "code"];
+const MAX_QUERY_LEN: usize = 100;
+// 一次读取的数据量，不小于单条消息的上限
+const INDEX_READ_SIZE: u64 = 4 * 1024 * 1024;
+
+// 消息流中的一条消息对索引的修改
+#[derive(Debug, PartialEq)]
+pub(crate) enum IndexChange {
+    Insert(SearchResult),
+    Edit {
+        conversation_id: Uuid,
+        message_id: i64,
+        content: String,
+    },
+    Delete {
+        conversation_id: Uuid,
+        message_id: i64,
+    },
+}
+
+// 搜索词，text 用于全文检索，pattern 用于子串匹配
+pub(crate) struct SearchQuery {
+    pub text: String,
+    pub pattern: String,
+}
+
+impl SearchQuery {
+    pub(crate) fn parse(q: &str) -> Result<Self, ResponseError> {
+        let text = q.trim();
+        if text.is_empty() || text.chars().count() > MAX_QUERY_LEN {
+            return Err(ResponseError::DataInvalid);
+        }
+        let escaped = text
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        Ok(Self {
+            text: text.to_string(),
+            pattern: format!("%{}%", escaped),
+        })
+    }
+}
+
+// 消息内容中的所有字符串，quill 等结构化内容也能搜索
+pub(crate) fn searchable_text(content: &Value) -> String {
+    fn collect<'a>(value: &'a Value, texts: &mut Vec<&'a str>) {
+        match value {
+            Value::String(text) => texts.push(text),
+            Value::Array(values) => values.iter().for_each(|value| collect(value, texts)),
+            Value::Object(values) => values.values().for_each(|value| collect(value, texts)),
+            _ => {}
+        }
+    }
+    let mut texts = vec![];
+    collect(content, &mut texts);
+    texts
+        .into_iter()
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// 消息在 offset 处，不属于该会话的消息忽略
+pub(crate) fn index_change(message: &Message, conversation_id: Uuid, offset: i64) -> Option<IndexChange> {
+    if message.conversation_id != conversation_id {
+        return None;
+    }
+    match message.type_.as_str() {
+        MESSAGE_TYPE_EDIT => Some(IndexChange::Edit {
+            conversation_id,
+            message_id: message.reply_to?,
+            content: searchable_text(&message.content),
+        }),
+        MESSAGE_TYPE_DELETE => Some(IndexChange::Delete {
+            conversation_id,
+            message_id: message.reply_to?,
+        }),
+        type_ if SEARCHABLE_TYPES.contains(&type_) => {
+            let content = searchable_text(&message.content);
+            if content.is_empty() {
+                return None;
+            }
+            Some(IndexChange::Insert(SearchResult {
+                conversation_id,
+                message_id: offset,
+                user_id: message.user_id,
+                type_: message.type_.clone(),
+                content,
+                timestamp: message.timestamp,
+            }))
+        }
+        _ => None,
+    }
+}
+
+// 从上次的位置读取会话的消息流，返回索引的修改数
+async fn index_stream(
+    db: &Repo,
+    stream_client: &StreamClient,
+    conversation_id: Uuid,
+    stream_id: i64,
+    mut offset: i64,
+) -> Result<usize> {
+    let mut count = 0;
+    while let Some(response) = stream_client
+        .read_stream_range(stream_id, offset as u64, INDEX_READ_SIZE, true)
+        .await?
+    {
+        if response.data.is_empty() {
+            break;
+        }
+        let next_offset = offset + response.data.len() as i64;
+        let records = StreamRecordDecoder::new(stream_id, offset as u64, response.data)
+            .decode_all()?
+            .unwrap_or_default();
+        let changes = records
+            .iter()
+            .filter(|(record, _)| record.meta.data_format == DataFormat::JsonMessage)
+            .filter_map(|(record, offset)| {
+                let message: Message = serde_json::from_slice(&record.content).ok()?;
+                index_change(&message, conversation_id, *offset as i64)
+            })
+            .collect::<Vec<_>>();
+        db.apply_search_changes(stream_id, &changes, next_offset).await?;
+        count += changes.len();
+        offset = next_offset;
+    }
+    Ok(count)
+}
+
+// 定期索引所有会话的新消息
+// 服务令牌是短期的，每轮用 `stream_client` 取得新的客户端
+pub(crate) fn spawn_indexer(
+    db: Repo,
+    stream_client: impl Fn() -> Result<StreamClient> + Send + 'static,
+    interval: Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let streams = match db.list_search_streams().await {
+                Ok(streams) => streams,
+                Err(e) => {
+                    log::error!("list search streams error: {:?}", e);
+                    continue;
+                }
+            };
+            let stream_client = match stream_client() {
+                Ok(stream_client) => stream_client,
+                Err(e) => {
+                    log::error!("search index skipped, create stream client error: {:?}", e);
+                    continue;
+                }
+            };
+            for (conversation_id, stream_id, offset) in streams {
+                match index_stream(&db, &stream_client, conversation_id, stream_id, offset).await {
+                    Ok(0) => {}
+                    Ok(count) => log::info!("search index: stream_id={}, changes={}", stream_id, count),
+                    Err(e) => log::error!("search index error: stream_id={}, error={:?}", stream_id, e),
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use serde_json::json;
+
+    fn message(conversation_id: Uuid, type_: &str, content: Value, reply_to: Option<i64>) -> Message {
+        Message {
+            id: 0,
+            user_id: Uuid::new_v4(),
+            content,
+            conversation_id,
+            timestamp: Utc::now(),
+            reply_to,
+            type_: type_.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_searchable_text() {
+        assert_eq!(searchable_text(&json!("hello")), "hello");
+        let quill = json!({ "ops": [{ "insert": "hello " }, { "insert": "world", "attributes": { "bold": true } }] });
+        assert_eq!(searchable_text(&quill), "hello world");
+        assert_eq!(searchable_text(&json!({ "url": "" })), "");
+    }
+
+    #[test]
+    fn test_index_change() {
+        let conversation_id = Uuid::new_v4();
+        let text = message(conversation_id, "text", json!("你好 cherry"), None);
+        match index_change(&text, conversation_id, 42) {
+            Some(IndexChange::Insert(result)) => {
+                assert_eq!(result.message_id, 42);
+                assert_eq!(result.content, "你好 cherry");
+                assert_eq!(result.user_id, text.user_id);
+            }
+            change => panic!("unexpected change: {:?}", change),
+        }
+
+        // 其他会话、非文本和空内容的消息不索引
+        assert!(index_change(&text, Uuid::new_v4(), 42).is_none());
+        let image = message(conversation_id, "image", json!({ "url": "a.png" }), None);
+        assert!(index_change(&image, conversation_id, 42).is_none());
+        let empty = message(conversation_id, "text", json!(" "), None);
+        assert!(index_change(&empty, conversation_id, 42).is_none());
+
+        let edit = message(conversation_id, "edit", json!("edited"), Some(42));
+        assert_eq!(
+            index_change(&edit, conversation_id, 100),
+            Some(IndexChange::Edit {
+                conversation_id,
+                message_id: 42,
+                content: "edited".to_string()
+            })
+        );
+        let delete = message(conversation_id, "delete", json!({}), Some(42));
+        assert_eq!(
+            index_change(&delete, conversation_id, 100),
+            Some(IndexChange::Delete {
+                conversation_id,
+                message_id: 42
+            })
+        );
+    }
+
+    #[test]
+    fn test_search_query() {
+        let query = SearchQuery::parse(" 100%_done ").ok().unwrap();
+        assert_eq!(query.text, "100%_done");
+        assert_eq!(query.pattern, "%100\\%\\_done%");
+        assert!(SearchQuery::parse("  ").is_err());
+        assert!(SearchQuery::parse(&"a".repeat(101)).is_err());
+    }
+}
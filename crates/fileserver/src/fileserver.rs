@@ -39,6 +39,23 @@ pub struct FileServerConfig {
     pub thumbnail_size: u32,
     pub disable_acl: bool, // for testing
     pub jwt_secret: Option<String>,
+    // signs the service token used to pull the revoked user tokens
+    #[serde(default)]
+    pub service_secret: Option<String>,
+    // seconds between two pulls of the revoked user tokens from cherryserver
+    #[serde(default = "default_revocation_sync_interval")]
+    pub revocation_sync_interval: u64,
+    // seconds between two pulls of the user token keys from cherryserver
+    #[serde(default = "default_jwks_sync_interval")]
+    pub jwks_sync_interval: u64,
+}
+
+fn default_revocation_sync_interval() -> u64 {
+    10
+}
+
+fn default_jwks_sync_interval() -> u64 {
+    60
 }
 
 impl FileServerConfig {
@@ -1,7 +1,7 @@
 mod db;
 mod fileserver;
 
-use std::{env, path::PathBuf};
+use std::{env, path::PathBuf, time::Duration};
 
 use clap::Parser;
 
@@ -40,13 +40,26 @@ async fn main() {
         })
         .init();
 
+    // user tokens are verified with the JWKS of cherryserver, the secret
+    // only for the tokens issued before cherryserver got signing keys
     if let Some(jwt_secret) = config.jwt_secret.as_deref() {
-        // set jwt secret to env
-        log::info!("Setting JWT_SECRET from config file");
-        unsafe { std::env::set_var("JWT_SECRET", jwt_secret) };
-    } else if std::env::var("JWT_SECRET").is_err() {
-        log::error!("JWT_SECRET is not set");
-        panic!("JWT_SECRET is not set");
+        cherrycore::jwt::set_secret(jwt_secret);
+    }
+    cherrycore::jwt::spawn_jwks_sync(
+        config.cherry_url.clone(),
+        Duration::from_secs(config.jwks_sync_interval),
+    );
+
+    if let Some(service_secret) = config.service_secret.as_deref() {
+        cherrycore::jwt::set_service_secret(service_secret);
+    }
+    if cherrycore::jwt::has_service_secret() {
+        cherrycore::jwt::spawn_revocation_sync(
+            config.cherry_url.clone(),
+            Duration::from_secs(config.revocation_sync_interval),
+        );
+    } else {
+        log::warn!("revoked user tokens are not synced without SERVICE_JWT_SECRET");
     }
 
     let file_server = FileServer::new(config).await;
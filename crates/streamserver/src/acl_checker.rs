@@ -1,54 +1,280 @@
-use crate::StreamServer;
 use anyhow::Result;
-use cherrycore::types;
-use std::time;
-
-pub struct AclChecker<'a> {
-    user_id: uuid::Uuid,
-    stream_id: u64,
-    server: &'a StreamServer,
-    check_ts: time::Instant,
+use axum::{Json, Router, extract::State, routing::post};
+use cherrycore::{
+    jwt::ServiceClaims,
+    types::{self, AclAction},
+};
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{self, Duration},
+};
+use streamstore::StreamId;
+
+use crate::StreamServer;
+
+type AclKey = (uuid::Uuid, StreamId, AclAction);
+
+// Answer of cherryserver for a (user, stream, action), the conversation is set
+// for allowed appends so the appended messages can be checked against it.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AclDecision {
+    pub allowed: bool,
+    pub conversation_id: Option<uuid::Uuid>,
+}
+
+#[derive(Default)]
+struct AclEntries {
+    decisions: HashMap<AclKey, (AclDecision, time::Instant)>,
+    // bumped by every invalidation, a fetch started before it must not insert
+    generation: u64,
+    // next time the expired decisions are swept
+    evict_ts: Option<time::Instant>,
+}
+
+// Shared cache of the ACL decisions of cherryserver, keyed by (user, stream, action).
+// Allowed entries live for `ttl`, denied ones for the shorter `negative_ttl`,
+// and cherryserver invalidates entries when the members of a stream change.
+// Expired entries are swept every `ttl` on insert.
+pub struct AclCache {
+    cherry_server_url: String,
+    disabled: bool,
+    ttl: Duration,
+    negative_ttl: Duration,
+    client: reqwest::Client,
+    entries: Mutex<AclEntries>,
 }
 
-impl<'a> AclChecker<'a> {
-    pub fn new(user_id: uuid::Uuid, stream_id: u64, server: &'a StreamServer) -> Self {
+impl AclCache {
+    pub fn new(
+        cherry_server_url: String,
+        disabled: bool,
+        ttl: Duration,
+        negative_ttl: Duration,
+    ) -> Self {
         Self {
-            user_id,
-            stream_id,
-            server,
-            check_ts: time::Instant::now()
-                .checked_sub(time::Duration::from_secs(5))
-                .unwrap(),
+            cherry_server_url,
+            disabled,
+            ttl,
+            negative_ttl,
+            client: reqwest::Client::new(),
+            entries: Mutex::new(AclEntries::default()),
         }
     }
 
-    pub async fn check_acl(&mut self) -> Result<bool> {
-        if self.check_ts < time::Instant::now() {
-            let allowed = self.check_acl_from_cherry_server().await?;
-            self.check_ts = time::Instant::now() + time::Duration::from_secs(5);
-            Ok(allowed)
-        } else {
-            Ok(true)
+    pub async fn check_acl(
+        &self,
+        user_id: uuid::Uuid,
+        stream_id: StreamId,
+        action: AclAction,
+    ) -> Result<AclDecision> {
+        if self.disabled {
+            return Ok(AclDecision {
+                allowed: true,
+                conversation_id: None,
+            });
         }
-    }
 
-    pub async fn check_acl_from_cherry_server(&self) -> Result<bool> {
-        if self.server.config.disable_acl_check {
-            return Ok(true);
+        let key = (user_id, stream_id, action);
+        let generation = {
+            let entries = self.entries.lock().unwrap();
+            if let Some((decision, expire_ts)) = entries.decisions.get(&key)
+                && *expire_ts > time::Instant::now()
+            {
+                return Ok(*decision);
+            }
+            entries.generation
+        };
+
+        let decision = self.check_acl_from_cherry_server(user_id, stream_id, action).await?;
+        let ttl = if decision.allowed { self.ttl } else { self.negative_ttl };
+        let now = time::Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        // the decision may predate an invalidation, answer with it but don't cache it
+        if entries.generation != generation {
+            return Ok(decision);
         }
+        if entries.evict_ts.is_none_or(|evict_ts| evict_ts <= now) {
+            entries.decisions.retain(|_, (_, expire_ts)| *expire_ts > now);
+            entries.evict_ts = Some(now + self.ttl);
+        }
+        entries.decisions.insert(key, (decision, now + ttl));
+        Ok(decision)
+    }
 
-        let url = format!("{}/api/v1/acl/check", self.server.config.cherry_server_url);
-        let client = reqwest::Client::new();
-        let response = client
+    // Drop the cached decisions matching the user and/or the stream, all of them if both are None.
+    pub fn invalidate(&self, user_id: Option<uuid::Uuid>, stream_id: Option<StreamId>) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        entries.generation += 1;
+        let count = entries.decisions.len();
+        entries.decisions.retain(|(entry_user_id, entry_stream_id, _), _| {
+            let user_matched = user_id.is_none_or(|user_id| user_id == *entry_user_id);
+            let stream_matched = stream_id.is_none_or(|stream_id| stream_id == *entry_stream_id);
+            !(user_matched && stream_matched)
+        });
+        count - entries.decisions.len()
+    }
+
+    async fn check_acl_from_cherry_server(
+        &self,
+        user_id: uuid::Uuid,
+        stream_id: StreamId,
+        action: AclAction,
+    ) -> Result<AclDecision> {
+        let url = format!("{}/api/v1/acl/check", self.cherry_server_url);
+        let response = self
+            .client
             .get(url)
             .query(&[
-                ("user_id", self.user_id.to_string()),
-                ("stream_id", self.stream_id.to_string()),
+                ("user_id", user_id.to_string()),
+                ("stream_id", stream_id.to_string()),
+                ("action", action.as_str().to_string()),
             ])
             .send()
             .await?;
         let body = response.text().await?;
         let response: types::CheckAclResponse = serde_json::from_str(&body)?;
-        Ok(response.allowed)
+        Ok(AclDecision {
+            allowed: response.allowed,
+            conversation_id: response.conversation_id,
+        })
+    }
+}
+
+// Only services (cherryserver) may invalidate the cache.
+async fn invalidate_acl(
+    claims: ServiceClaims,
+    server: State<StreamServer>,
+    request: Json<types::AclInvalidateRequest>,
+) -> Result<Json<types::AclInvalidateResponse>, types::ResponseError> {
+    let invalidated = server.acl_cache.invalidate(request.user_id, request.stream_id);
+    // the members cached for typing notifications may have changed too
+    server.presence.invalidate();
+    log::info!(
+        "invalidate acl, service: {}, user_id: {:?}, stream_id: {:?}, invalidated: {}",
+        claims.service,
+        request.user_id,
+        request.stream_id,
+        invalidated
+    );
+    Ok(Json(types::AclInvalidateResponse { invalidated }))
+}
+
+pub(crate) fn init_routes() -> Router<StreamServer> {
+    Router::new().route("/api/v1/acl/invalidate", post(invalidate_acl))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_acl_cache_invalidate() {
+        // no cherryserver behind the url, only the cached entries answer
+        let cache = AclCache::new(
+            "http://127.0.0.1:1".to_string(),
+            false,
+            Duration::from_secs(60),
+            Duration::from_secs(5),
+        );
+        let (alice, bob) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+        let expire_ts = time::Instant::now() + Duration::from_secs(60);
+        let conversation_id = uuid::Uuid::new_v4();
+        let decision = |allowed| AclDecision {
+            allowed,
+            conversation_id: None,
+        };
+        {
+            let entries = &mut cache.entries.lock().unwrap().decisions;
+            entries.insert((alice, 1, AclAction::Read), (decision(true), expire_ts));
+            entries.insert((alice, 1, AclAction::Append), (decision(false), expire_ts));
+            entries.insert((alice, 2, AclAction::Read), (decision(false), expire_ts));
+            entries.insert(
+                (bob, 1, AclAction::Append),
+                (
+                    AclDecision {
+                        allowed: true,
+                        conversation_id: Some(conversation_id),
+                    },
+                    expire_ts,
+                ),
+            );
+        }
+
+        // a muted member may read but not append
+        assert!(cache.check_acl(alice, 1, AclAction::Read).await.unwrap().allowed);
+        assert!(!cache.check_acl(alice, 1, AclAction::Append).await.unwrap().allowed);
+        assert!(!cache.check_acl(alice, 2, AclAction::Read).await.unwrap().allowed);
+        let decision = cache.check_acl(bob, 1, AclAction::Append).await.unwrap();
+        assert!(decision.allowed);
+        assert_eq!(decision.conversation_id, Some(conversation_id));
+
+        assert_eq!(cache.invalidate(None, Some(1)), 3);
+        assert!(cache.check_acl(alice, 1, AclAction::Read).await.is_err());
+        assert_eq!(cache.invalidate(Some(alice), None), 1);
+        assert_eq!(cache.invalidate(None, None), 0);
+    }
+
+    #[tokio::test]
+    async fn test_acl_cache_disabled() {
+        let cache = AclCache::new(
+            "http://127.0.0.1:1".to_string(),
+            true,
+            Duration::from_secs(60),
+            Duration::from_secs(5),
+        );
+        let decision = cache
+            .check_acl(uuid::Uuid::new_v4(), 1, AclAction::Append)
+            .await
+            .unwrap();
+        assert!(decision.allowed);
+        assert!(decision.conversation_id.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_acl_cache_fetch() {
+        // a cherryserver that allows everything, slowly enough to invalidate meanwhile
+        let app = Router::new().route(
+            "/api/v1/acl/check",
+            axum::routing::get(|| async {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                Json(types::CheckAclResponse {
+                    allowed: true,
+                    conversation_id: None,
+                })
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let cache = std::sync::Arc::new(AclCache::new(
+            url,
+            false,
+            Duration::from_secs(60),
+            Duration::from_secs(5),
+        ));
+        let alice = uuid::Uuid::new_v4();
+        let expired_ts = time::Instant::now() - Duration::from_secs(1);
+        cache.entries.lock().unwrap().decisions.insert(
+            (alice, 2, AclAction::Read),
+            (AclDecision::default(), expired_ts),
+        );
+
+        // invalidated while the fetch is in flight, the decision is not cached
+        let fetch = tokio::spawn({
+            let cache = cache.clone();
+            async move { cache.check_acl(alice, 1, AclAction::Read).await.unwrap() }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        cache.invalidate(Some(alice), Some(1));
+        assert!(fetch.await.unwrap().allowed);
+        assert!(!cache.entries.lock().unwrap().decisions.contains_key(&(alice, 1, AclAction::Read)));
+
+        // cached without an invalidation, and the expired entry is swept on insert
+        assert!(cache.check_acl(alice, 1, AclAction::Read).await.unwrap().allowed);
+        let entries = cache.entries.lock().unwrap();
+        assert!(entries.decisions.contains_key(&(alice, 1, AclAction::Read)));
+        assert_eq!(entries.decisions.len(), 1);
     }
 }
@@ -0,0 +1,329 @@
+use std::{
+    collections::HashMap,
+    io::Read,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use streamstore::{StreamId, store::Store};
+
+// Internal stream holding the committed offsets, never readable by clients.
+pub(crate) const CONSUMER_OFFSETS_STREAM_ID: StreamId = -1;
+// Internal stream holding snapshots of all the offsets, so that startup only
+// replays the commits after the last snapshot.
+pub(crate) const CONSUMER_OFFSETS_SNAPSHOT_STREAM_ID: StreamId = -2;
+// Commits between two snapshots.
+const SNAPSHOT_INTERVAL: u64 = 1000;
+
+pub(crate) fn is_internal_stream(stream_id: StreamId) -> bool {
+    stream_id < 0
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ConsumerOffsetRecord {
+    user_id: uuid::Uuid,
+    consumer: String,
+    stream_id: StreamId,
+    offset: u64,
+}
+
+impl ConsumerOffsetRecord {
+    // length(4, little endian) + json
+    fn encode(&self) -> Result<Vec<u8>> {
+        let json = serde_json::to_vec(self)?;
+        let mut data = Vec::with_capacity(4 + json.len());
+        data.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        data.extend_from_slice(&json);
+        Ok(data)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ConsumerOffsetsSnapshot {
+    // end of the offsets stream when the snapshot was taken
+    offsets_end: u64,
+    offsets: Vec<ConsumerOffsetRecord>,
+}
+
+impl ConsumerOffsetsSnapshot {
+    // length(4, little endian) + json + length(4, little endian), the trailing
+    // length lets the last snapshot be read from the end of the stream
+    fn encode(&self) -> Result<Vec<u8>> {
+        let json = serde_json::to_vec(self)?;
+        let mut data = Vec::with_capacity(8 + json.len());
+        data.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        data.extend_from_slice(&json);
+        data.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        Ok(data)
+    }
+
+    fn load_last(store: &Store) -> Result<Option<Self>> {
+        let (begin, end) = match store.get_stream_range(CONSUMER_OFFSETS_SNAPSHOT_STREAM_ID) {
+            Ok(range) => range,
+            Err(_) => return Ok(None),
+        };
+        if end < begin + 8 {
+            return Ok(None);
+        }
+        let mut reader = store.new_stream_reader(CONSUMER_OFFSETS_SNAPSHOT_STREAM_ID)?;
+        let mut len = [0u8; 4];
+        reader.set_offset(end - 4);
+        reader.read_exact(&mut len)?;
+        let len = u32::from_le_bytes(len) as u64;
+        if end < begin + 8 + len {
+            anyhow::bail!("invalid consumer offsets snapshot, length: {}", len);
+        }
+        let mut json = vec![0u8; len as usize];
+        reader.set_offset(end - 4 - len);
+        reader.read_exact(&mut json)?;
+        Ok(Some(serde_json::from_slice(&json)?))
+    }
+}
+
+type ConsumerKey = (uuid::Uuid, String, StreamId);
+
+// Committed read positions per (user, consumer, stream), persisted as records
+// appended to the internal offsets stream. Every SNAPSHOT_INTERVAL commits all
+// the offsets are written to the snapshot stream, startup loads the last
+// snapshot and replays the commits after it.
+pub(crate) struct ConsumerOffsets {
+    offsets: Mutex<HashMap<ConsumerKey, u64>>,
+    snapshot_interval: u64,
+    commits_since_snapshot: AtomicU64,
+}
+
+impl ConsumerOffsets {
+    pub fn load(store: &Store) -> Result<Self> {
+        let mut offsets = HashMap::new();
+        let mut replay_from = store.get_stream_begin(CONSUMER_OFFSETS_STREAM_ID).unwrap_or(0);
+        if let Some(snapshot) = ConsumerOffsetsSnapshot::load_last(store)? {
+            for record in snapshot.offsets {
+                offsets.insert((record.user_id, record.consumer, record.stream_id), record.offset);
+            }
+            replay_from = replay_from.max(snapshot.offsets_end);
+        }
+        let mut reader = match store.new_stream_reader(CONSUMER_OFFSETS_STREAM_ID) {
+            Ok(reader) => reader,
+            Err(_) => {
+                log::info!("no consumer offsets stream, loaded {} offsets", offsets.len());
+                return Ok(Self::new(offsets, 0));
+            }
+        };
+        reader.set_offset(replay_from);
+
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+
+        let mut replayed = 0;
+        let mut buf = data.as_slice();
+        while buf.len() >= 4 {
+            let len = u32::from_le_bytes(buf[..4].try_into()?) as usize;
+            if buf.len() < 4 + len {
+                break;
+            }
+            let record: ConsumerOffsetRecord = serde_json::from_slice(&buf[4..4 + len])?;
+            offsets.insert(
+                (record.user_id, record.consumer, record.stream_id),
+                record.offset,
+            );
+            buf = &buf[4 + len..];
+            replayed += 1;
+        }
+        log::info!(
+            "loaded {} consumer offsets, replayed {} commits",
+            offsets.len(),
+            replayed
+        );
+        Ok(Self::new(offsets, replayed))
+    }
+
+    fn new(offsets: HashMap<ConsumerKey, u64>, commits_since_snapshot: u64) -> Self {
+        Self {
+            offsets: Mutex::new(offsets),
+            snapshot_interval: SNAPSHOT_INTERVAL,
+            commits_since_snapshot: AtomicU64::new(commits_since_snapshot),
+        }
+    }
+
+    pub fn get(&self, user_id: uuid::Uuid, consumer: &str, stream_id: StreamId) -> Option<u64> {
+        self.offsets
+            .lock()
+            .unwrap()
+            .get(&(user_id, consumer.to_string(), stream_id))
+            .copied()
+    }
+
+    pub async fn commit(
+        &self,
+        store: &Store,
+        user_id: uuid::Uuid,
+        consumer: &str,
+        stream_id: StreamId,
+        offset: u64,
+    ) -> Result<()> {
+        let key = (user_id, consumer.to_string(), stream_id);
+        // the offset is set before the append, a snapshot that sees the end of
+        // the offsets stream past this commit also sees the offset
+        let previous = self.offsets.lock().unwrap().insert(key.clone(), offset);
+        if previous == Some(offset) {
+            return Ok(());
+        }
+        let record = ConsumerOffsetRecord {
+            user_id,
+            consumer: consumer.to_string(),
+            stream_id,
+            offset,
+        };
+        let appended = match record.encode() {
+            Ok(data) => store.append_async(CONSUMER_OFFSETS_STREAM_ID, data).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = appended {
+            // not persisted, put back the previous offset unless a later commit replaced it
+            let mut offsets = self.offsets.lock().unwrap();
+            if offsets.get(&key) == Some(&offset) {
+                match previous {
+                    Some(previous) => offsets.insert(key, previous),
+                    None => offsets.remove(&key),
+                };
+            }
+            return Err(e);
+        }
+        let commits = self.commits_since_snapshot.fetch_add(1, Ordering::Relaxed) + 1;
+        if commits >= self.snapshot_interval {
+            self.commits_since_snapshot.store(0, Ordering::Relaxed);
+            if let Err(e) = self.snapshot(store).await {
+                log::warn!("snapshot consumer offsets failed: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    async fn snapshot(&self, store: &Store) -> Result<()> {
+        let offsets_end = store.get_stream_end(CONSUMER_OFFSETS_STREAM_ID)?;
+        let offsets = self
+            .offsets
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|((user_id, consumer, stream_id), offset)| ConsumerOffsetRecord {
+                user_id: *user_id,
+                consumer: consumer.clone(),
+                stream_id: *stream_id,
+                offset: *offset,
+            })
+            .collect::<Vec<_>>();
+        let count = offsets.len();
+        let snapshot = ConsumerOffsetsSnapshot {
+            offsets_end,
+            offsets,
+        };
+        store
+            .append_async(CONSUMER_OFFSETS_SNAPSHOT_STREAM_ID, snapshot.encode()?)
+            .await?;
+        log::info!(
+            "snapshot {} consumer offsets, offsets stream end: {}",
+            count,
+            offsets_end
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use streamstore::options::Options;
+
+    fn test_options(name: &str) -> (std::path::PathBuf, Options) {
+        let dir = std::env::temp_dir().join(format!(
+            "streamserver-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        let options = Options::new_with_data_path(dir.to_str().unwrap());
+        (dir, options)
+    }
+
+    #[tokio::test]
+    async fn test_commit_and_reload() {
+        let (dir, options) = test_options("consumer-offsets-reload");
+        let store = options.open_store().unwrap();
+        let offsets = ConsumerOffsets::load(&store).unwrap();
+        let (alice, bob) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+        offsets.commit(&store, alice, "default", 1, 10).await.unwrap();
+        offsets.commit(&store, alice, "default", 1, 20).await.unwrap();
+        offsets.commit(&store, alice, "desktop", 1, 5).await.unwrap();
+        offsets.commit(&store, bob, "default", 2, 7).await.unwrap();
+        assert_eq!(offsets.get(alice, "default", 1), Some(20));
+
+        drop(store);
+        let store = options.open_store_readonly().unwrap();
+        let offsets = ConsumerOffsets::load(&store).unwrap();
+        assert_eq!(offsets.get(alice, "default", 1), Some(20));
+        assert_eq!(offsets.get(alice, "desktop", 1), Some(5));
+        assert_eq!(offsets.get(bob, "default", 2), Some(7));
+        assert_eq!(offsets.get(bob, "default", 1), None);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_and_reload() {
+        let (dir, options) = test_options("consumer-offsets-snapshot");
+        let store = options.open_store().unwrap();
+        let mut offsets = ConsumerOffsets::load(&store).unwrap();
+        offsets.snapshot_interval = 4;
+        let users = (0..3).map(|_| uuid::Uuid::new_v4()).collect::<Vec<_>>();
+        for offset in 1..=10 {
+            for user_id in users.iter() {
+                offsets.commit(&store, *user_id, "default", 1, offset).await.unwrap();
+            }
+        }
+        // committing the same offset again writes nothing
+        let end = store.get_stream_end(CONSUMER_OFFSETS_STREAM_ID).unwrap();
+        offsets.commit(&store, users[0], "default", 1, 10).await.unwrap();
+        assert_eq!(store.get_stream_end(CONSUMER_OFFSETS_STREAM_ID).unwrap(), end);
+
+        drop(store);
+        let store = options.open_store_readonly().unwrap();
+        let snapshot = ConsumerOffsetsSnapshot::load_last(&store).unwrap().unwrap();
+        assert_eq!(snapshot.offsets.len(), 3);
+        let offsets = ConsumerOffsets::load(&store).unwrap();
+        // 30 commits with a snapshot every 4, only the last 2 are replayed
+        assert_eq!(offsets.commits_since_snapshot.load(Ordering::Relaxed), 2);
+        for user_id in users.iter() {
+            assert_eq!(offsets.get(*user_id, "default", 1), Some(10));
+        }
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_commit_append_failed() {
+        let (dir, options) = test_options("consumer-offsets-failed");
+        let store = options.open_store().unwrap();
+        let alice = uuid::Uuid::new_v4();
+        ConsumerOffsets::load(&store)
+            .unwrap()
+            .commit(&store, alice, "default", 1, 10)
+            .await
+            .unwrap();
+
+        // appends to a read-only store fail, the commit must not look persisted
+        let store = options.open_store_readonly().unwrap();
+        let offsets = ConsumerOffsets::load(&store).unwrap();
+        assert!(offsets.commit(&store, alice, "default", 1, 20).await.is_err());
+        assert_eq!(offsets.get(alice, "default", 1), Some(10));
+        assert!(offsets.commit(&store, alice, "default", 1, 20).await.is_err());
+        assert!(offsets.commit(&store, alice, "default", 2, 5).await.is_err());
+        assert_eq!(offsets.get(alice, "default", 2), None);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,230 @@
+use chrono::{DateTime, Duration, Utc};
+use cherrycore::types::{
+    DataFormat, MESSAGE_RECORD_META_SIZE, MESSAGE_TYPE_DELETE, Message, ResponseError,
+    StreamRecord, StreamRecordMeta,
+};
+use serde::Deserialize;
+use serde_json::Value;
+
+// Message types users may append, `system` messages are only written by services.
+pub const USER_MESSAGE_TYPES: &[&str] = &[
+    "text", "image", "audio", "video", "file", "emoji", "code", "location", "contact", "event",
+    "custom", "reaction", "quill", "edit", "delete",
+];
+
+// The part of a message the client decides, id, user_id and timestamp are
+// ignored and filled in by the server.
+#[derive(Deserialize)]
+struct MessageDraft {
+    conversation_id: uuid::Uuid,
+    content: Value,
+    #[serde(default)]
+    reply_to: Option<i64>,
+    #[serde(rename = "type")]
+    type_: String,
+}
+
+// Validate the records a user appends and rewrite them with the sender of the
+// JWT and the server time. `conversation_id` is the conversation of the stream,
+// None when it is unknown (acl check disabled).
+pub fn seal_records(
+    data: &[u8],
+    user_id: uuid::Uuid,
+    conversation_id: Option<uuid::Uuid>,
+    now: DateTime<Utc>,
+) -> Result<Vec<Message>, ResponseError> {
+    let mut sealed = vec![];
+    let mut data = data;
+    while !data.is_empty() {
+        let meta = StreamRecordMeta::decode(data).map_err(|_| ResponseError::DataInvalid)?;
+        let record_size = meta.content_size as usize + MESSAGE_RECORD_META_SIZE * 2;
+        if data.len() < record_size {
+            return Err(ResponseError::DataInvalid);
+        }
+        let record =
+            StreamRecord::decode(&data[..record_size]).map_err(|_| ResponseError::DataInvalid)?;
+        // events are written by cherryserver only
+        if record.meta.data_format != DataFormat::JsonMessage {
+            return Err(ResponseError::DataInvalid);
+        }
+        sealed.push(seal_message(&record.content, user_id, conversation_id, now)?);
+        data = &data[record_size..];
+    }
+    Ok(sealed)
+}
+
+pub fn encode_messages(messages: &[Message]) -> Result<Vec<u8>, ResponseError> {
+    let mut data = vec![];
+    for message in messages {
+        data.extend(message.encode()?);
+    }
+    Ok(data)
+}
+
+// Decode the message record at the start of `data`, None if it is not a whole message record.
+pub fn decode_message(data: &[u8]) -> Option<Message> {
+    let record = StreamRecord::decode(data).ok()?;
+    if record.meta.data_format != DataFormat::JsonMessage {
+        return None;
+    }
+    serde_json::from_slice(&record.content).ok()
+}
+
+// Only the author may edit or delete a message, and delete it within
+// `recall_window` after it was sent. A zero window doesn't limit recalls.
+pub fn check_amendment(
+    amendment: &Message,
+    target: &Message,
+    recall_window: Duration,
+    now: DateTime<Utc>,
+) -> Result<(), ResponseError> {
+    if target.conversation_id != amendment.conversation_id
+        || target.is_amendment()
+        || !USER_MESSAGE_TYPES.contains(&target.type_.as_str())
+    {
+        return Err(ResponseError::DataInvalid);
+    }
+    if target.user_id != amendment.user_id {
+        return Err(ResponseError::Forbidden);
+    }
+    if amendment.type_ == MESSAGE_TYPE_DELETE
+        && !recall_window.is_zero()
+        && target.timestamp + recall_window < now
+    {
+        return Err(ResponseError::Forbidden);
+    }
+    Ok(())
+}
+
+fn seal_message(
+    content: &[u8],
+    user_id: uuid::Uuid,
+    conversation_id: Option<uuid::Uuid>,
+    now: DateTime<Utc>,
+) -> Result<Message, ResponseError> {
+    let draft: MessageDraft =
+        serde_json::from_slice(content).map_err(|_| ResponseError::DataInvalid)?;
+    if !USER_MESSAGE_TYPES.contains(&draft.type_.as_str())
+        || draft.content.is_null()
+        || draft.reply_to.is_some_and(|reply_to| reply_to < 0)
+    {
+        return Err(ResponseError::DataInvalid);
+    }
+    let message = Message {
+        // the id of a message is its stream offset
+        id: 0,
+        user_id,
+        content: draft.content,
+        conversation_id: draft.conversation_id,
+        timestamp: now,
+        reply_to: draft.reply_to,
+        type_: draft.type_,
+    };
+    // edits and deletes must name the message they amend
+    if message.is_amendment() && message.reply_to.is_none() {
+        return Err(ResponseError::DataInvalid);
+    }
+    if conversation_id.is_some_and(|conversation_id| conversation_id != message.conversation_id) {
+        return Err(ResponseError::Forbidden);
+    }
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cherrycore::types::StreamEvent;
+
+    fn message(conversation_id: uuid::Uuid, type_: &str) -> Message {
+        Message {
+            id: 42,
+            user_id: uuid::Uuid::new_v4(),
+            content: serde_json::json!({ "text": "hello" }),
+            conversation_id,
+            timestamp: DateTime::from_timestamp(0, 0).unwrap(),
+            reply_to: Some(7),
+            type_: type_.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_seal_records() {
+        let (user_id, conversation_id) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+        let now = Utc::now();
+        let mut data = message(conversation_id, "text").encode().unwrap();
+        data.extend(message(conversation_id, "image").encode().unwrap());
+
+        let sealed = seal_records(&data, user_id, Some(conversation_id), now).ok().unwrap();
+        assert_eq!(sealed.len(), 2);
+        let sealed = encode_messages(&sealed).ok().unwrap();
+        let meta = StreamRecordMeta::decode(&sealed).unwrap();
+        let first_len = meta.content_size as usize + MESSAGE_RECORD_META_SIZE * 2;
+        let first = decode_message(&sealed[..first_len]).unwrap();
+        let second = decode_message(&sealed[first_len..]).unwrap();
+        assert_eq!(first.user_id, user_id);
+        assert_eq!(first.timestamp, now);
+        assert_eq!(first.id, 0);
+        assert_eq!(first.reply_to, Some(7));
+        assert_eq!(second.type_, "image");
+        assert_eq!(second.user_id, user_id);
+
+        // unknown while the acl check is disabled
+        assert!(seal_records(&data, user_id, None, now).is_ok());
+    }
+
+    #[test]
+    fn test_seal_records_rejected() {
+        let (user_id, conversation_id) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+        let now = Utc::now();
+        let seal = |data: &[u8]| seal_records(data, user_id, Some(conversation_id), now);
+
+        for type_ in ["system", "unknown", ""] {
+            let data = message(conversation_id, type_).encode().unwrap();
+            assert!(matches!(seal(&data), Err(ResponseError::DataInvalid)));
+        }
+        let mut edit = message(conversation_id, "edit");
+        edit.reply_to = None;
+        assert!(matches!(seal(&edit.encode().unwrap()), Err(ResponseError::DataInvalid)));
+        let data = message(uuid::Uuid::new_v4(), "text").encode().unwrap();
+        assert!(matches!(seal(&data), Err(ResponseError::Forbidden)));
+
+        let data = message(conversation_id, "text").encode().unwrap();
+        assert!(matches!(seal(&data[..data.len() - 1]), Err(ResponseError::DataInvalid)));
+        assert!(matches!(seal(b"not a record"), Err(ResponseError::DataInvalid)));
+
+        let data = StreamEvent::ConversationDeleted { conversation_id }.encode().unwrap();
+        assert!(matches!(seal(&data), Err(ResponseError::DataInvalid)));
+        assert!(decode_message(&data).is_none());
+    }
+
+    #[test]
+    fn test_check_amendment() {
+        let conversation_id = uuid::Uuid::new_v4();
+        let now = Utc::now();
+        let window = Duration::minutes(2);
+        let mut target = message(conversation_id, "text");
+        target.timestamp = now - Duration::minutes(1);
+        let mut amendment = message(conversation_id, "delete");
+        amendment.user_id = target.user_id;
+        assert!(check_amendment(&amendment, &target, window, now).is_ok());
+
+        // too late to recall, but edits and unlimited windows are fine
+        let later = now + Duration::minutes(5);
+        assert!(matches!(
+            check_amendment(&amendment, &target, window, later),
+            Err(ResponseError::Forbidden)
+        ));
+        assert!(check_amendment(&amendment, &target, Duration::zero(), later).is_ok());
+        amendment.type_ = "edit".to_string();
+        assert!(check_amendment(&amendment, &target, window, later).is_ok());
+
+        // only the author, and never an amendment itself
+        let mut other = message(conversation_id, "edit");
+        other.user_id = uuid::Uuid::new_v4();
+        assert!(matches!(
+            check_amendment(&other, &target, window, now),
+            Err(ResponseError::Forbidden)
+        ));
+        assert!(check_amendment(&amendment, &amendment, window, now).is_err());
+    }
+}
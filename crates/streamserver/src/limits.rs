@@ -0,0 +1,313 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{self, Duration},
+};
+
+use cherrycore::types::ResponseError;
+use serde::Deserialize;
+use streamstore::StreamId;
+
+// how often the idle buckets are dropped
+pub const PRUNE_BUCKETS_INTERVAL: Duration = Duration::from_secs(60);
+
+// Rates are appends per second, bursts the bucket capacity, 0 disables a limit.
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct StreamLimitsConfig {
+    // max bytes of one append
+    pub max_append_size: u64,
+    pub user_append_rate: f64,
+    pub user_append_burst: f64,
+    pub stream_append_rate: f64,
+    pub stream_append_burst: f64,
+    // concurrent read subscriptions of a user over all its connections
+    pub max_subscriptions_per_user: usize,
+}
+
+impl Default for StreamLimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_append_size: 1024 * 1024,
+            user_append_rate: 20.0,
+            user_append_burst: 100.0,
+            stream_append_rate: 100.0,
+            stream_append_burst: 500.0,
+            max_subscriptions_per_user: 1024,
+        }
+    }
+}
+
+struct TokenBucket {
+    tokens: f64,
+    last_ts: time::Instant,
+}
+
+impl TokenBucket {
+    fn new(burst: f64) -> Self {
+        Self {
+            tokens: burst,
+            last_ts: time::Instant::now(),
+        }
+    }
+
+    fn try_take(&mut self, rate: f64, burst: f64) -> bool {
+        let now = time::Instant::now();
+        let elapsed = now.duration_since(self.last_ts).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(burst);
+        self.last_ts = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn refund(&mut self, burst: f64) {
+        self.tokens = (self.tokens + 1.0).min(burst);
+    }
+
+    // Refilled since the last take, the same as a new bucket.
+    fn is_full(&self, now: time::Instant, rate: f64, burst: f64) -> bool {
+        self.tokens + now.duration_since(self.last_ts).as_secs_f64() * rate >= burst
+    }
+}
+
+// Give back a token taken by `try_take`.
+fn refund<K: std::hash::Hash + Eq>(
+    buckets: &Mutex<HashMap<K, TokenBucket>>,
+    key: &K,
+    rate: f64,
+    burst: f64,
+) {
+    if rate <= 0.0 {
+        return;
+    }
+    if let Some(bucket) = buckets.lock().unwrap().get_mut(key) {
+        bucket.refund(burst);
+    }
+}
+
+fn prune<K>(buckets: &Mutex<HashMap<K, TokenBucket>>, rate: f64, burst: f64) -> usize {
+    let now = time::Instant::now();
+    let mut buckets = buckets.lock().unwrap();
+    let count = buckets.len();
+    buckets.retain(|_, bucket| !bucket.is_full(now, rate, burst));
+    count - buckets.len()
+}
+
+fn try_take<K: std::hash::Hash + Eq>(
+    buckets: &Mutex<HashMap<K, TokenBucket>>,
+    key: K,
+    rate: f64,
+    burst: f64,
+) -> bool {
+    if rate <= 0.0 {
+        return true;
+    }
+    buckets
+        .lock()
+        .unwrap()
+        .entry(key)
+        .or_insert_with(|| TokenBucket::new(burst))
+        .try_take(rate, burst)
+}
+
+pub struct StreamLimits {
+    config: StreamLimitsConfig,
+    users: Mutex<HashMap<uuid::Uuid, TokenBucket>>,
+    streams: Mutex<HashMap<StreamId, TokenBucket>>,
+    subscriptions: Arc<Mutex<HashMap<uuid::Uuid, usize>>>,
+}
+
+// Held by a read subscription, releases its slot of the user when dropped.
+pub struct SubscriptionPermit {
+    user_id: uuid::Uuid,
+    subscriptions: Arc<Mutex<HashMap<uuid::Uuid, usize>>>,
+}
+
+impl Drop for SubscriptionPermit {
+    fn drop(&mut self) {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        if let Some(count) = subscriptions.get_mut(&self.user_id) {
+            *count -= 1;
+            if *count == 0 {
+                subscriptions.remove(&self.user_id);
+            }
+        }
+    }
+}
+
+impl StreamLimits {
+    pub fn new(config: StreamLimitsConfig) -> Self {
+        Self {
+            config,
+            users: Mutex::new(HashMap::new()),
+            streams: Mutex::new(HashMap::new()),
+            subscriptions: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    // Max bytes of one append, 0 means no limit.
+    pub fn max_append_size(&self) -> u64 {
+        self.config.max_append_size
+    }
+
+    pub fn check_append_size(&self, size: usize) -> Result<(), ResponseError> {
+        if self.config.max_append_size > 0 && size as u64 > self.config.max_append_size {
+            return Err(ResponseError::DataTooLarge);
+        }
+        Ok(())
+    }
+
+    // Take a token from the buckets of the user and of the stream.
+    pub fn check_append_rate(
+        &self,
+        user_id: uuid::Uuid,
+        stream_id: StreamId,
+    ) -> Result<(), ResponseError> {
+        let config = &self.config;
+        if !try_take(&self.users, user_id, config.user_append_rate, config.user_append_burst) {
+            log::warn!("user append rate exceeded, user_id: {}", user_id);
+            return Err(ResponseError::TooManyRequests);
+        }
+        if !try_take(
+            &self.streams,
+            stream_id,
+            config.stream_append_rate,
+            config.stream_append_burst,
+        ) {
+            log::warn!("stream append rate exceeded, stream_id: {}", stream_id);
+            // a flooded stream must not use up the appends of the user to the other streams
+            refund(&self.users, &user_id, config.user_append_rate, config.user_append_burst);
+            return Err(ResponseError::TooManyRequests);
+        }
+        Ok(())
+    }
+
+    // Drop the buckets of the idle users and streams, returns how many were dropped.
+    pub fn prune_buckets(&self) -> usize {
+        let config = &self.config;
+        prune(&self.users, config.user_append_rate, config.user_append_burst)
+            + prune(&self.streams, config.stream_append_rate, config.stream_append_burst)
+    }
+
+    pub fn acquire_subscription(
+        &self,
+        user_id: uuid::Uuid,
+    ) -> Result<SubscriptionPermit, ResponseError> {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        let count = subscriptions.entry(user_id).or_insert(0);
+        let max = self.config.max_subscriptions_per_user;
+        if max > 0 && *count >= max {
+            log::warn!("too many subscriptions, user_id: {}", user_id);
+            return Err(ResponseError::TooManyRequests);
+        }
+        *count += 1;
+        Ok(SubscriptionPermit {
+            user_id,
+            subscriptions: self.subscriptions.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_rate_limit() {
+        let limits = StreamLimits::new(StreamLimitsConfig {
+            user_append_rate: 0.001,
+            user_append_burst: 3.0,
+            stream_append_rate: 0.001,
+            stream_append_burst: 4.0,
+            ..Default::default()
+        });
+        let (alice, bob) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+
+        for _ in 0..3 {
+            assert!(limits.check_append_rate(alice, 1).is_ok());
+        }
+        assert!(matches!(
+            limits.check_append_rate(alice, 1),
+            Err(ResponseError::TooManyRequests)
+        ));
+        // bob has a separate user bucket but shares the stream bucket
+        assert!(limits.check_append_rate(bob, 1).is_ok());
+        assert!(limits.check_append_rate(bob, 1).is_err());
+        assert!(limits.check_append_rate(bob, 2).is_ok());
+    }
+
+    #[test]
+    fn test_stream_limit_refunds_user() {
+        let limits = StreamLimits::new(StreamLimitsConfig {
+            user_append_rate: 0.001,
+            user_append_burst: 2.0,
+            stream_append_rate: 0.001,
+            stream_append_burst: 1.0,
+            ..Default::default()
+        });
+        let alice = uuid::Uuid::new_v4();
+        assert!(limits.check_append_rate(alice, 1).is_ok());
+        // rejected by the flooded stream, the token of alice is given back
+        for _ in 0..5 {
+            assert!(limits.check_append_rate(alice, 1).is_err());
+        }
+        assert!(limits.check_append_rate(alice, 2).is_ok());
+        assert!(limits.check_append_rate(alice, 3).is_err());
+    }
+
+    #[test]
+    fn test_prune_buckets() {
+        let limits = StreamLimits::new(StreamLimitsConfig {
+            user_append_rate: 1000.0,
+            user_append_burst: 2.0,
+            stream_append_rate: 0.001,
+            stream_append_burst: 2.0,
+            ..Default::default()
+        });
+        let alice = uuid::Uuid::new_v4();
+        assert!(limits.check_append_rate(alice, 1).is_ok());
+        assert_eq!(limits.prune_buckets(), 0);
+
+        // the user bucket refills within milliseconds, the stream bucket doesn't
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(limits.prune_buckets(), 1);
+        assert!(limits.users.lock().unwrap().is_empty());
+        assert_eq!(limits.streams.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_append_size_limit() {
+        let limits = StreamLimits::new(StreamLimitsConfig {
+            max_append_size: 4,
+            ..Default::default()
+        });
+        assert!(limits.check_append_size(4).is_ok());
+        assert!(matches!(
+            limits.check_append_size(5),
+            Err(ResponseError::DataTooLarge)
+        ));
+    }
+
+    #[test]
+    fn test_subscription_permits() {
+        let limits = StreamLimits::new(StreamLimitsConfig {
+            max_subscriptions_per_user: 2,
+            ..Default::default()
+        });
+        let user_id = uuid::Uuid::new_v4();
+        let Ok(first) = limits.acquire_subscription(user_id) else {
+            panic!("first subscription should be allowed");
+        };
+        let Ok(_second) = limits.acquire_subscription(user_id) else {
+            panic!("second subscription should be allowed");
+        };
+        assert!(limits.acquire_subscription(user_id).is_err());
+
+        drop(first);
+        assert!(limits.acquire_subscription(user_id).is_ok());
+    }
+}
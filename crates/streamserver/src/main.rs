@@ -1,5 +1,5 @@
 use std::{
-    collections::HashMap, env, net::SocketAddr, path::PathBuf, sync::{Arc, Mutex}
+    collections::HashMap, env, net::SocketAddr, path::PathBuf, sync::{Arc, Mutex}, time::Duration
 };
 
 use anyhow::Result;
@@ -7,14 +7,24 @@ use axum::{
     Router,
     routing::{get, post},
 };
-use cherrycore::types::ResponseError;
+use cherrycore::types::{AclAction, ResponseError};
 use clap::Parser;
 use serde::Deserialize;
 use tokio::{net::TcpListener, sync::watch};
 
 use streamstore::{StreamId, store::Store};
 mod acl_checker;
+mod consumer_offsets;
+mod envelope;
+mod limits;
+mod presence;
 mod stream;
+mod unread;
+
+use acl_checker::{AclCache, AclDecision};
+use consumer_offsets::ConsumerOffsets;
+use limits::{StreamLimits, StreamLimitsConfig};
+use presence::PresenceHub;
 
 #[derive(Clone, Deserialize)]
 struct StreamServerConfig {
@@ -22,7 +32,50 @@ struct StreamServerConfig {
     pub cherry_server_url: String,
     pub disable_acl_check: bool,
     pub jwt_secret: Option<String>,
+    // secret of the service tokens, service callers are rejected without it
+    #[serde(default)]
+    pub service_secret: Option<String>,
     pub stream_storage_path: String,
+    // bytes of hot segment blocks cached in memory, 0 disables the cache
+    #[serde(default)]
+    pub block_cache_size: u64,
+    // seconds an allowed/denied acl decision of cherryserver is cached
+    #[serde(default = "default_acl_cache_ttl")]
+    pub acl_cache_ttl: u64,
+    #[serde(default = "default_acl_negative_cache_ttl")]
+    pub acl_negative_cache_ttl: u64,
+    // rate limits and quotas of the users
+    #[serde(default)]
+    pub limits: StreamLimitsConfig,
+    // seconds between two pulls of the revoked user tokens from cherryserver
+    #[serde(default = "default_revocation_sync_interval")]
+    pub revocation_sync_interval: u64,
+    // seconds between two pulls of the user token keys from cherryserver
+    #[serde(default = "default_jwks_sync_interval")]
+    pub jwks_sync_interval: u64,
+    // seconds after sending in which a user may recall (delete) a message, 0 means no limit
+    #[serde(default = "default_message_recall_window")]
+    pub message_recall_window: u64,
+}
+
+fn default_acl_cache_ttl() -> u64 {
+    60
+}
+
+fn default_acl_negative_cache_ttl() -> u64 {
+    5
+}
+
+fn default_revocation_sync_interval() -> u64 {
+    10
+}
+
+fn default_jwks_sync_interval() -> u64 {
+    60
+}
+
+fn default_message_recall_window() -> u64 {
+    120
 }
 
 impl StreamServerConfig {
@@ -41,6 +94,10 @@ struct StreamServer {
 struct StreamServerInner {
     config: StreamServerConfig,
     store: streamstore::store::Store,
+    consumer_offsets: ConsumerOffsets,
+    acl_cache: AclCache,
+    limits: StreamLimits,
+    presence: Arc<PresenceHub>,
     watchers: Arc<Mutex<HashMap<StreamId, (watch::Sender<u64>, watch::Receiver<u64>)>>>,
 }
 
@@ -52,25 +109,64 @@ impl std::ops::Deref for StreamServer {
 }
 
 impl StreamServer {
-    pub fn new(config: StreamServerConfig, store: streamstore::store::Store) -> Self {
+    pub fn new(
+        config: StreamServerConfig,
+        store: streamstore::store::Store,
+        consumer_offsets: ConsumerOffsets,
+    ) -> Self {
         let watchers = Arc::new(Mutex::new(HashMap::new()));
+        let acl_cache = AclCache::new(
+            config.cherry_server_url.clone(),
+            config.disable_acl_check,
+            Duration::from_secs(config.acl_cache_ttl),
+            Duration::from_secs(config.acl_negative_cache_ttl),
+        );
+        let limits = StreamLimits::new(config.limits.clone());
+        let presence = Arc::new(PresenceHub::new(config.cherry_server_url.clone()));
         Self {
             inner: Arc::new(StreamServerInner {
                 config,
                 store,
+                consumer_offsets,
+                acl_cache,
+                limits,
+                presence,
                 watchers,
             }),
         }
     }
 
+    async fn check_acl(&self, user_id: uuid::Uuid, stream_id: StreamId, action: AclAction) -> bool {
+        self.acl_decision(user_id, stream_id, action).await.allowed
+    }
+
+    async fn acl_decision(
+        &self,
+        user_id: uuid::Uuid,
+        stream_id: StreamId,
+        action: AclAction,
+    ) -> AclDecision {
+        match self.acl_cache.check_acl(user_id, stream_id, action).await {
+            Ok(decision) => decision,
+            Err(e) => {
+                log::error!("check acl error, stream_id: {}, error: {}", stream_id, e);
+                AclDecision::default()
+            }
+        }
+    }
+
     async fn append_stream(
         &self,
         stream_id: StreamId,
         data: Vec<u8>,
     ) -> Result<u64, ResponseError> {
+        if consumer_offsets::is_internal_stream(stream_id) {
+            return Err(ResponseError::Forbidden);
+        }
         if data.is_empty() {
             return Err(ResponseError::DataEmpty);
         }
+        self.limits.check_append_size(data.len())?;
         let offset = self.store.append_async(stream_id, data).await?;
         // notify watchers to read the stream data
         let watchers = self.watchers.lock().unwrap();
@@ -81,6 +177,35 @@ impl StreamServer {
     }
 }
 
+#[cfg(test)]
+impl StreamServer {
+    // A server on an empty store in the temp dir, without acl checks.
+    pub(crate) fn new_for_test(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("streamserver-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let config = StreamServerConfig {
+            server_port: 0,
+            cherry_server_url: "http://127.0.0.1:1".to_string(),
+            disable_acl_check: true,
+            jwt_secret: None,
+            service_secret: None,
+            stream_storage_path: dir.to_str().unwrap().to_string(),
+            block_cache_size: 0,
+            acl_cache_ttl: default_acl_cache_ttl(),
+            acl_negative_cache_ttl: default_acl_negative_cache_ttl(),
+            limits: StreamLimitsConfig::default(),
+            revocation_sync_interval: default_revocation_sync_interval(),
+            jwks_sync_interval: default_jwks_sync_interval(),
+            message_recall_window: default_message_recall_window(),
+        };
+        let store = streamstore::options::Options::new_with_data_path(&config.stream_storage_path)
+            .open_store()
+            .unwrap();
+        let consumer_offsets = ConsumerOffsets::load(&store).unwrap();
+        Self::new(config, store, consumer_offsets)
+    }
+}
+
 #[derive(Parser, Debug)]
 struct Cli {
     #[clap(short, long, default_value = "config.yaml")]
@@ -113,22 +238,53 @@ async fn main() {
         })
         .init();
 
+    // user tokens are verified with the JWKS of cherryserver, the secret
+    // only for the tokens issued before cherryserver got signing keys
     if let Some(jwt_secret) = config.jwt_secret.as_deref() {
-        // set jwt secret to env
-        unsafe { std::env::set_var("JWT_SECRET", jwt_secret) };
-    } else if std::env::var("JWT_SECRET").is_err() {
-        panic!("JWT_SECRET is not set");
+        cherrycore::jwt::set_secret(jwt_secret);
+    }
+    cherrycore::jwt::spawn_jwks_sync(
+        config.cherry_server_url.clone(),
+        Duration::from_secs(config.jwks_sync_interval),
+    );
+
+    if let Some(service_secret) = config.service_secret.as_deref() {
+        cherrycore::jwt::set_service_secret(service_secret);
+    }
+    if cherrycore::jwt::has_service_secret() {
+        cherrycore::jwt::spawn_revocation_sync(
+            config.cherry_server_url.clone(),
+            Duration::from_secs(config.revocation_sync_interval),
+        );
+    } else {
+        log::warn!(
+            "SERVICE_JWT_SECRET is not set, service callers are rejected and revoked user tokens are not synced"
+        );
     }
 
     let store = streamstore::options::Options::default()
         .wal_path(&config.stream_storage_path)
+        .block_cache_size(config.block_cache_size)
         .open_store()
         .unwrap();
 
-    let server = StreamServer::new(config.clone(), store);
+    let consumer_offsets = ConsumerOffsets::load(&store).unwrap();
+    let server = StreamServer::new(config.clone(), store, consumer_offsets);
+
+    let limits_server = server.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(limits::PRUNE_BUCKETS_INTERVAL);
+        loop {
+            interval.tick().await;
+            let pruned = limits_server.limits.prune_buckets();
+            log::debug!("pruned {} idle rate limit buckets", pruned);
+        }
+    });
 
     let app = Router::new()
         .merge(stream::init_routes())
+        .merge(acl_checker::init_routes())
+        .merge(unread::init_routes())
         .with_state(server);
     let listener = TcpListener::bind(format!("0.0.0.0:{}", config.server_port))
         .await
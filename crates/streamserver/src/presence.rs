@@ -0,0 +1,274 @@
+use anyhow::Result;
+use cherrycore::{
+    client::cherry::CherryClient,
+    jwt::{self, ServiceClaims},
+    types::{
+        PresenceAudienceRequest, PresenceStatus, StreamControlResponse, StreamReadEvent,
+        UpdatePresenceRequest,
+    },
+};
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+// how long the members of a conversation are cached for typing notifications
+const MEMBERS_CACHE_TTL: Duration = Duration::from_secs(30);
+const PRESENCE_TOKEN_EXPIRE_SECONDS: u64 = 300;
+
+struct Connection {
+    id: u64,
+    sender: mpsc::Sender<StreamReadEvent>,
+}
+
+// other members of a conversation and when they expire
+type CachedMembers = (Vec<Uuid>, Instant);
+
+struct UserPresence {
+    status: PresenceStatus,
+    connections: Vec<Connection>,
+}
+
+// Presence of the users connected to the stream read WebSocket. It is never
+// written to the stream store: it is sent to the connected contacts and
+// members only, and cherryserver keeps the last status of every user.
+pub struct PresenceHub {
+    cherry_server_url: String,
+    next_id: AtomicU64,
+    users: Mutex<HashMap<Uuid, UserPresence>>,
+    // keyed by (user, conversation)
+    members: Mutex<HashMap<(Uuid, Uuid), CachedMembers>>,
+    // statuses waiting for the publishing task of the user
+    queues: Mutex<HashMap<Uuid, mpsc::UnboundedSender<PresenceStatus>>>,
+}
+
+impl PresenceHub {
+    pub fn new(cherry_server_url: String) -> Self {
+        Self {
+            cherry_server_url,
+            next_id: AtomicU64::new(0),
+            users: Mutex::new(HashMap::new()),
+            members: Mutex::new(HashMap::new()),
+            queues: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Register a read socket of the user, returns its id and true for the
+    // first one, which brings the user online.
+    pub fn connect(&self, user_id: Uuid, sender: mpsc::Sender<StreamReadEvent>) -> (u64, bool) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut users = self.users.lock().unwrap();
+        let presence = users.entry(user_id).or_insert_with(|| UserPresence {
+            status: PresenceStatus::Online,
+            connections: vec![],
+        });
+        presence.connections.push(Connection { id, sender });
+        (id, presence.connections.len() == 1)
+    }
+
+    // Unregister a read socket, returns true for the last one: the user is offline.
+    pub fn disconnect(&self, user_id: Uuid, id: u64) -> bool {
+        let mut users = self.users.lock().unwrap();
+        let Some(presence) = users.get_mut(&user_id) else {
+            return false;
+        };
+        presence.connections.retain(|connection| connection.id != id);
+        if presence.connections.is_empty() {
+            users.remove(&user_id);
+            return true;
+        }
+        false
+    }
+
+    pub fn set_status(self: &Arc<Self>, user_id: Uuid, status: PresenceStatus) {
+        let changed = match self.users.lock().unwrap().get_mut(&user_id) {
+            Some(presence) if presence.status != status => {
+                presence.status = status;
+                true
+            }
+            _ => false,
+        };
+        if changed {
+            self.publish_in_order(user_id, status);
+        }
+    }
+
+    // Publish in the background without reordering: one task per user takes
+    // the statuses from its queue, so an Offline can't overtake the Online of
+    // the same user. The task exits once the queue is empty.
+    pub fn publish_in_order(self: &Arc<Self>, user_id: Uuid, status: PresenceStatus) {
+        let mut queues = self.queues.lock().unwrap();
+        if let Some(sender) = queues.get(&user_id) {
+            let _ = sender.send(status);
+            return;
+        }
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let _ = sender.send(status);
+        queues.insert(user_id, sender);
+        let hub = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let status = {
+                    let mut queues = hub.queues.lock().unwrap();
+                    match receiver.try_recv() {
+                        Ok(status) => status,
+                        Err(_) => {
+                            queues.remove(&user_id);
+                            break;
+                        }
+                    }
+                };
+                hub.publish(user_id, status).await;
+            }
+        });
+    }
+
+    pub async fn typing(&self, user_id: Uuid, conversation_id: Uuid, typing: bool) -> Result<()> {
+        let members = self.conversation_members(user_id, conversation_id).await?;
+        self.deliver(
+            &members,
+            StreamControlResponse::Typing {
+                user_id,
+                conversation_id,
+                typing,
+            },
+        );
+        Ok(())
+    }
+
+    // Drop the cached members, called when cherryserver changes the members of a stream.
+    pub fn invalidate(&self) {
+        self.members.lock().unwrap().clear();
+    }
+
+    // Send to every connection of the users, dropped for the slow ones.
+    fn deliver(&self, user_ids: &[Uuid], response: StreamControlResponse) -> usize {
+        let users = self.users.lock().unwrap();
+        let mut delivered = 0;
+        for user_id in user_ids {
+            let Some(presence) = users.get(user_id) else {
+                continue;
+            };
+            for connection in presence.connections.iter() {
+                if connection
+                    .sender
+                    .try_send(StreamReadEvent::Control(response.clone()))
+                    .is_ok()
+                {
+                    delivered += 1;
+                }
+            }
+        }
+        delivered
+    }
+
+    // Store the status in cherryserver and send it to the connected contacts.
+    async fn publish(&self, user_id: Uuid, status: PresenceStatus) {
+        let request = UpdatePresenceRequest { user_id, status };
+        let contacts = match self.client() {
+            Ok(client) => client.update_presence(&request).await,
+            Err(e) => Err(e),
+        };
+        match contacts {
+            Ok(contacts) => {
+                let delivered =
+                    self.deliver(&contacts.user_ids, StreamControlResponse::Presence { user_id, status });
+                log::info!(
+                    "presence, user_id: {}, status: {}, delivered: {}",
+                    user_id,
+                    status.as_str(),
+                    delivered
+                );
+            }
+            Err(e) => log::error!("update presence error, user_id: {}, error: {}", user_id, e),
+        }
+    }
+
+    async fn conversation_members(&self, user_id: Uuid, conversation_id: Uuid) -> Result<Vec<Uuid>> {
+        let key = (user_id, conversation_id);
+        let now = Instant::now();
+        if let Some((members, _)) = self
+            .members
+            .lock()
+            .unwrap()
+            .get(&key)
+            .filter(|(_, expire_ts)| *expire_ts > now)
+        {
+            return Ok(members.clone());
+        }
+        let request = PresenceAudienceRequest {
+            user_id,
+            conversation_id: Some(conversation_id),
+        };
+        let members = self.client()?.presence_audience(&request).await?.user_ids;
+        let mut cached = self.members.lock().unwrap();
+        // a miss already waits for cherryserver, sweep the expired entries meanwhile
+        cached.retain(|_, (_, expire_ts)| *expire_ts > now);
+        cached.insert(key, (members.clone(), now + MEMBERS_CACHE_TTL));
+        Ok(members)
+    }
+
+    fn client(&self) -> Result<CherryClient> {
+        if !jwt::has_service_secret() {
+            return Err(anyhow::anyhow!("service secret is not set"));
+        }
+        let token = ServiceClaims::new("streamserver", PRESENCE_TOKEN_EXPIRE_SECONDS)
+            .to_token()
+            .map_err(|e| anyhow::anyhow!("create service token failed: {}", e))?;
+        let client = CherryClient::new_with_base_url(self.cherry_server_url.clone())?;
+        Ok(client.with_auth((&Uuid::nil(), &token)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presence_connections() {
+        let hub = PresenceHub::new("http://127.0.0.1:1".to_string());
+        let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+        let (tx, mut rx) = mpsc::channel(1);
+        let (first, online) = hub.connect(alice, tx.clone());
+        assert!(online);
+        let (second, online) = hub.connect(alice, tx);
+        assert!(!online);
+
+        let typing = StreamControlResponse::Typing {
+            user_id: bob,
+            conversation_id: Uuid::new_v4(),
+            typing: true,
+        };
+        // the channel holds one event, the other connection drops its copy
+        assert_eq!(hub.deliver(&[alice, bob], typing.clone()), 1);
+        assert!(matches!(rx.try_recv(), Ok(StreamReadEvent::Control(response)) if response == typing));
+
+        assert!(!hub.disconnect(alice, first));
+        assert!(hub.disconnect(alice, second));
+        assert!(!hub.disconnect(alice, second));
+        assert_eq!(hub.deliver(&[alice], typing), 0);
+    }
+
+    #[tokio::test]
+    async fn test_publish_in_order() {
+        // no cherryserver behind the url, publishing fails but the queue drains
+        let hub = Arc::new(PresenceHub::new("http://127.0.0.1:1".to_string()));
+        let alice = Uuid::new_v4();
+        hub.publish_in_order(alice, PresenceStatus::Online);
+        hub.publish_in_order(alice, PresenceStatus::Offline);
+        assert_eq!(hub.queues.lock().unwrap().len(), 1);
+        for _ in 0..100 {
+            if hub.queues.lock().unwrap().is_empty() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("the publishing task of the user didn't exit");
+    }
+}
@@ -2,57 +2,96 @@ use anyhow::Result;
 use axum::{
     Json, Router,
     extract::{
-        State, WebSocketUpgrade,
+        Path, Query, State, WebSocketUpgrade,
         ws::{Message, WebSocket},
     },
-    response::IntoResponse,
+    http::{HeaderMap, StatusCode, header},
+    response::{
+        IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
     routing::{get, post},
 };
 use futures_util::{SinkExt, StreamExt};
 use std::{
-    collections::HashSet,
+    collections::HashMap,
+    convert::Infallible,
     io::Read,
-    sync::{Arc, Mutex},
-    time,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
 };
 use streamstore::StreamId;
 use tokio::{select, sync::Semaphore};
 
-use cherrycore::{jwt::JwtClaims, types::*};
+use cherrycore::{
+    jwt::{Caller, JwtClaims},
+    types::*,
+};
 use tokio::sync::{mpsc, watch};
 
-use crate::StreamServer;
+use crate::{StreamServer, consumer_offsets::is_internal_stream, envelope};
 
 #[axum::debug_handler]
 async fn append_stream_batch(
+    caller: Caller,
     server: State<StreamServer>,
     batch: Json<StreamAppendBatchRequest>,
 ) -> Result<Json<StreamAppendBatchResponse>, ResponseError> {
+    // services are trusted, users need the acl of every stream they append to
+    let user_id = caller.user_id();
+    log::info!("append stream batch, caller: {:?}, size: {}", caller, batch.batch.len());
+
     let mut jobs = vec![];
     for request in batch.0.batch.into_iter() {
+        let stream_id = request.stream_id;
         let server_clone = server.clone();
         let job = tokio::spawn(async move {
-            let stream_id = request.stream_id;
-            let data = request.data.unwrap_or_default();
-            if let Ok(offset) = server_clone.store.append_async(stream_id, data).await {
-                log::info!(
-                    "append stream success, stream_id: {}, offset: {}",
-                    stream_id,
-                    offset
-                );
-            } else {
-                log::error!("append stream error, stream_id: {}", stream_id);
+            let mut data = request.data.unwrap_or_default();
+            if let Some(user_id) = user_id {
+                let decision = server_clone.acl_decision(user_id, stream_id, AclAction::Append).await;
+                if !decision.allowed {
+                    return Err(ResponseError::Forbidden);
+                }
+                server_clone.limits.check_append_rate(user_id, stream_id)?;
+                data = seal_append(&server_clone, stream_id, user_id, decision.conversation_id, &data)
+                    .await?;
             }
+            server_clone.append_stream(stream_id, data).await
         });
-        jobs.push(job);
+        jobs.push((stream_id, job));
     }
 
     // wait for all jobs to complete
-    for job in jobs {
-        job.await.unwrap();
+    let mut results = Vec::with_capacity(jobs.len());
+    for (stream_id, job) in jobs {
+        let result = match job.await {
+            Ok(result) => result,
+            Err(e) => Err(ResponseError::InternalError(e.into())),
+        };
+        match result {
+            Ok(offset) => {
+                log::info!("append stream success, stream_id: {}, offset: {}", stream_id, offset);
+                results.push(StreamAppendBatchResult {
+                    stream_id,
+                    offset: Some(offset),
+                    error: None,
+                });
+            }
+            Err(e) => {
+                log::error!("append stream error, stream_id: {}, error: {}", stream_id, e);
+                results.push(StreamAppendBatchResult {
+                    stream_id,
+                    offset: None,
+                    error: Some(e.to_string()),
+                });
+            }
+        }
     }
 
-    Ok(Json(StreamAppendBatchResponse {}))
+    Ok(Json(StreamAppendBatchResponse { results }))
 }
 
 #[axum::debug_handler]
@@ -66,12 +105,24 @@ async fn append_stream(
         request.stream_id,
         claims.user_id
     );
-    let mut acl_checker = AclChecker::new(claims.user_id, request.stream_id, &server);
-    if !acl_checker.check_acl().await.unwrap_or(false) {
+    let decision = server
+        .acl_decision(claims.user_id, request.stream_id, AclAction::Append)
+        .await;
+    if !decision.allowed {
         return Err(ResponseError::Forbidden);
     }
+    server.limits.check_append_rate(claims.user_id, request.stream_id)?;
+    // the sender and the time of the messages are decided by the server
+    let data = seal_append(
+        &server,
+        request.stream_id,
+        claims.user_id,
+        decision.conversation_id,
+        &request.data.take().unwrap_or_default(),
+    )
+    .await?;
     let offset = match server
-        .append_stream(request.stream_id, request.data.take().unwrap_or_default())  
+        .append_stream(request.stream_id, data)
         .await
     {
         Ok(offset) => offset,   
@@ -87,74 +138,226 @@ async fn append_stream(
     }))
 }
 
-struct AclChecker<'a> {
-    user_id: uuid::Uuid,
+// Commands forwarded from the socket receiver to a running subscription.
+#[derive(Debug)]
+enum SubscriptionCommand {
+    Seek(u64),
+    Pause,
+    Resume,
+    Credit(u32),
+}
+
+// events buffered per subscription, the socket writer takes from all of them in turn
+const SUBSCRIPTION_EVENT_BUFFER: usize = 8;
+
+struct Subscription {
+    commands: mpsc::UnboundedSender<SubscriptionCommand>,
+    token: tokio_util::sync::CancellationToken,
+    position: Arc<AtomicU64>,
+}
+
+struct SubscriptionState {
     stream_id: StreamId,
-    server: &'a StreamServer,
-    check_ts: time::Instant,
+    offset: u64,
+    paused: bool,
+    // number of data frames the client still accepts, None means unlimited
+    credit: Option<u64>,
+    // offset sent to the client so far, shared with the socket receiver
+    position: Arc<AtomicU64>,
 }
 
-impl<'a> AclChecker<'a> {
-    fn new(user_id: uuid::Uuid, stream_id: StreamId, server: &'a StreamServer) -> Self {
+impl SubscriptionState {
+    fn new(stream_id: StreamId, offset: u64, credit: Option<u32>) -> Self {
         Self {
-            user_id,
             stream_id,
-            server,
-            check_ts: time::Instant::now()
-                .checked_sub(time::Duration::from_secs(5))
-                .unwrap(),
+            offset,
+            paused: false,
+            credit: credit.map(|credit| credit as u64),
+            position: Arc::new(AtomicU64::new(offset)),
+        }
+    }
+
+    fn ack(&self, op: &str) -> StreamReadEvent {
+        StreamReadEvent::Control(StreamControlResponse::Ack {
+            stream_id: self.stream_id,
+            op: op.to_string(),
+            offset: self.offset,
+        })
+    }
+
+    fn error(&self, op: &str, error: String) -> StreamReadEvent {
+        StreamReadEvent::Control(StreamControlResponse::Error {
+            stream_id: Some(self.stream_id),
+            op: Some(op.to_string()),
+            error,
+        })
+    }
+
+    fn apply(&mut self, command: SubscriptionCommand, server: &StreamServer) -> StreamReadEvent {
+        match command {
+            SubscriptionCommand::Seek(offset) => {
+                let (begin, end) = server
+                    .store
+                    .get_stream_range(self.stream_id)
+                    .unwrap_or((0, 0));
+                if offset < begin || offset > end {
+                    return self.error(
+                        "seek",
+                        format!("offset {} is out of range [{}, {}]", offset, begin, end),
+                    );
+                }
+                self.offset = offset;
+                self.ack("seek")
+            }
+            SubscriptionCommand::Pause => {
+                self.paused = true;
+                self.ack("pause")
+            }
+            SubscriptionCommand::Resume => {
+                self.paused = false;
+                self.ack("resume")
+            }
+            SubscriptionCommand::Credit(credit) => {
+                if let Some(current) = self.credit.as_mut() {
+                    *current += credit as u64;
+                }
+                self.ack("credit")
+            }
         }
     }
+}
 
-    async fn check_acl(&mut self) -> Result<bool> {
-        if self.check_ts < time::Instant::now() {
-            // TODO: check acl
-            self.check_ts = time::Instant::now() + time::Duration::from_secs(5);
-            Ok(true)
-        } else {
-            Ok(true)
+// End of the stream to read up to, after checking `offset` is inside the stream.
+// A stream without data yet ends at 0.
+fn stream_read_end(server: &StreamServer, stream_id: StreamId, offset: u64) -> Result<u64> {
+    match server.store.get_stream_range(stream_id) {
+        Ok((begin, end)) => {
+            if offset < begin || offset > end {
+                log::error!("offset or length is out of range");
+                return Err(anyhow::anyhow!(
+                    "offset {} is out of range [{}, {}]",
+                    offset,
+                    begin,
+                    end
+                ));
+            }
+            Ok(end)
+        }
+        Err(_) => {
+            log::info!("stream not found, stream_id: {}", stream_id);
+            Ok(0)
         }
     }
 }
 
+// Read the next chunk of the stream at `offset`, None if there is no data there yet.
+async fn read_stream_chunk(
+    server: &StreamServer,
+    stream_id: StreamId,
+    offset: u64,
+) -> Result<Option<StreamReadResponse>> {
+    let mut reader = server.store.new_stream_reader(stream_id)?;
+    reader.set_offset(offset);
+    let data = tokio::task::spawn_blocking(move || {
+        let mut data = vec![0; 128 * 1024];
+        let read_bytes = reader.read(&mut data)?;
+        data.truncate(read_bytes);
+        Ok::<_, std::io::Error>(data)
+    })
+    .await??;
+    if data.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(StreamReadResponse {
+        stream_id,
+        offset,
+        data,
+    }))
+}
+
+// Receiver notified with the new end offset of the stream on every append.
+fn stream_watcher(server: &StreamServer, stream_id: StreamId, offset: u64) -> watch::Receiver<u64> {
+    server
+        .watchers
+        .lock()
+        .unwrap()
+        .entry(stream_id)
+        .or_insert_with(|| {
+            let (tx, rx) = watch::channel(offset);
+            (tx, rx)
+        })
+        .1
+        .clone()
+}
+
 async fn read_one_stream_handler(
     user_id: uuid::Uuid,
     token: tokio_util::sync::CancellationToken,
-    request: StreamReadRequest,
+    mut state: SubscriptionState,
+    mut commands: mpsc::UnboundedReceiver<SubscriptionCommand>,
     semaphore: Arc<Semaphore>,
-    sender: mpsc::Sender<StreamReadResponse>,
+    sender: mpsc::Sender<StreamReadEvent>,
     server: State<StreamServer>,
 ) -> Result<()> {
+    let stream_id = state.stream_id;
     log::info!(
-        "read_one_stream_handler, user_id: {:?}, request: {:?}",
+        "read_one_stream_handler, user_id: {:?}, stream_id: {}, offset: {}, credit: {:?}",
         user_id,
-        request,
+        stream_id,
+        state.offset,
+        state.credit
     );
+    // forward the ack of a command to the client, cancel-aware
+    macro_rules! send_event {
+        ($event:expr) => {
+            select! {
+                result = sender.send($event) => {
+                    if result.is_err() {
+                        return Ok(());
+                    }
+                }
+                _ = token.cancelled() => {
+                    return Ok(());
+                }
+            }
+        };
+    }
+    // apply a command of the client and ack it, then start over from the new state
+    macro_rules! apply_command {
+        ($command:expr) => {
+            match $command {
+                Some(command) => {
+                    let event = state.apply(command, &server);
+                    send_event!(event);
+                    continue;
+                }
+                None => return Ok(()),
+            }
+        };
+    }
 
-    let stream_id = request.stream_id;
-    let mut offset = request.offset;
-    let mut acl_checker = AclChecker::new(user_id, stream_id, &server);
     loop {
-        // check acl every 5 seconds
-        if !acl_checker.check_acl().await.unwrap_or(false) {
+        state.position.store(state.offset, Ordering::Relaxed);
+        // served from the shared acl cache, refreshed when it expires or is invalidated
+        if !server.check_acl(user_id, stream_id, AclAction::Read).await {
             log::error!("acl check failed, stream_id: {}", stream_id);
             return Err(anyhow::anyhow!("acl check failed"));
         }
 
-        if let Ok((begin, end)) = server.store.get_stream_range(stream_id) {
-            if offset < begin || offset > end {
-                log::error!("offset or length is out of range");
-                return Err(anyhow::anyhow!("offset or length is out of range"));
+        // wait for the client while paused or out of credit
+        if state.paused || state.credit == Some(0) {
+            select! {
+                command = commands.recv() => apply_command!(command),
+                _ = token.cancelled() => {
+                    return Ok(());
+                }
             }
-            log::info!(
-                "get_stream_range, stream_id: {:?}, offset: {:?}, begin: {:?}, end: {:?}",
-                stream_id,
-                offset,
-                begin,
-                end
-            );
-
-            let _permit = select! {
+        }
+
+        let end = stream_read_end(&server, stream_id, state.offset)?;
+
+        if state.offset < end {
+            let permit = select! {
                 permit = semaphore.acquire() => {
                     permit
                 }
@@ -163,107 +366,55 @@ async fn read_one_stream_handler(
                 }
             };
 
-            let reader = server.store.new_stream_reader(stream_id).unwrap();
-            reader.set_offset(offset);
-            loop {
-                // check acl every 5 seconds
-                if !acl_checker.check_acl().await.unwrap_or(false) {
-                    log::error!("acl check failed, stream_id: {}", stream_id);
-                    return Err(anyhow::anyhow!("acl check failed"));
+            // a command goes before the chunk being read or sent, the chunk is
+            // dropped and read again from the offset the command leaves
+            let response = select! {
+                response = read_stream_chunk(&server, stream_id, state.offset) => response?,
+                command = commands.recv() => apply_command!(command),
+                _ = token.cancelled() => {
+                    return Ok(());
                 }
-
-                let mut reader_clone = reader.clone();
-                let data = select! {
-                    data = tokio::task::spawn_blocking(move || {
-                        let mut data = vec![0; 128 * 1024];
-                        match reader_clone.read(&mut data) {
-                            Ok(read_bytes) => {
-                                log::info!(
-                                    "read_one_stream_handler, stream_id: {:?}, offset: {:?}, read_bytes: {:?}",
-                                    stream_id,
-                                    offset,
-                                    read_bytes
-                                );
-                                data.truncate(read_bytes);
-                                Ok(data)
-                            }
-                            Err(e) => {
-                                Err(e)
-                            }
-                        }
-                    })=> {
-                        match data {
-                            Ok(data) => {
-                                data
-                            }
-                            Err(e) => {
-                                log::error!("spawn_blocking read stream error, stream_id: {}, error: {}", stream_id, e);
-                                break;
-                            }
+            };
+            // only the reads are limited, not the wait for the client
+            drop(permit);
+            if let Some(response) = response {
+                let next_offset = response.offset + response.data.len() as u64;
+                log::info!(
+                    "read success, stream_id: {:?}, offset: {:?}, data_len: {:?}",
+                    stream_id,
+                    response.offset,
+                    response.data.len()
+                );
+                select! {
+                    result = sender.send(StreamReadEvent::Data(response)) => {
+                        if result.is_err() {
+                            return Ok(());
                         }
                     }
+                    command = commands.recv() => apply_command!(command),
                     _ = token.cancelled() => {
                         return Ok(());
                     }
-                };
-                offset = reader.offset();
-                if let Ok(data) = data {
-                    if data.is_empty() {
-                        log::info!("read stream end, stream_id: {}", stream_id);
-                        break;
-                    }
-
-                    let data_len = data.len();
-                    log::info!(
-                        "read success, stream_id: {:?}, offset: {:?}, data_len: {:?}",
-                        stream_id,
-                        offset,
-                        data_len
-                    );
-                    let response = StreamReadResponse {
-                        stream_id,
-                        offset: offset - data.len() as u64,
-                        data,   
-                    };
-                    select! {
-                        _ = sender.send(response) => {
-                            log::info!(
-                                "send response, stream_id: {:?}, offset: {:?}, data_len: {:?}",
-                                stream_id,
-                                offset,
-                                data_len
-                            );
-                            continue;
-                        }
-                        _ = token.cancelled() => {
-                            return Ok(());
-                        }
-                    }
-                } else {
-                    log::error!("read stream error, stream_id: {}", stream_id);
-                    break;
                 }
+                state.offset = next_offset;
+                if let Some(credit) = state.credit.as_mut() {
+                    *credit -= 1;
+                }
+                continue;
             }
-        } else {
-            log::info!("stream not found, stream_id: {}", stream_id);
+            log::info!("read stream end, stream_id: {}", stream_id);
         }
 
-        let mut rx = server
-            .watchers
-            .lock()
-            .unwrap()
-            .entry(stream_id)
-            .or_insert_with(|| {
-                let (tx, rx) = watch::channel(offset);
-                (tx, rx)
-            })
-            .1
-            .clone();
+        // caught up, wait for new data or a command from the client
+        let mut rx = stream_watcher(&server, stream_id, state.offset);
 
+        let offset = state.offset;
         select! {
-            _ = rx.wait_for(move |new_offset| *new_offset > offset) => {
+            // drop the borrowed value before the other branches await
+            _ = async { rx.wait_for(move |new_offset| *new_offset > offset).await.is_ok() } => {
                 continue;
             }
+            command = commands.recv() => apply_command!(command),
             _ = token.cancelled() => {
                 return Ok(());
             }
@@ -271,86 +422,322 @@ async fn read_one_stream_handler(
     }
 }
 
+// Commit the read position of a consumer after checking the user can read the stream.
+async fn commit_consumer_offset(
+    server: &StreamServer,
+    user_id: uuid::Uuid,
+    consumer: Option<&str>,
+    stream_id: StreamId,
+    offset: u64,
+) -> Result<String, ResponseError> {
+    if is_internal_stream(stream_id) {
+        return Err(ResponseError::Forbidden);
+    }
+    if !server.check_acl(user_id, stream_id, AclAction::Read).await {
+        return Err(ResponseError::Forbidden);
+    }
+    let (_begin, end) = server
+        .store
+        .get_stream_range(stream_id)
+        .map_err(|_| ResponseError::StreamNotFound)?;
+    if offset > end {
+        return Err(ResponseError::DataInvalid);
+    }
+    let consumer = consumer
+        .filter(|consumer| !consumer.is_empty())
+        .unwrap_or(DEFAULT_STREAM_CONSUMER);
+    server
+        .consumer_offsets
+        .commit(&server.store, user_id, consumer, stream_id, offset)
+        .await?;
+    log::info!(
+        "commit offset, user_id: {}, consumer: {}, stream_id: {}, offset: {}",
+        user_id,
+        consumer,
+        stream_id,
+        offset
+    );
+    Ok(consumer.to_string())
+}
+
+async fn commit_offset(
+    claims: JwtClaims,
+    server: State<StreamServer>,
+    request: Json<StreamCommitOffsetRequest>,
+) -> Result<Json<StreamCommittedOffsetResponse>, ResponseError> {
+    let consumer = commit_consumer_offset(
+        &server,
+        claims.user_id,
+        request.consumer.as_deref(),
+        request.stream_id,
+        request.offset,
+    )
+    .await?;
+    Ok(Json(StreamCommittedOffsetResponse {
+        stream_id: request.stream_id,
+        consumer,
+        offset: Some(request.offset),
+    }))
+}
+
+async fn get_committed_offset(
+    claims: JwtClaims,
+    server: State<StreamServer>,
+    request: Query<StreamCommittedOffsetRequest>,
+) -> Result<Json<StreamCommittedOffsetResponse>, ResponseError> {
+    if is_internal_stream(request.stream_id) {
+        return Err(ResponseError::Forbidden);
+    }
+    let consumer = request
+        .consumer
+        .clone()
+        .filter(|consumer| !consumer.is_empty())
+        .unwrap_or_else(|| DEFAULT_STREAM_CONSUMER.to_string());
+    let offset = server
+        .consumer_offsets
+        .get(claims.user_id, &consumer, request.stream_id);
+    Ok(Json(StreamCommittedOffsetResponse {
+        stream_id: request.stream_id,
+        consumer,
+        offset,
+    }))
+}
+
+fn control_error(stream_id: Option<StreamId>, op: Option<&str>, error: String) -> StreamReadEvent {
+    StreamReadEvent::Control(StreamControlResponse::Error {
+        stream_id,
+        op: op.map(|op| op.to_string()),
+        error,
+    })
+}
+
+fn parse_control_request(text: &str) -> Result<StreamControlRequest, serde_json::Error> {
+    match serde_json::from_str::<StreamControlRequest>(text) {
+        Ok(request) => Ok(request),
+        // clients before the control protocol send a bare read request
+        Err(e) => match serde_json::from_str::<StreamReadRequest>(text) {
+            Ok(request) => Ok(request.into()),
+            Err(_) => Err(e),
+        },
+    }
+}
+
+async fn publish_presence(
+    server: &StreamServer,
+    user_id: uuid::Uuid,
+    request: StreamControlRequest,
+) -> Result<()> {
+    match request {
+        StreamControlRequest::Presence { status } => {
+            server.presence.set_status(user_id, status);
+        }
+        StreamControlRequest::Typing {
+            conversation_id,
+            typing,
+        } => server.presence.typing(user_id, conversation_id, typing).await?,
+        _ => {}
+    }
+    Ok(())
+}
+
 async fn read_stream_handler(
     user_id: uuid::Uuid,
     socket: WebSocket,
+    binary: bool,
     server: State<StreamServer>,
 ) -> Result<()> {
-    let (socket_sender, mut socket_receiver) = socket.split();
-    let socket_sender = Arc::new(tokio::sync::Mutex::new(socket_sender));
-    let socket_sender_clone = socket_sender.clone();
+    let (mut socket_sender, mut socket_receiver) = socket.split();
     let token = tokio_util::sync::CancellationToken::new();
     let token_clone = token.clone();
-    let (tx, mut rx) = mpsc::channel(32);
+    let (tx, mut rx) = mpsc::channel::<StreamReadEvent>(32);
+    // every subscription has its own channel, one the client doesn't read fast
+    // enough only holds back itself
+    let (subscription_tx, mut subscription_rx) = mpsc::unbounded_channel::<mpsc::Receiver<StreamReadEvent>>();
     let semaphore = Arc::new(Semaphore::new(8));
 
+    // the socket doesn't wait for cherryserver to store the presence
+    let (connection_id, online) = server.presence.connect(user_id, tx.clone());
+    let presence_server = server.0.clone();
+    if online {
+        presence_server.presence.publish_in_order(user_id, PresenceStatus::Online);
+    }
+
     // Spawn a task to handle incoming messages from the WebSocket
     let receiver_task = tokio::spawn(async move {
-        let mut stream_ids = HashSet::<StreamId>::new();
+        let mut subscriptions = HashMap::<StreamId, Subscription>::new();
         while let Some(msg) = socket_receiver.next().await {
             log::info!("read stream, msg: {:?}", msg);
             match msg {
                 Ok(Message::Text(text)) => {
-                    let text_str = text.to_string();
-                    log::info!("read stream, text_str: {:?}", text_str);
-                    match serde_json::from_str::<StreamReadRequest>(&text_str) {
-                        Ok(request) => {
-                            let semaphore = semaphore.clone();
-                            let tx = tx.clone();
-                            let token = token_clone.clone();
-                            let server = server.clone();
-                            log::info!(
-                                "read stream, spawn read_one_stream_handler, request: {:?}",
-                                request
-                            );
-
-                            if !stream_ids.contains(&request.stream_id) {
-                                stream_ids.insert(request.stream_id);
-                            } else {
-                                log::info!(
-                                    "read stream, stream_id already exists: {:?}",
-                                    request.stream_id
-                                );
-                                continue;
+                    let request = match parse_control_request(text.as_str()) {
+                        Ok(request) => request,
+                        Err(e) => {
+                            log::error!("Failed to parse stream control request: {}", e);
+                            let error = control_error(None, None, format!("Invalid request format: {}", e));
+                            if tx.send(error).await.is_err() {
+                                break;
                             }
+                            continue;
+                        }
+                    };
+                    let op = request.op();
+                    // presence and typing are not tied to a stream
+                    let Some(stream_id) = request.stream_id() else {
+                        if let Err(e) = publish_presence(&server, user_id, request).await {
+                            log::warn!("publish presence error, user_id: {}, error: {}", user_id, e);
+                            if tx.send(control_error(None, Some(op), e.to_string())).await.is_err() {
+                                break;
+                            }
+                        }
+                        continue;
+                    };
+
+                    // drop the subscriptions whose reader already stopped
+                    if subscriptions
+                        .get(&stream_id)
+                        .is_some_and(|subscription| subscription.commands.is_closed())
+                    {
+                        subscriptions.remove(&stream_id);
+                    }
+
+                    if is_internal_stream(stream_id) {
+                        let error = control_error(Some(stream_id), Some(op), ResponseError::Forbidden.to_string());
+                        if tx.send(error).await.is_err() {
+                            break;
+                        }
+                        continue;
+                    }
 
-                            tokio::spawn(async move {
-                                if let Err(e) = read_one_stream_handler(
-                                    user_id,
-                                    token,
-                                    request,
-                                    semaphore,
-                                    tx,
-                                    server.clone(),
-                                )
-                                .await
-                                {
-                                    log::error!("read stream error: {}", e);
+                    // resolve the committed offset of the consumer, then subscribe as usual
+                    let request = match request {
+                        StreamControlRequest::SubscribeCommitted {
+                            stream_id,
+                            consumer,
+                            credit,
+                        } => {
+                            let consumer = consumer.as_deref().unwrap_or(DEFAULT_STREAM_CONSUMER);
+                            let offset = match server.consumer_offsets.get(user_id, consumer, stream_id) {
+                                Some(offset) => offset,
+                                None => server.store.get_stream_begin(stream_id).unwrap_or(0),
+                            };
+                            StreamControlRequest::Subscribe {
+                                stream_id,
+                                offset,
+                                credit,
+                            }
+                        }
+                        request => request,
+                    };
+
+                    let event = match request {
+                        StreamControlRequest::Commit {
+                            stream_id,
+                            consumer,
+                            offset,
+                        } => match commit_consumer_offset(&server, user_id, consumer.as_deref(), stream_id, offset).await {
+                            Ok(_consumer) => StreamReadEvent::Control(StreamControlResponse::Ack {
+                                stream_id,
+                                op: op.to_string(),
+                                offset,
+                            }),
+                            Err(e) => control_error(Some(stream_id), Some(op), e.to_string()),
+                        },
+                        StreamControlRequest::Subscribe {
+                            stream_id,
+                            offset,
+                            credit,
+                        } => {
+                            let permit = if subscriptions.contains_key(&stream_id) {
+                                Err("already subscribed".to_string())
+                            } else {
+                                server.limits.acquire_subscription(user_id).map_err(|e| e.to_string())
+                            };
+                            match permit {
+                                Err(error) => control_error(Some(stream_id), Some(op), error),
+                                Ok(permit) => {
+                                    let (commands, commands_rx) = mpsc::unbounded_channel();
+                                    let (events, events_rx) = mpsc::channel(SUBSCRIPTION_EVENT_BUFFER);
+                                    if subscription_tx.send(events_rx).is_err() {
+                                        break;
+                                    }
+                                    let state = SubscriptionState::new(stream_id, offset, credit);
+                                    let subscription_token = token_clone.child_token();
+                                    subscriptions.insert(
+                                        stream_id,
+                                        Subscription {
+                                            commands,
+                                            token: subscription_token.clone(),
+                                            position: state.position.clone(),
+                                        },
+                                    );
+                                    log::info!("read stream, subscribe stream_id: {}, offset: {}", stream_id, offset);
+
+                                    let semaphore = semaphore.clone();
+                                    let tx = tx.clone();
+                                    let server = server.clone();
+                                    tokio::spawn(async move {
+                                        // the slot of the user is released when the subscription ends
+                                        let _permit = permit;
+                                        if let Err(e) = read_one_stream_handler(
+                                            user_id,
+                                            subscription_token,
+                                            state,
+                                            commands_rx,
+                                            semaphore,
+                                            events,
+                                            server,
+                                        )
+                                        .await
+                                        {
+                                            log::error!("read stream error: {}", e);
+                                            let _ = tx.send(control_error(Some(stream_id), None, e.to_string())).await;
+                                        }
+                                    });
+                                    StreamReadEvent::Control(StreamControlResponse::Ack {
+                                        stream_id,
+                                        op: op.to_string(),
+                                        offset,
+                                    })
                                 }
-                            });
+                            }
                         }
-                        Err(e) => {
-                            log::error!("Failed to parse stream read request: {}", e);
-                            // Send error message back to client
-                            if let Ok(error_msg) = serde_json::to_string(&StreamErrorResponse {
-                                error: format!("Invalid request format: {}", e),
-                            }) {
-                                let mut sender = socket_sender.lock().await;
-                                if let Err(e) = sender.send(Message::Text(error_msg.into())).await {
-                                    log::error!("Failed to send error message: {}", e);
+                        StreamControlRequest::Unsubscribe { stream_id } => {
+                            match subscriptions.remove(&stream_id) {
+                                Some(subscription) => {
+                                    subscription.token.cancel();
+                                    StreamReadEvent::Control(StreamControlResponse::Ack {
+                                        stream_id,
+                                        op: op.to_string(),
+                                        offset: subscription.position.load(Ordering::Relaxed),
+                                    })
                                 }
+                                None => control_error(Some(stream_id), Some(op), "not subscribed".to_string()),
+                            }
+                        }
+                        request => {
+                            let command = match request {
+                                StreamControlRequest::Seek { offset, .. } => SubscriptionCommand::Seek(offset),
+                                StreamControlRequest::Pause { .. } => SubscriptionCommand::Pause,
+                                StreamControlRequest::Resume { .. } => SubscriptionCommand::Resume,
+                                StreamControlRequest::Credit { credit, .. } => SubscriptionCommand::Credit(credit),
+                                _ => unreachable!(),
+                            };
+                            // the subscription acks the command itself
+                            match subscriptions.get(&stream_id) {
+                                Some(subscription) if subscription.commands.send(command).is_ok() => continue,
+                                _ => control_error(Some(stream_id), Some(op), "not subscribed".to_string()),
                             }
                         }
+                    };
+                    if tx.send(event).await.is_err() {
+                        break;
                     }
                 }
                 Ok(Message::Binary(_data)) => {
                     log::warn!("Received binary message, which is not supported");
                 }
-                Ok(Message::Ping(data)) => {
-                    let mut sender = socket_sender.lock().await;
-                    if let Err(e) = sender.send(Message::Pong(data)).await {
-                        log::error!("Failed to send pong: {}", e);
-                    }
+                Ok(Message::Ping(_)) => {
+                    // axum answers pings automatically
                 }
                 Ok(Message::Pong(_)) => {
                     // Ignore pong messages
@@ -370,18 +757,43 @@ async fn read_stream_handler(
 
     // Spawn a task to handle outgoing messages to the WebSocket
     let sender_task = tokio::spawn(async move {
-        while let Some(response) = rx.recv().await {
-            match serde_json::to_string(&response) {
-                Ok(json) => {
-                    let mut sender = socket_sender_clone.lock().await;
-                    if let Err(e) = sender.send(Message::Text(json.into())).await {
-                        log::error!("Failed to send stream data: {}", e);
-                        break;
-                    }
+        let mut subscription_events = futures_util::stream::SelectAll::new();
+        loop {
+            let event = select! {
+                event = rx.recv() => match event {
+                    Some(event) => event,
+                    None => break,
+                },
+                Some(events) = subscription_rx.recv() => {
+                    subscription_events.push(futures_util::stream::unfold(events, |mut events| async move {
+                        events.recv().await.map(|event| (event, events))
+                    }).boxed());
+                    continue;
                 }
-                Err(e) => {
-                    log::error!("Failed to serialize stream response: {}", e);
+                Some(event) = subscription_events.next(), if !subscription_events.is_empty() => event,
+            };
+            let message = match event {
+                StreamReadEvent::Data(response) if binary => {
+                    Message::Binary(response.encode_binary().into())
                 }
+                StreamReadEvent::Data(response) => match serde_json::to_string(&response) {
+                    Ok(json) => Message::Text(json.into()),
+                    Err(e) => {
+                        log::error!("Failed to serialize stream response: {}", e);
+                        continue;
+                    }
+                },
+                StreamReadEvent::Control(response) => match serde_json::to_string(&response) {
+                    Ok(json) => Message::Text(json.into()),
+                    Err(e) => {
+                        log::error!("Failed to serialize stream control response: {}", e);
+                        continue;
+                    }
+                },
+            };
+            if let Err(e) = socket_sender.send(message).await {
+                log::error!("Failed to send stream data: {}", e);
+                break;
             }
         }
     });
@@ -397,9 +809,277 @@ async fn read_stream_handler(
         }
     }
 
+    if presence_server.presence.disconnect(user_id, connection_id) {
+        presence_server.presence.publish_in_order(user_id, PresenceStatus::Offline);
+    }
     Ok(())
 }
 
+// upper bound of the wait of a long-poll read
+const MAX_POLL_WAIT_MS: u64 = 30_000;
+
+// Long-poll read for clients that cannot keep a WebSocket open.
+async fn poll_stream(
+    claims: JwtClaims,
+    server: State<StreamServer>,
+    request: Query<StreamPollRequest>,
+) -> Result<Json<StreamReadResponse>, ResponseError> {
+    let (stream_id, offset) = (request.stream_id, request.offset);
+    if is_internal_stream(stream_id) || !server.check_acl(claims.user_id, stream_id, AclAction::Read).await {
+        return Err(ResponseError::Forbidden);
+    }
+    let end = stream_read_end(&server, stream_id, offset).map_err(|_| ResponseError::DataInvalid)?;
+
+    let wait_ms = request.wait_ms.unwrap_or(0).min(MAX_POLL_WAIT_MS);
+    if offset >= end && wait_ms > 0 {
+        let mut rx = stream_watcher(&server, stream_id, offset);
+        let _ = tokio::time::timeout(Duration::from_millis(wait_ms), async {
+            rx.wait_for(|new_offset| *new_offset > offset).await.is_ok()
+        })
+        .await;
+    }
+
+    let empty = StreamReadResponse {
+        stream_id,
+        offset,
+        data: Vec::new(),
+    };
+    // nothing to read: the stream has no data yet or the wait timed out
+    match server.store.get_stream_end(stream_id) {
+        Ok(end) if offset < end => {}
+        _ => return Ok(Json(empty)),
+    }
+    let response = read_stream_chunk(&server, stream_id, offset).await?.unwrap_or(empty);
+    Ok(Json(response))
+}
+
+// default and max bytes returned by one range read
+const DEFAULT_RANGE_READ_SIZE: u64 = 64 * 1024;
+const MAX_RANGE_READ_SIZE: u64 = 4 * 1024 * 1024;
+
+// Parse `bytes=first-last` or `bytes=first-`, the last byte is inclusive.
+fn parse_range_header(value: &str) -> Option<(u64, Option<u64>)> {
+    let (first, last) = value.trim().strip_prefix("bytes=")?.split_once('-')?;
+    let first = first.trim().parse().ok()?;
+    let last = match last.trim() {
+        "" => None,
+        last => Some(last.parse().ok()?),
+    };
+    Some((first, last))
+}
+
+// Read up to `limit` bytes of the stream from `offset`.
+pub(crate) async fn read_stream_bytes(
+    server: &StreamServer,
+    stream_id: StreamId,
+    offset: u64,
+    limit: u64,
+) -> Result<Vec<u8>> {
+    let mut reader = server.store.new_stream_reader(stream_id)?;
+    reader.set_offset(offset);
+    let data = tokio::task::spawn_blocking(move || {
+        let mut data = vec![0; limit as usize];
+        let mut filled = 0;
+        while filled < data.len() {
+            let read_bytes = reader.read(&mut data[filled..])?;
+            if read_bytes == 0 {
+                break;
+            }
+            filled += read_bytes;
+        }
+        data.truncate(filled);
+        Ok::<_, std::io::Error>(data)
+    })
+    .await??;
+    Ok(data)
+}
+
+// Validate and seal the messages a user appends, edits and deletes also need
+// the message they amend, read back from the stream at its offset.
+async fn seal_append(
+    server: &StreamServer,
+    stream_id: StreamId,
+    user_id: uuid::Uuid,
+    conversation_id: Option<uuid::Uuid>,
+    data: &[u8],
+) -> Result<Vec<u8>, ResponseError> {
+    let now = chrono::Utc::now();
+    let messages = envelope::seal_records(data, user_id, conversation_id, now)?;
+    let recall_window = chrono::Duration::seconds(server.config.message_recall_window as i64);
+    let max_record_size = server.limits.max_append_size();
+    for message in messages.iter().filter(|message| message.is_amendment()) {
+        let offset = message.reply_to.unwrap_or_default() as u64;
+        let (begin, end) = server
+            .store
+            .get_stream_range(stream_id)
+            .map_err(|_| ResponseError::DataInvalid)?;
+        if offset < begin || offset + MESSAGE_RECORD_META_SIZE as u64 > end {
+            return Err(ResponseError::DataInvalid);
+        }
+        let meta =
+            read_stream_bytes(server, stream_id, offset, MESSAGE_RECORD_META_SIZE as u64).await?;
+        let meta = StreamRecordMeta::decode(&meta).map_err(|_| ResponseError::DataInvalid)?;
+        let record_size = meta.content_size as u64 + MESSAGE_RECORD_META_SIZE as u64 * 2;
+        // the target was appended within the size limit, a larger record means
+        // reply_to is not the start of a record, don't allocate for it
+        if (max_record_size > 0 && record_size > max_record_size) || offset + record_size > end {
+            return Err(ResponseError::DataInvalid);
+        }
+        let record = read_stream_bytes(server, stream_id, offset, record_size).await?;
+        let target = envelope::decode_message(&record).ok_or(ResponseError::DataInvalid)?;
+        envelope::check_amendment(message, &target, recall_window, now)?;
+    }
+    envelope::encode_messages(&messages)
+}
+
+// Plain HTTP read of historical data. Stream data never changes once written,
+// so the returned range can be cached by the client.
+async fn read_stream_range(
+    caller: Caller,
+    server: State<StreamServer>,
+    Path(stream_id): Path<StreamId>,
+    headers: HeaderMap,
+    request: Query<StreamRangeRequest>,
+) -> Result<Response, ResponseError> {
+    // services (the search indexer of cherryserver) may read any stream
+    let allowed = match caller.user_id() {
+        Some(user_id) => server.check_acl(user_id, stream_id, AclAction::Read).await,
+        None => true,
+    };
+    if is_internal_stream(stream_id) || !allowed {
+        return Err(ResponseError::Forbidden);
+    }
+    let (begin, end) = server
+        .store
+        .get_stream_range(stream_id)
+        .map_err(|_| ResponseError::StreamNotFound)?;
+
+    let range = headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| parse_range_header(value).ok_or(ResponseError::DataInvalid))
+        .transpose()?;
+    let (offset, limit) = match range {
+        Some((first, Some(last))) if last >= first => (first, last - first + 1),
+        Some((_first, Some(_last))) => return Err(ResponseError::DataInvalid),
+        Some((first, None)) => (first, request.limit.unwrap_or(DEFAULT_RANGE_READ_SIZE)),
+        None => (
+            request.offset.unwrap_or(begin),
+            request.limit.unwrap_or(DEFAULT_RANGE_READ_SIZE),
+        ),
+    };
+    let limit = limit.min(MAX_RANGE_READ_SIZE);
+
+    if offset < begin || offset >= end || limit == 0 {
+        return Ok((
+            StatusCode::RANGE_NOT_SATISFIABLE,
+            [(header::CONTENT_RANGE, format!("bytes */{}", end))],
+        )
+            .into_response());
+    }
+
+    let limit = limit.min(end - offset);
+    let mut data = read_stream_bytes(&server, stream_id, offset, limit).await?;
+    if request.records {
+        data.truncate(StreamRecord::complete_len(&data));
+        if data.is_empty() {
+            // the first record does not fit in the limit
+            return Err(ResponseError::DataTooLarge);
+        }
+    }
+    log::info!(
+        "read stream range, stream_id: {}, offset: {}, len: {}",
+        stream_id,
+        offset,
+        data.len()
+    );
+
+    let last = offset + data.len() as u64 - 1;
+    Ok((
+        StatusCode::PARTIAL_CONTENT,
+        [
+            (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+            (header::CONTENT_RANGE, format!("bytes {}-{}/{}", offset, last, end)),
+            (header::CACHE_CONTROL, "private, max-age=31536000, immutable".to_string()),
+            (header::ETAG, format!("\"{}-{}-{}\"", stream_id, offset, data.len())),
+        ],
+        data,
+    )
+        .into_response())
+}
+
+fn sse_event(event: StreamReadEvent) -> Event {
+    match event {
+        StreamReadEvent::Data(response) => {
+            let next_offset = response.offset + response.data.len() as u64;
+            Event::default()
+                .event(STREAM_SSE_DATA_EVENT)
+                .id(next_offset.to_string())
+                .data(serde_json::to_string(&response).unwrap_or_default())
+        }
+        StreamReadEvent::Control(response) => Event::default()
+            .event(STREAM_SSE_ERROR_EVENT)
+            .data(serde_json::to_string(&response).unwrap_or_default()),
+    }
+}
+
+// Server-Sent Events read of one stream, served by the same reader as a
+// WebSocket subscription. A reconnecting client resumes from Last-Event-ID.
+async fn sse_stream(
+    claims: JwtClaims,
+    server: State<StreamServer>,
+    headers: HeaderMap,
+    request: Query<StreamReadRequest>,
+) -> Result<Sse<impl futures_util::Stream<Item = Result<Event, Infallible>>>, ResponseError> {
+    let user_id = claims.user_id;
+    let stream_id = request.stream_id;
+    if is_internal_stream(stream_id) || !server.check_acl(user_id, stream_id, AclAction::Read).await {
+        return Err(ResponseError::Forbidden);
+    }
+    let offset = headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(request.offset);
+    let permit = server.limits.acquire_subscription(user_id)?;
+    log::info!("sse read stream, stream_id: {}, offset: {}", stream_id, offset);
+
+    let token = tokio_util::sync::CancellationToken::new();
+    let (tx, mut rx) = mpsc::channel::<StreamReadEvent>(32);
+    let (commands, commands_rx) = mpsc::unbounded_channel();
+    tokio::spawn({
+        let token = token.clone();
+        async move {
+            // the client cannot send commands, keep the channel open for the reader
+            let _commands = commands;
+            let _permit = permit;
+            if let Err(e) = read_one_stream_handler(
+                user_id,
+                token,
+                SubscriptionState::new(stream_id, offset, None),
+                commands_rx,
+                Arc::new(Semaphore::new(1)),
+                tx.clone(),
+                server,
+            )
+            .await
+            {
+                log::error!("sse read stream error: {}", e);
+                let _ = tx.send(control_error(Some(stream_id), None, e.to_string())).await;
+            }
+        }
+    });
+
+    // stop the reader once the client goes away and the response is dropped
+    let guard = token.drop_guard();
+    let events = futures_util::stream::poll_fn(move |cx| {
+        let _guard = &guard;
+        rx.poll_recv(cx)
+    })
+    .map(|event| Ok(sse_event(event)));
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
 #[axum::debug_handler]
 async fn read_stream(
     ws: WebSocketUpgrade,
@@ -408,8 +1088,13 @@ async fn read_stream(
 ) -> impl IntoResponse {
     let user_id = claims.user_id;
     log::info!("read stream, claims: {:?}", claims);
+    // JSON text frames stay the default for clients that do not ask for a subprotocol
+    let ws = ws.protocols([STREAM_READ_BINARY_PROTOCOL, STREAM_READ_JSON_PROTOCOL]);
+    let binary = ws
+        .selected_protocol()
+        .is_some_and(|protocol| protocol.as_bytes() == STREAM_READ_BINARY_PROTOCOL.as_bytes());
     ws.on_upgrade(move |socket| async move {
-        if let Err(e) = read_stream_handler(user_id, socket, server).await {
+        if let Err(e) = read_stream_handler(user_id, socket, binary, server).await {
             log::error!("read stream error: {}", e);
         }
     })
@@ -419,5 +1104,247 @@ pub(crate) fn init_routes() -> Router<StreamServer> {
     Router::new()
         .route("/api/v1/stream/append", post(append_stream))
         .route("/api/v1/stream/read", get(read_stream))
+        .route("/api/v1/stream/sse", get(sse_stream))
+        .route("/api/v1/stream/poll", get(poll_stream))
+        .route("/api/v1/stream/{stream_id}/data", get(read_stream_range))
         .route("/api/v2/stream/append_batch", post(append_stream_batch))
+        .route("/api/v1/stream/offset", get(get_committed_offset))
+        .route("/api/v1/stream/offset/commit", post(commit_offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cherrycore::jwt::ServiceClaims;
+
+    #[tokio::test]
+    async fn test_service_append_event() {
+        let server = StreamServer::new_for_test("service-append-event");
+        let conversation_id = uuid::Uuid::new_v4();
+        let event = StreamEvent::MessagePinned {
+            conversation_id,
+            message_id: 42,
+            user_id: uuid::Uuid::new_v4(),
+        };
+        // cherryserver records conversation events with its service token
+        let caller = Caller::Service(ServiceClaims::new("cherryserver", 60));
+        let batch = StreamAppendBatchRequest {
+            batch: vec![StreamAppendRequest {
+                stream_id: 1,
+                data: Some(event.encode().unwrap()),
+            }],
+        };
+        let response = append_stream_batch(caller, State(server.clone()), Json(batch))
+            .await
+            .ok()
+            .unwrap();
+        assert!(response.results[0].error.is_none());
+        assert!(response.results[0].offset.is_some());
+
+        let (begin, end) = server.store.get_stream_range(1).unwrap();
+        let data = read_stream_bytes(&server, 1, begin, end - begin).await.unwrap();
+        let decoded = StreamEvent::decode(&data).unwrap();
+        assert!(matches!(
+            decoded,
+            StreamEvent::MessagePinned { message_id: 42, .. }
+        ));
+        assert_eq!(decoded.conversation_id(), Some(conversation_id));
+    }
+
+    fn draft(conversation_id: uuid::Uuid, type_: &str, reply_to: Option<i64>) -> Vec<u8> {
+        let content = serde_json::json!({
+            "conversation_id": conversation_id,
+            "content": "hello",
+            "reply_to": reply_to,
+            "type": type_,
+        });
+        StreamRecord {
+            meta: StreamRecordMeta::new(DataFormat::JsonMessage),
+            content: serde_json::to_vec(&content).unwrap(),
+            tail: StreamRecordMeta::new(DataFormat::JsonMessage),
+        }
+        .encode()
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_seal_append_reply_to_bounds() {
+        let server = StreamServer::new_for_test("seal-append-bounds");
+        let (user_id, conversation_id) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+        let seal = |data: Vec<u8>| {
+            let server = &server;
+            async move { seal_append(server, 1, user_id, Some(conversation_id), &data).await }
+        };
+        let data = seal(draft(conversation_id, "text", None)).await.ok().unwrap();
+        let end = server.append_stream(1, data).await.ok().unwrap();
+        let (offset, _) = server.store.get_stream_range(1).unwrap();
+
+        let edit = |reply_to: u64| draft(conversation_id, "edit", Some(reply_to as i64));
+        assert!(seal(edit(offset)).await.is_ok());
+        // past the end of the stream
+        assert!(matches!(seal(edit(end)).await, Err(ResponseError::DataInvalid)));
+        // inside the message, where any bytes may pass for a record header
+        for reply_to in offset + 1..end {
+            assert!(seal(edit(reply_to)).await.is_err(), "reply_to {} was accepted", reply_to);
+        }
+    }
+
+    async fn next_event(events: &mut mpsc::Receiver<StreamReadEvent>) -> Option<StreamReadEvent> {
+        tokio::time::timeout(Duration::from_millis(200), events.recv())
+            .await
+            .ok()
+            .flatten()
+    }
+
+    async fn next_data(events: &mut mpsc::Receiver<StreamReadEvent>, offset: u64) -> u64 {
+        match next_event(events).await {
+            Some(StreamReadEvent::Data(response)) => {
+                assert_eq!(response.offset, offset);
+                offset + response.data.len() as u64
+            }
+            _ => panic!("expected data at offset {}", offset),
+        }
+    }
+
+    async fn expect_ack(events: &mut mpsc::Receiver<StreamReadEvent>, expected: &str) {
+        assert!(matches!(
+            next_event(events).await,
+            Some(StreamReadEvent::Control(StreamControlResponse::Ack { op, .. })) if op == expected
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_subscription_credit_and_pause() {
+        let server = StreamServer::new_for_test("subscription-credit");
+        // larger than a read chunk, the stream is sent in several frames
+        for i in 0..3 {
+            server.append_stream(1, vec![i; 200 * 1024]).await.ok().unwrap();
+        }
+        let end = server.store.get_stream_end(1).unwrap();
+
+        let token = tokio_util::sync::CancellationToken::new();
+        let (commands, commands_rx) = mpsc::unbounded_channel();
+        let (events, mut events_rx) = mpsc::channel(SUBSCRIPTION_EVENT_BUFFER);
+        let state = SubscriptionState::new(1, 0, Some(1));
+        let position = state.position.clone();
+        let reader = tokio::spawn(read_one_stream_handler(
+            uuid::Uuid::new_v4(),
+            token.clone(),
+            state,
+            commands_rx,
+            Arc::new(Semaphore::new(1)),
+            events,
+            State(server.clone()),
+        ));
+
+        // one frame per credit
+        let mut offset = next_data(&mut events_rx, 0).await;
+        assert!(next_event(&mut events_rx).await.is_none());
+        assert_eq!(position.load(Ordering::Relaxed), offset);
+        commands.send(SubscriptionCommand::Credit(2)).unwrap();
+        expect_ack(&mut events_rx, "credit").await;
+        for _ in 0..2 {
+            offset = next_data(&mut events_rx, offset).await;
+        }
+        assert!(next_event(&mut events_rx).await.is_none());
+
+        // nothing is sent while paused, even with credit left
+        commands.send(SubscriptionCommand::Pause).unwrap();
+        expect_ack(&mut events_rx, "pause").await;
+        commands.send(SubscriptionCommand::Credit(100)).unwrap();
+        expect_ack(&mut events_rx, "credit").await;
+        assert!(next_event(&mut events_rx).await.is_none());
+
+        commands.send(SubscriptionCommand::Resume).unwrap();
+        expect_ack(&mut events_rx, "resume").await;
+        while offset < end {
+            offset = next_data(&mut events_rx, offset).await;
+        }
+        assert_eq!(offset, end);
+
+        // new data is sent once appended
+        assert!(next_event(&mut events_rx).await.is_none());
+        server.append_stream(1, b"more".to_vec()).await.ok().unwrap();
+        assert_eq!(next_data(&mut events_rx, end).await, end + 4);
+        assert!(next_event(&mut events_rx).await.is_none());
+        assert_eq!(position.load(Ordering::Relaxed), end + 4);
+
+        token.cancel();
+        assert!(reader.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_poll_stream_empty() {
+        let server = StreamServer::new_for_test("poll-stream-empty");
+        let poll = |stream_id, offset, wait_ms| {
+            poll_stream(
+                JwtClaims::new(uuid::Uuid::new_v4(), 60),
+                State(server.clone()),
+                Query(StreamPollRequest {
+                    stream_id,
+                    offset,
+                    wait_ms: Some(wait_ms),
+                }),
+            )
+        };
+        // a stream without data yet, with and without waiting
+        let response = poll(1, 0, 0).await.ok().unwrap();
+        assert!(response.data.is_empty());
+        assert!(poll(1, 0, 10).await.ok().unwrap().data.is_empty());
+
+        let end = server.append_stream(1, b"hello".to_vec()).await.ok().unwrap();
+        let response = poll(1, 0, 0).await.ok().unwrap();
+        assert_eq!(response.data, b"hello");
+        // caught up, the wait times out
+        let response = poll(1, end, 10).await.ok().unwrap();
+        assert!(response.data.is_empty());
+        assert_eq!(response.offset, end);
+    }
+
+    #[tokio::test]
+    async fn test_subscription_pause_and_seek_backlog() {
+        let server = StreamServer::new_for_test("subscription-backlog");
+        for i in 0..3 {
+            server.append_stream(1, vec![i; 200 * 1024]).await.ok().unwrap();
+        }
+
+        // a slow client: one event in flight, the reader waits on the send
+        let token = tokio_util::sync::CancellationToken::new();
+        let (commands, commands_rx) = mpsc::unbounded_channel();
+        let (events, mut events_rx) = mpsc::channel(1);
+        let reader = tokio::spawn(read_one_stream_handler(
+            uuid::Uuid::new_v4(),
+            token.clone(),
+            SubscriptionState::new(1, 0, None),
+            commands_rx,
+            Arc::new(Semaphore::new(1)),
+            events,
+            State(server.clone()),
+        ));
+
+        let offset = next_data(&mut events_rx, 0).await;
+        commands.send(SubscriptionCommand::Pause).unwrap();
+        // the frame already queued may still come, nothing after the ack
+        let mut acked = None;
+        while acked.is_none() {
+            match next_event(&mut events_rx).await {
+                Some(StreamReadEvent::Data(response)) => assert_eq!(response.offset, offset),
+                Some(StreamReadEvent::Control(StreamControlResponse::Ack { op, offset, .. })) => {
+                    assert_eq!(op, "pause");
+                    acked = Some(offset);
+                }
+                _ => panic!("expected the pause ack"),
+            }
+        }
+        assert!(next_event(&mut events_rx).await.is_none());
+
+        commands.send(SubscriptionCommand::Seek(0)).unwrap();
+        expect_ack(&mut events_rx, "seek").await;
+        commands.send(SubscriptionCommand::Resume).unwrap();
+        expect_ack(&mut events_rx, "resume").await;
+        next_data(&mut events_rx, 0).await;
+
+        token.cancel();
+        assert!(reader.await.unwrap().is_ok());
+    }
 }
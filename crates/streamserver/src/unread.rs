@@ -0,0 +1,161 @@
+use axum::{Json, Router, extract::State, routing::post};
+use cherrycore::{
+    jwt::ServiceClaims,
+    types::{
+        MAX_UNREAD_COUNT, MESSAGE_RECORD_META_SIZE, ResponseError, StreamRecordMeta,
+        StreamUnreadCount, StreamUnreadRequest, StreamUnreadResponse,
+    },
+};
+use streamstore::StreamId;
+
+use crate::{StreamServer, consumer_offsets::is_internal_stream, envelope, stream::read_stream_bytes};
+
+const UNREAD_READ_SIZE: u64 = 256 * 1024;
+
+// Count the messages in `data`, read at `offset` on a record boundary, that are
+// after `after` and sent by another user, at most `limit` of them. Returns the
+// count and the length of the whole records counted over.
+fn count_messages(
+    data: &[u8],
+    offset: u64,
+    after: Option<u64>,
+    user_id: uuid::Uuid,
+    limit: u64,
+) -> (u64, usize) {
+    let mut count = 0;
+    let mut len = 0;
+    while count < limit {
+        let Ok(meta) = StreamRecordMeta::decode(&data[len..]) else {
+            break;
+        };
+        let record_size = meta.content_size as usize + MESSAGE_RECORD_META_SIZE * 2;
+        if data.len() - len < record_size {
+            break;
+        }
+        let record_offset = offset + len as u64;
+        if after.is_none_or(|after| record_offset > after) {
+            // events, reactions, edits and deletes are not unread messages
+            let unread = envelope::decode_message(&data[len..len + record_size]).is_some_and(
+                |message| {
+                    message.user_id != user_id
+                        && !message.is_amendment()
+                        && message.type_ != "reaction"
+                },
+            );
+            if unread {
+                count += 1;
+            }
+        }
+        len += record_size;
+    }
+    (count, len)
+}
+
+async fn count_stream_unread(
+    server: &StreamServer,
+    stream_id: StreamId,
+    after: Option<u64>,
+    user_id: uuid::Uuid,
+) -> anyhow::Result<u64> {
+    let (begin, end) = server.store.get_stream_range(stream_id)?;
+    let mut offset = after.unwrap_or(begin).max(begin);
+    let mut read_size = UNREAD_READ_SIZE;
+    let mut count = 0;
+    while offset < end && count < MAX_UNREAD_COUNT {
+        let data = read_stream_bytes(server, stream_id, offset, read_size.min(end - offset)).await?;
+        let (counted, len) = count_messages(&data, offset, after, user_id, MAX_UNREAD_COUNT - count);
+        if len == 0 {
+            // the next record doesn't fit in the read size, read it whole
+            let Ok(meta) = StreamRecordMeta::decode(&data) else {
+                break;
+            };
+            let record_size = meta.content_size as u64 + MESSAGE_RECORD_META_SIZE as u64 * 2;
+            if record_size <= read_size {
+                break;
+            }
+            read_size = record_size;
+            continue;
+        }
+        read_size = UNREAD_READ_SIZE;
+        count += counted;
+        offset += len as u64;
+    }
+    Ok(count)
+}
+
+// Only services (cherryserver) may count the unread messages of a user.
+async fn count_unread(
+    claims: ServiceClaims,
+    server: State<StreamServer>,
+    request: Json<StreamUnreadRequest>,
+) -> Result<Json<StreamUnreadResponse>, ResponseError> {
+    let mut counts = Vec::with_capacity(request.streams.len());
+    for query in request.streams.iter() {
+        let count = if is_internal_stream(query.stream_id) {
+            0
+        } else {
+            count_stream_unread(&server, query.stream_id, query.after, request.user_id)
+                .await
+                .unwrap_or_else(|e| {
+                    log::error!("count unread error, stream_id: {}, error: {}", query.stream_id, e);
+                    0
+                })
+        };
+        counts.push(StreamUnreadCount {
+            stream_id: query.stream_id,
+            count,
+        });
+    }
+    log::info!(
+        "count unread, service: {}, user_id: {}, streams: {}",
+        claims.service,
+        request.user_id,
+        counts.len()
+    );
+    Ok(Json(StreamUnreadResponse { counts }))
+}
+
+pub(crate) fn init_routes() -> Router<StreamServer> {
+    Router::new().route("/api/v1/stream/unread", post(count_unread))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use cherrycore::types::{Message, StreamEvent};
+
+    fn message(user_id: uuid::Uuid, type_: &str) -> Vec<u8> {
+        Message {
+            id: 0,
+            user_id,
+            content: serde_json::json!({ "text": "hello" }),
+            conversation_id: uuid::Uuid::nil(),
+            timestamp: Utc::now(),
+            reply_to: None,
+            type_: type_.to_string(),
+        }
+        .encode()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_count_messages() {
+        let (alice, bob) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+        let first = message(bob, "text");
+        let mut data = first.clone();
+        data.extend(message(alice, "text"));
+        data.extend(message(bob, "reaction"));
+        data.extend(message(bob, "edit"));
+        data.extend(StreamEvent::ConversationDeleted { conversation_id: uuid::Uuid::nil() }.encode().unwrap());
+        data.extend(message(bob, "image"));
+
+        assert_eq!(count_messages(&data, 100, None, alice, MAX_UNREAD_COUNT), (2, data.len()));
+        // the message at `after` is read already
+        assert_eq!(count_messages(&data, 100, Some(100), alice, MAX_UNREAD_COUNT), (1, data.len()));
+        assert_eq!(count_messages(&data, 100, None, bob, MAX_UNREAD_COUNT), (1, data.len()));
+        assert_eq!(count_messages(&data, 100, None, alice, 1), (1, first.len()));
+        // a partial record is left for the next read
+        assert_eq!(count_messages(&data[..first.len() + 1], 0, None, alice, 10), (1, first.len()));
+    }
+}
@@ -0,0 +1,229 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    sync::{Arc, Mutex},
+};
+
+use crate::{StreamId, metrics, segments::Segment};
+
+// (segment id, stream id, block index)
+pub(crate) type BlockKey = (u64, StreamId, u64);
+
+struct CacheState {
+    blocks: HashMap<BlockKey, (Arc<[u8]>, u64)>,
+    // access tick -> key, the first entry is the least recently used block
+    lru: BTreeMap<u64, BlockKey>,
+    tick: u64,
+    size: u64,
+}
+
+// Bounded LRU cache of hot segment blocks, shared by all the stream readers.
+pub(crate) struct BlockCache {
+    capacity: u64,
+    block_size: u64,
+    state: Mutex<CacheState>,
+}
+
+impl BlockCache {
+    pub fn new(capacity: u64, block_size: u64) -> Self {
+        assert!(block_size > 0, "Block size must be greater than zero");
+        BlockCache {
+            capacity,
+            block_size,
+            state: Mutex::new(CacheState {
+                blocks: HashMap::new(),
+                lru: BTreeMap::new(),
+                tick: 0,
+                size: 0,
+            }),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn block_size(&self) -> u64 {
+        self.block_size
+    }
+
+    #[allow(dead_code)]
+    pub fn size(&self) -> u64 {
+        self.state.lock().unwrap().size
+    }
+
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().blocks.len()
+    }
+
+    pub fn get(&self, key: &BlockKey) -> Option<Arc<[u8]>> {
+        let mut state = self.state.lock().unwrap();
+        state.tick += 1;
+        let tick = state.tick;
+        let (block, last_tick) = match state.blocks.get_mut(key) {
+            Some((block, last_tick)) => (block.clone(), std::mem::replace(last_tick, tick)),
+            None => {
+                metrics::block_cache_miss_count.inc();
+                return None;
+            }
+        };
+        state.lru.remove(&last_tick);
+        state.lru.insert(tick, *key);
+        metrics::block_cache_hit_count.inc();
+        Some(block)
+    }
+
+    pub fn insert(&self, key: BlockKey, block: Arc<[u8]>) {
+        let block_len = block.len() as u64;
+        if block_len > self.capacity {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        state.tick += 1;
+        let tick = state.tick;
+        if let Some((old, last_tick)) = state.blocks.insert(key, (block, tick)) {
+            state.lru.remove(&last_tick);
+            state.size -= old.len() as u64;
+        }
+        state.lru.insert(tick, key);
+        state.size += block_len;
+
+        while state.size > self.capacity {
+            let Some((_tick, key)) = state.lru.pop_first() else {
+                break;
+            };
+            if let Some((block, _)) = state.blocks.remove(&key) {
+                state.size -= block.len() as u64;
+            }
+        }
+        metrics::block_cache_bytes.set(state.size as i64);
+    }
+
+    // Same contract as `Segment::read_stream`, serving the data block by block
+    // from the cache and filling the missing blocks from the segment.
+    pub fn read_stream(
+        &self,
+        segment: &Segment,
+        stream_id: StreamId,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> io::Result<usize> {
+        let Some(stream_header) = segment.find_stream_header(stream_id) else {
+            return segment.read_stream(stream_id, offset, buf);
+        };
+        let begin = stream_header.offset;
+        let end = stream_header.offset + stream_header.size;
+        if offset < begin || offset >= end {
+            return Ok(0);
+        }
+
+        let mut offset = offset;
+        let mut copied = 0;
+        while copied < buf.len() && offset < end {
+            let block_index = (offset - begin) / self.block_size;
+            let key = (segment.id(), stream_id, block_index);
+            let block = match self.get(&key) {
+                Some(block) => block,
+                None => {
+                    let data = segment.stream_data(stream_id).unwrap();
+                    let start = (block_index * self.block_size) as usize;
+                    let stop = (start + self.block_size as usize).min(data.len());
+                    let block: Arc<[u8]> = data[start..stop].into();
+                    self.insert(key, block.clone());
+                    block
+                }
+            };
+
+            let start = (offset - begin - block_index * self.block_size) as usize;
+            let n = (block.len() - start).min(buf.len() - copied);
+            buf[copied..copied + n].copy_from_slice(&block[start..start + n]);
+            copied += n;
+            offset += n as u64;
+        }
+        Ok(copied)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(len: usize, value: u8) -> Arc<[u8]> {
+        vec![value; len].into()
+    }
+
+    #[test]
+    fn test_block_cache_get_insert() {
+        let cache = BlockCache::new(1024, 256);
+        assert_eq!(cache.block_size(), 256);
+        assert!(cache.get(&(1, 1, 0)).is_none());
+
+        cache.insert((1, 1, 0), block(256, 7));
+        assert_eq!(&*cache.get(&(1, 1, 0)).unwrap(), &[7u8; 256][..]);
+        assert!(cache.get(&(2, 1, 0)).is_none());
+        assert_eq!(cache.size(), 256);
+        assert_eq!(cache.len(), 1);
+
+        // replacing a block does not leak its size
+        cache.insert((1, 1, 0), block(100, 8));
+        assert_eq!(cache.size(), 100);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_block_cache_evicts_least_recently_used() {
+        let cache = BlockCache::new(300, 100);
+        cache.insert((1, 1, 0), block(100, 0));
+        cache.insert((1, 1, 1), block(100, 1));
+        cache.insert((1, 1, 2), block(100, 2));
+
+        // touch block 0 so block 1 becomes the least recently used one
+        assert!(cache.get(&(1, 1, 0)).is_some());
+        cache.insert((1, 1, 3), block(100, 3));
+
+        assert!(cache.get(&(1, 1, 1)).is_none());
+        assert!(cache.get(&(1, 1, 0)).is_some());
+        assert!(cache.get(&(1, 1, 2)).is_some());
+        assert!(cache.get(&(1, 1, 3)).is_some());
+        assert_eq!(cache.size(), 300);
+    }
+
+    #[test]
+    fn test_block_cache_read_stream() {
+        let table = crate::mem_table::MemTable::new(Box::new(|_stream_id| Ok(0)));
+        for id in 1..=10 {
+            table
+                .append(&crate::entry::Entry {
+                    version: 1,
+                    id,
+                    stream_id: 1,
+                    data: format!("{:04}", id).into_bytes(),
+                    callback: None,
+                })
+                .unwrap();
+        }
+        let path = std::env::temp_dir().join(format!("block-cache-{}.seg", std::process::id()));
+        let segment = crate::segments::generate_segment(&path, &table).unwrap();
+        segment.set_drop_delete(true);
+        let expected = segment.stream_data(1).unwrap().to_vec();
+
+        let cache = BlockCache::new(1024, 6);
+        for offset in [0u64, 5, 13, 39] {
+            for len in [1usize, 6, 7, 64] {
+                let mut buf = vec![0u8; len];
+                let n = cache.read_stream(&segment, 1, offset, &mut buf).unwrap();
+                let want = &expected[offset as usize..(offset as usize + len).min(expected.len())];
+                assert_eq!(&buf[..n], want);
+            }
+        }
+        assert_eq!(cache.read_stream(&segment, 1, 40, &mut [0u8; 4]).unwrap(), 0);
+        assert!(cache.read_stream(&segment, 2, 0, &mut [0u8; 4]).is_err());
+        assert_eq!(cache.len(), 7);
+    }
+
+    #[test]
+    fn test_block_cache_skips_oversized_block() {
+        let cache = BlockCache::new(100, 100);
+        cache.insert((1, 1, 0), block(101, 0));
+        assert!(cache.get(&(1, 1, 0)).is_none());
+        assert_eq!(cache.size(), 0);
+    }
+}
@@ -22,6 +22,9 @@ pub enum Error {
     #[error("store is read-only")]
     StoreIsReadOnly,
 
+    #[error("store is not opened read-only")]
+    StoreNotOpenedReadOnly,
+
     #[error("channel is closed")]
     WalChannelSendError,
 
@@ -59,6 +62,10 @@ pub fn new_store_is_read_only() -> anyhow::Error {
     anyhow::anyhow!(Error::StoreIsReadOnly)
 }
 
+pub fn new_store_not_opened_read_only() -> anyhow::Error {
+    anyhow::anyhow!(Error::StoreNotOpenedReadOnly)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -85,6 +92,9 @@ mod tests {
         let error = Error::StoreIsReadOnly;
         assert_eq!(error.to_string(), "store is read-only");
 
+        let error = Error::StoreNotOpenedReadOnly;
+        assert_eq!(error.to_string(), "store is not opened read-only");
+
         let error = Error::WalChannelSendError;
         assert_eq!(error.to_string(), "channel is closed");
 
@@ -120,6 +130,9 @@ mod tests {
 
         let err = new_store_is_read_only();
         assert!(err.to_string().contains("store is read-only"));
+
+        let err = new_store_not_opened_read_only();
+        assert!(err.to_string().contains("store is not opened read-only"));
     }
 
     #[test]
@@ -1,3 +1,4 @@
+mod block_cache;
 pub mod entry;
 mod errors;
 mod futures;
@@ -6,6 +7,7 @@ mod metrics;
 pub mod options;
 mod reader;
 mod reload;
+mod segment_index;
 mod segments;
 pub mod store;
 mod table;
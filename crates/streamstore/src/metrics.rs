@@ -5,6 +5,7 @@ use prometheus_client::{
     encoding::text::encode,
     metrics::{
         counter::Counter,
+        gauge::Gauge,
         histogram::{Histogram, exponential_buckets},
     },
     registry::Registry,
@@ -48,6 +49,33 @@ lazy_static! {
         );
         c
     };
+    pub static ref block_cache_hit_count: Counter = {
+        let c: Counter = Default::default();
+        registry.lock().unwrap().register(
+            "block_cache_hit_count",
+            "Count of segment block cache hits",
+            c.clone(),
+        );
+        c
+    };
+    pub static ref block_cache_miss_count: Counter = {
+        let c: Counter = Default::default();
+        registry.lock().unwrap().register(
+            "block_cache_miss_count",
+            "Count of segment block cache misses",
+            c.clone(),
+        );
+        c
+    };
+    pub static ref block_cache_bytes: Gauge = {
+        let g: Gauge = Default::default();
+        registry.lock().unwrap().register(
+            "block_cache_bytes",
+            "Bytes of segment blocks held by the block cache",
+            g.clone(),
+        );
+        g
+    };
     pub static ref find_segment_time_seconds: Histogram = {
         let h = Histogram::new(exponential_buckets(0.0000001, 2.0, 25));
         registry.lock().unwrap().register(
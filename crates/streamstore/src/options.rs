@@ -1,4 +1,4 @@
-use crate::Store;
+use crate::{Store, block_cache::BlockCache};
 use anyhow::Result;
 
 #[derive(Clone, Debug)]
@@ -11,6 +11,9 @@ pub struct Options {
     pub(crate) segment_merge_count: u64,
     pub(crate) max_segment_merge_level: u32,
     pub(crate) reload_check_crc: bool,
+    // bytes of segment blocks to keep in memory, 0 disables the block cache
+    pub(crate) block_cache_size: u64,
+    pub(crate) block_size: u64,
 }
 
 impl Default for Options {
@@ -24,6 +27,8 @@ impl Default for Options {
             segment_merge_count: 5,
             max_segment_merge_level: 5,
             reload_check_crc: false,
+            block_cache_size: 0,
+            block_size: 64 * 1024,
         }
     }
 }
@@ -82,12 +87,36 @@ impl Options {
         self.max_tables_count = max_tables_count;
         self
     }
+    pub fn block_cache_size(&mut self, block_cache_size: u64) -> &mut Self {
+        self.block_cache_size = block_cache_size;
+        self
+    }
+    pub fn block_size(&mut self, block_size: u64) -> &mut Self {
+        assert!(block_size > 0, "Block size must be greater than zero");
+        self.block_size = block_size;
+        self
+    }
     pub fn wal_path_str(&self) -> &str {
         &self.wal_path
     }
 
+    pub(crate) fn new_block_cache(&self) -> Option<BlockCache> {
+        if self.block_cache_size == 0 {
+            return None;
+        }
+        Some(BlockCache::new(self.block_cache_size, self.block_size))
+    }
+
     pub fn open_store(&self) -> Result<Store> {
         let store = Store::reload(self)?;
         Ok(store)
     }
+
+    /// Open the data directory without starting the WAL, memtable and merge
+    /// threads. Appends are rejected; call `Store::refresh` to pick up the
+    /// segments written by the process that owns the directory.
+    pub fn open_store_readonly(&self) -> Result<Store> {
+        let store = Store::reload_readonly(self)?;
+        Ok(store)
+    }
 }
@@ -4,6 +4,7 @@ use crate::{
     StreamId,
     mem_table::MemTableWeak,
     metrics,
+    segments::Segment,
     store::{SegmentWeak, StreamStoreInner},
 };
 
@@ -56,6 +57,13 @@ impl StreamReader {
             .store(offset, std::sync::atomic::Ordering::Relaxed);
     }
 
+    fn read_segment(&self, segment: &Segment, buf: &mut [u8]) -> io::Result<usize> {
+        match &self.inner.block_cache {
+            Some(cache) => cache.read_stream(segment, self.stream_id, self.offset(), buf),
+            None => segment.read_stream(self.stream_id, self.offset(), buf),
+        }
+    }
+
     fn reset_read_state(&mut self) {
         self.read_mem_table = None;
         self.read_segment = None;
@@ -77,7 +85,7 @@ impl StreamReader {
                     self.stream_id
                 );
 
-                let bytes_read = segment.read_stream(self.stream_id, self.offset(), buf)?;
+                let bytes_read = self.read_segment(&segment, buf)?;
                 if bytes_read > 0 {
                     self.offset_inc(bytes_read);
                     read_bytes_all += bytes_read;
@@ -93,11 +101,7 @@ impl StreamReader {
                 Some(segment) => {
                     metrics::read_segment_miss_count.inc();
                     metrics::find_segment_time_seconds.observe(begin_ts.elapsed().as_secs_f64());
-                    let bytes_read = segment.read_stream(
-                        self.stream_id,
-                        self.offset(),
-                        &mut buf[read_bytes_all..],
-                    )?;
+                    let bytes_read = self.read_segment(&segment, &mut buf[read_bytes_all..])?;
                     self.offset_inc(bytes_read);
                     read_bytes_all += bytes_read;
                     if read_bytes_all >= buf.len() {
@@ -276,6 +280,17 @@ impl io::Read for StreamReader {
                 }
                 StreamReadState::MemTable => {
                     let memtable = self.inner.table.load();
+                    match memtable.get_stream_range(self.stream_id) {
+                        Some((begin, end)) if begin <= self.offset() && self.offset() <= end => {}
+                        _ => {
+                            // The memtable was rotated or refreshed underneath the reader
+                            if read_bytes_all > 0 {
+                                return Ok(read_bytes_all);
+                            }
+                            self.reset_read_state();
+                            continue;
+                        }
+                    }
 
                     let bytes_read = memtable.read_stream(
                         self.stream_id,
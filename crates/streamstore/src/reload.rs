@@ -13,8 +13,11 @@ use std::{
 use crate::{
     entry::Decoder,
     errors,
-    mem_table::{GetStreamOffset, MemTable},
-    segments::Segment, StreamId,
+    mem_table::{GetStreamOffset, MemTable, MemTableArc},
+    options::Options,
+    segments::Segment,
+    store::SegmentArc,
+    StreamId,
 };
 
 pub fn reload_segments(segment_path: &str, check_crc: bool) -> Result<VecDeque<Arc<Segment>>> {
@@ -39,7 +42,15 @@ pub fn reload_segments(segment_path: &str, check_crc: bool) -> Result<VecDeque<A
             continue;
         }
 
-        let segment = Segment::open(&filename)?;
+        let segment = match Segment::open(&filename) {
+            Ok(segment) => segment,
+            // the segment was merged and deleted by a live writer after we listed it
+            Err(_) if !filename.exists() => {
+                log::debug!("Segment file vanished while reloading: {:?}", filename);
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
 
         if check_crc {
             // check crc
@@ -65,7 +76,7 @@ pub fn reload_segments(segment_path: &str, check_crc: bool) -> Result<VecDeque<A
     Ok(segment_files)
 }
 
-fn list_wal_files(wal_path: &str) -> Result<Vec<(String, u64)>> {
+fn list_wal_files(wal_path: &str, readonly: bool) -> Result<Vec<(String, u64)>> {
     let mut wals = vec![];
 
     // read file from wal dir
@@ -91,11 +102,22 @@ fn list_wal_files(wal_path: &str) -> Result<Vec<(String, u64)>> {
             }
 
             // Open the WAL file
-            let mut file = File::open(filename).map_err(errors::new_io_error)?;
+            let mut file = match File::open(filename) {
+                Ok(file) => file,
+                Err(e) if readonly && e.kind() == std::io::ErrorKind::NotFound => {
+                    log::debug!("WAL file {} was removed by the writer, skip it", filename);
+                    continue;
+                }
+                Err(e) => return Err(errors::new_io_error(e)),
+            };
 
             // check file is empty
             if file.metadata().map_err(errors::new_io_error)?.len() == 0 {
                 drop(file);
+                if readonly {
+                    // the writer just rotated to this file, leave it alone
+                    continue;
+                }
                 log::warn!("WAL file is empty: {}. delete it", filename);
                 std::fs::remove_file(filename).context("Failed to remove empty WAL file")?;
                 continue;
@@ -103,12 +125,21 @@ fn list_wal_files(wal_path: &str) -> Result<Vec<(String, u64)>> {
 
             let mut entry_index = 0;
             // Decode the entries from the WAL file
-            file.decode(Box::new(|entry| {
+            let res = file.decode(Box::new(|entry| {
                 // Handle the entry
                 log::debug!("decode {} first entry id {}", filename, entry.id);
                 entry_index = entry.id;
                 Ok(false)
-            }))?;
+            }));
+            match res {
+                Ok(_) => {}
+                // the first entry is still being written by the writer
+                Err(e) if readonly => {
+                    log::debug!("Skip WAL file {} with partial entry: {:?}", filename, e);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
 
             wals.push((filename.to_string(), entry_index));
         }
@@ -152,7 +183,7 @@ pub fn reload_wals(
         })
     };
 
-    let wals = list_wal_files(wal_path)?;
+    let wals = list_wal_files(wal_path, false)?;
     let mut files = HashMap::new();
     let mut entry_index = 0;
     let mut table = Rc::new(MemTable::new(make_stream_offset_fn()));
@@ -237,3 +268,159 @@ pub fn reload_wals(
 
     Ok((tables, files, (file, file_name)))
 }
+
+// Replay the WAL files into memtables without creating, truncating or deleting
+// any file. The tail of the newest WAL may be torn by a concurrent writer, so a
+// partial entry at the end of the last file is ignored.
+fn replay_wals_readonly(
+    wal_path: &str,
+    last_segment_entry_index: u64,
+    max_table_size: u64,
+    offset_map: &mut HashMap<StreamId, u64>,
+) -> Result<(VecDeque<MemTableArc>, Option<u64>)> {
+    let shared_offset_map = Arc::new(Mutex::new(offset_map.clone()));
+
+    let make_stream_offset_fn = || -> GetStreamOffset {
+        let offset_map = shared_offset_map.clone();
+        Box::new(move |stream_id| match offset_map.lock().unwrap().get(&stream_id) {
+            Some(offset) => Ok(*offset),
+            None => Ok(0), // Default to 0 if not found
+        })
+    };
+
+    let wals = if std::path::Path::new(wal_path).exists() {
+        list_wal_files(wal_path, true)?
+    } else {
+        vec![]
+    };
+
+    let mut first_entry_index = None;
+    let mut table = Arc::new(MemTable::new(make_stream_offset_fn()));
+    let mut tables = VecDeque::new();
+    for (index, (filename, _entry_index)) in wals.iter().enumerate() {
+        let mut file = match File::open(filename) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                log::debug!("WAL file {} was removed by the writer, skip it", filename);
+                continue;
+            }
+            Err(e) => return Err(errors::new_io_error(e)),
+        };
+
+        let res = file.decode(Box::new(|entry| {
+            if entry.id <= last_segment_entry_index {
+                return Ok(true);
+            }
+            if first_entry_index.is_none() {
+                first_entry_index = Some(entry.id);
+            }
+
+            table.append(&entry)?;
+            if table.get_size() > max_table_size {
+                for stream_id in table.get_stream_ids() {
+                    let (_begin, end) = table.get_stream_range(stream_id).unwrap();
+                    shared_offset_map.lock().unwrap().insert(stream_id, end);
+                }
+                tables.push_back(table.clone());
+                table = Arc::new(MemTable::new(make_stream_offset_fn()));
+            }
+            Ok(true)
+        }));
+
+        match res {
+            Ok(_) => {}
+            Err(e) if index == wals.len() - 1 => {
+                log::debug!("Ignore partial tail of WAL file {}: {:?}", filename, e);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    tables.push_back(table);
+
+    for table in tables.iter() {
+        for stream_id in table.get_stream_ids() {
+            if let Some((_begin, end)) = table.get_stream_range(stream_id) {
+                offset_map.insert(stream_id, end);
+            }
+        }
+    }
+
+    Ok((tables, first_entry_index))
+}
+
+// Segments that were merged into a higher level segment may still be on disk
+// for a short while after the merge; keep only the merged one.
+fn drop_merged_segments(segments: VecDeque<SegmentArc>) -> VecDeque<SegmentArc> {
+    let covered = |segment: &SegmentArc| {
+        let (first, last) = segment.entry_index();
+        segments.iter().any(|other| {
+            let (other_first, other_last) = other.entry_index();
+            other.level() > segment.level() && other_first <= first && last <= other_last
+        })
+    };
+    segments
+        .iter()
+        .filter(|segment| !covered(segment))
+        .cloned()
+        .collect()
+}
+
+pub(crate) type ReadonlySnapshot = (
+    VecDeque<SegmentArc>,
+    VecDeque<MemTableArc>,
+    HashMap<StreamId, u64>,
+);
+
+// Load the segments and replay the WAL of a data directory owned by another
+// process. The writer may generate a segment and garbage collect its WAL
+// between our two directory scans, so retry when the entry ids do not line up.
+pub(crate) fn load_readonly(options: &Options) -> Result<ReadonlySnapshot> {
+    if !std::path::Path::new(&options.segment_path).exists() {
+        return Err(errors::new_invalid_path(PathBuf::from(&options.segment_path)));
+    }
+
+    const MAX_ATTEMPTS: usize = 5;
+    for attempt in 1..=MAX_ATTEMPTS {
+        let segment_files = drop_merged_segments(reload_segments(
+            &options.segment_path,
+            options.reload_check_crc,
+        )?);
+        let last_segment_entry_index = segment_files
+            .iter()
+            .map(|segment| segment.entry_index().1)
+            .max()
+            .unwrap_or(0);
+
+        let mut offset_map = HashMap::new();
+        for segment in segment_files.iter() {
+            for stream_header in segment.get_stream_headers() {
+                let offset = stream_header.offset + stream_header.size;
+                let current = offset_map.entry(stream_header.stream_id).or_insert(offset);
+                *current = (*current).max(offset);
+            }
+        }
+
+        let (mem_tables, first_entry_index) = replay_wals_readonly(
+            &options.wal_path,
+            last_segment_entry_index,
+            options.max_table_size,
+            &mut offset_map,
+        )?;
+
+        match first_entry_index {
+            Some(id) if id != last_segment_entry_index + 1 => {
+                log::info!(
+                    "WAL entry {} does not follow segment entry {}, reload again (attempt {})",
+                    id,
+                    last_segment_entry_index,
+                    attempt
+                );
+            }
+            _ => return Ok((segment_files, mem_tables, offset_map)),
+        }
+    }
+    Err(errors::new_invalid_data().context(format!(
+        "failed to load a consistent snapshot of {} after {} attempts",
+        options.segment_path, MAX_ATTEMPTS
+    )))
+}
@@ -0,0 +1,158 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+use crate::{StreamId, store::SegmentArc};
+
+// Per stream interval index over the segment files.
+// stream_id -> begin offset -> (end offset, segment)
+#[derive(Default)]
+pub(crate) struct SegmentIndex {
+    streams: HashMap<StreamId, BTreeMap<u64, (u64, SegmentArc)>>,
+}
+
+impl SegmentIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn build<'a>(segments: impl IntoIterator<Item = &'a SegmentArc>) -> Self {
+        let mut index = Self::new();
+        for segment in segments {
+            index.insert(segment);
+        }
+        index
+    }
+
+    pub fn insert(&mut self, segment: &SegmentArc) {
+        for stream_header in segment.get_stream_headers() {
+            let begin = stream_header.offset;
+            let end = stream_header.offset + stream_header.size;
+            self.streams
+                .entry(stream_header.stream_id)
+                .or_default()
+                .insert(begin, (end, segment.clone()));
+        }
+    }
+
+    // Remove the ranges of the segment, unless they were already replaced by
+    // another segment starting at the same offset (e.g. a merged segment).
+    pub fn remove(&mut self, segment: &SegmentArc) {
+        for stream_header in segment.get_stream_headers() {
+            let stream_id = stream_header.stream_id;
+            let Some(ranges) = self.streams.get_mut(&stream_id) else {
+                continue;
+            };
+            if let Some((_end, current)) = ranges.get(&stream_header.offset) {
+                if Arc::ptr_eq(current, segment) {
+                    ranges.remove(&stream_header.offset);
+                }
+            }
+            if ranges.is_empty() {
+                self.streams.remove(&stream_id);
+            }
+        }
+    }
+
+    pub fn find(&self, stream_id: StreamId, offset: u64) -> Option<SegmentArc> {
+        let (_begin, (end, segment)) = self.streams.get(&stream_id)?.range(..=offset).next_back()?;
+        if offset < *end {
+            Some(segment.clone())
+        } else {
+            None
+        }
+    }
+
+    pub fn stream_begin(&self, stream_id: StreamId) -> Option<u64> {
+        self.streams
+            .get(&stream_id)?
+            .first_key_value()
+            .map(|(begin, _)| *begin)
+    }
+
+    pub fn stream_end(&self, stream_id: StreamId) -> Option<u64> {
+        self.streams
+            .get(&stream_id)?
+            .last_key_value()
+            .map(|(_begin, (end, _))| *end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        entry::Entry,
+        mem_table::MemTable,
+        segments::{Segment, generate_segment},
+    };
+
+    fn make_segment(name: &str, first_id: u64, appends: &[(StreamId, u64, &[u8])]) -> SegmentArc {
+        let offsets: HashMap<StreamId, u64> =
+            appends.iter().map(|(stream_id, offset, _)| (*stream_id, *offset)).collect();
+        let table = MemTable::new(Box::new(move |stream_id| {
+            Ok(*offsets.get(&stream_id).unwrap_or(&0))
+        }));
+        for (i, (stream_id, _offset, data)) in appends.iter().enumerate() {
+            table
+                .append(&Entry {
+                    version: 1,
+                    id: first_id + i as u64,
+                    stream_id: *stream_id,
+                    data: data.to_vec(),
+                    callback: None,
+                })
+                .unwrap();
+        }
+        let path = std::env::temp_dir().join(format!(
+            "segment-index-{}-{}.seg",
+            name,
+            std::process::id()
+        ));
+        let segment: Segment = generate_segment(&path, &table).unwrap();
+        segment.set_drop_delete(true);
+        Arc::new(segment)
+    }
+
+    #[test]
+    fn test_segment_index_find() {
+        let s1 = make_segment("find-1", 1, &[(1, 0, b"hello"), (2, 0, b"ab")]);
+        let s2 = make_segment("find-2", 3, &[(1, 5, b"world")]);
+        let index = SegmentIndex::build([&s1, &s2]);
+
+        assert!(Arc::ptr_eq(&index.find(1, 0).unwrap(), &s1));
+        assert!(Arc::ptr_eq(&index.find(1, 4).unwrap(), &s1));
+        assert!(Arc::ptr_eq(&index.find(1, 5).unwrap(), &s2));
+        assert!(Arc::ptr_eq(&index.find(1, 9).unwrap(), &s2));
+        assert!(index.find(1, 10).is_none());
+        assert!(Arc::ptr_eq(&index.find(2, 1).unwrap(), &s1));
+        assert!(index.find(2, 2).is_none());
+        assert!(index.find(3, 0).is_none());
+
+        assert_eq!(index.stream_begin(1), Some(0));
+        assert_eq!(index.stream_end(1), Some(10));
+        assert_eq!(index.stream_end(2), Some(2));
+        assert_eq!(index.stream_begin(3), None);
+    }
+
+    #[test]
+    fn test_segment_index_replace_merged() {
+        let s1 = make_segment("merge-1", 1, &[(1, 0, b"hello")]);
+        let s2 = make_segment("merge-2", 2, &[(1, 5, b"world")]);
+        let merged = make_segment("merge-3", 1, &[(1, 0, b"helloworld")]);
+        let mut index = SegmentIndex::build([&s1, &s2]);
+
+        index.insert(&merged);
+        index.remove(&s1);
+        index.remove(&s2);
+
+        assert!(Arc::ptr_eq(&index.find(1, 0).unwrap(), &merged));
+        assert!(Arc::ptr_eq(&index.find(1, 7).unwrap(), &merged));
+        assert_eq!(index.stream_end(1), Some(10));
+
+        index.remove(&merged);
+        assert!(index.find(1, 0).is_none());
+        assert_eq!(index.stream_begin(1), None);
+    }
+}
@@ -16,6 +16,9 @@ const SEGMENT_HEADER_SIZE: u64 = std::mem::size_of::<SegmentHeader>() as u64;
 const SEGMENT_STREAM_HEADER_VERSION_V1: u64 = 1;
 const SEGMENT_HEADER_VERSION_V1: u32 = 1;
 
+// process wide id of the opened segments, used as block cache key
+static NEXT_SEGMENT_ID: atomic::AtomicU64 = atomic::AtomicU64::new(1);
+
 #[derive(Debug, Clone)]
 #[repr(C)]
 pub struct SegmentStreamHeader {
@@ -84,6 +87,7 @@ impl Display for SegmentHeader {
 pub struct Segment {
     #[allow(dead_code)]
     pub filename: path::PathBuf,
+    id: u64,
     file: Option<File>,
     data: Option<memmap2::Mmap>,
     drop_delete: atomic::AtomicBool,
@@ -94,6 +98,7 @@ impl Segment {
         let file = File::open(&file_name).map_err(errors::new_io_error)?;
         let mmap = unsafe { memmap2::Mmap::map(&file) }.map_err(errors::new_io_error)?;
         let segment = Segment {
+            id: NEXT_SEGMENT_ID.fetch_add(1, atomic::Ordering::Relaxed),
             file: Some(file),
             data: Some(mmap),
             filename: file_name.clone(),
@@ -130,6 +135,10 @@ impl Segment {
             .store(drop_delete, atomic::Ordering::Relaxed);
     }
 
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
     pub fn entry_index(&self) -> (u64, u64) {
         let header = self.get_segment_header();
         (header.first_entry, header.last_entry)
@@ -15,6 +15,7 @@ use arc_swap::ArcSwap;
 
 use crate::{
     StreamId,
+    block_cache::BlockCache,
     entry::{AppendEntryResultFn, DataType, Entry},
     errors::{self, new_stream_not_found},
     futures::AppendFuture,
@@ -23,6 +24,7 @@ use crate::{
     options::Options,
     reader::StreamReader,
     reload::{self, reload_segments},
+    segment_index::SegmentIndex,
     segments::{Segment, generate_segment, merge_segments},
     wal::{Wal, WalInner},
 };
@@ -32,7 +34,7 @@ pub(crate) type SegmentWeak = Weak<Segment>;
 
 pub struct StreamStoreInner {
     // segment files
-    wal_inner: Arc<WalInner>,
+    wal_inner: Option<Arc<WalInner>>,
     config: Options,
     entry_index: AtomicU64,
     entry_receiver: Mutex<Receiver<Vec<Entry>>>,
@@ -40,14 +42,19 @@ pub struct StreamStoreInner {
     pub(crate) table: ArcSwap<MemTable>,
     pub(crate) mem_tables: std::sync::RwLock<VecDeque<MemTableArc>>,
     pub(crate) segment_files: RwLock<VecDeque<SegmentArc>>,
+    // per stream interval index over segment_files, updated under its write lock
+    pub(crate) segment_index: RwLock<SegmentIndex>,
+    pub(crate) block_cache: Option<BlockCache>,
     pub(crate) offsets: Arc<Mutex<HashMap<StreamId, u64>>>,
+    // set from the start by `Options::open_store_readonly`, or after a write
+    // error of a writable store
     pub(crate) is_readonly: Arc<atomic::AtomicBool>,
 }
 
 #[derive(Clone)]
 pub struct Store {
     inner: Arc<StreamStoreInner>,
-    wal: Wal,
+    wal: Option<Wal>,
 }
 
 impl std::ops::Deref for Store {
@@ -121,27 +128,11 @@ impl StreamStoreInner {
     }
 
     pub(crate) fn find_segment(&self, stream_id: StreamId, offset: u64) -> Option<SegmentArc> {
-        self.segment_files
-            .read()
-            .unwrap()
-            .iter()
-            .find(|segment| match segment.get_stream_range(stream_id) {
-                Some((begin, end)) => begin <= offset && offset < end,
-                None => false,
-            })
-            .cloned()
+        self.segment_index.read().unwrap().find(stream_id, offset)
     }
 
     pub fn get_stream_begin(&self, stream_id: StreamId) -> Result<u64> {
-        let mut begin = self
-            .segment_files
-            .read()
-            .unwrap()
-            .iter()
-            .find_map(|segment| match segment.get_stream_range(stream_id) {
-                Some((begin, _end)) => return Some(begin),
-                None => return None,
-            });
+        let mut begin = self.segment_index.read().unwrap().stream_begin(stream_id);
 
         if begin.is_none() {
             begin = self.mem_tables.read().unwrap().iter().find_map(|table| {
@@ -181,15 +172,7 @@ impl StreamStoreInner {
         }
 
         if end.is_none() {
-            end = self
-                .segment_files
-                .read()
-                .unwrap()
-                .iter()
-                .find_map(|segment| match segment.get_stream_range(stream_id) {
-                    Some((_begin, end)) => return Some(end),
-                    None => return None,
-                });
+            end = self.segment_index.read().unwrap().stream_end(stream_id);
         }
         if end.is_none() {
             return Err(new_stream_not_found(stream_id));
@@ -284,7 +267,8 @@ impl StreamStoreInner {
             match generate_segment(&file_name, &table) {
                 Ok(_) => {
                     log::info!("Segment generated: {}", file_name.display());
-                    match self.wal_inner.gc(table.get_last_entry()) {
+                    let wal_inner = self.wal_inner.as_ref().unwrap();
+                    match wal_inner.gc(table.get_last_entry()) {
                         Ok(_) => {
                             log::info!(
                                 "WAL garbage collection completed for segment: {}",
@@ -315,6 +299,7 @@ impl StreamStoreInner {
 
             let mut segment_files_guard = self.segment_files.write().unwrap();
             segment_files_guard.push_back(segment.clone());
+            self.segment_index.write().unwrap().insert(&segment);
 
             let mut memtables = self.mem_tables.write().unwrap();
             if memtables.len() > self.config.max_tables_count as usize {
@@ -399,12 +384,16 @@ impl StreamStoreInner {
         };
 
         // Update the segment files list
+        let segment = Arc::new(segment);
         let mut segment_files_guard = self.segment_files.write().unwrap();
-        segment_files_guard.push_back(Arc::new(segment));
+        let mut segment_index_guard = self.segment_index.write().unwrap();
+        segment_files_guard.push_back(segment.clone());
+        segment_index_guard.insert(&segment);
 
         // Remove the merged segments from the list
         for segment in to_merges {
             segment_files_guard.retain(|s| s.filename() != segment.filename());
+            segment_index_guard.remove(&segment);
             segment.set_drop_delete(true);
         }
 
@@ -467,11 +456,12 @@ impl Store {
         if self.is_readonly.load(atomic::Ordering::SeqCst) {
             return Err(errors::new_store_is_read_only());
         }
+        let wal = self.wal.as_ref().ok_or_else(errors::new_store_is_read_only)?;
         let id = self
             .entry_index
             .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
 
-        self.wal.write(Entry {
+        wal.write(Entry {
             version: 1,
             id: id,
             stream_id,
@@ -485,13 +475,14 @@ impl Store {
         if self.is_readonly.load(atomic::Ordering::SeqCst) {
             return Err(errors::new_store_is_read_only());
         }
+        let wal = self.wal.as_ref().ok_or_else(errors::new_store_is_read_only)?;
         let id = self
             .entry_index
             .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
 
         let f = AppendFuture::new();
 
-        let result = self.wal.write(Entry {
+        let result = wal.write(Entry {
             version: 1,
             id: id,
             stream_id,
@@ -626,20 +617,22 @@ impl Store {
         let memtable = Rc::into_inner(mem_table).unwrap();
 
         let inner = StreamStoreInner {
-            wal_inner: wal.clone_inner(),
+            wal_inner: Some(wal.clone_inner()),
             config: options.clone(),
             offsets: Arc::new(Mutex::new(offset_map)),
             is_readonly: is_readonly.clone(),
             entry_index: AtomicU64::new(last_log_entry + 1),
             table: ArcSwap::new(Arc::new(memtable)),
             mem_tables: RwLock::new(VecDeque::new()),
+            segment_index: RwLock::new(SegmentIndex::build(&segment_files)),
             segment_files: RwLock::new(segment_files),
+            block_cache: options.new_block_cache(),
             entry_receiver: Mutex::new(entries_receiver),
         };
 
         let store = Store {
             inner: Arc::new(inner),
-            wal: wal,
+            wal: Some(wal),
         };
 
         // start background thread
@@ -648,6 +641,70 @@ impl Store {
         Ok(store)
     }
 
+    pub fn reload_readonly(options: &Options) -> Result<Self> {
+        let (segment_files, mut mem_tables, offset_map) = reload::load_readonly(options)?;
+        let table = mem_tables.pop_back().unwrap();
+        let last_log_entry = table.get_last_entry();
+
+        // nobody ever sends entries to a read-only store
+        let (_, entries_receiver) = sync_channel::<Vec<Entry>>(1);
+
+        let inner = StreamStoreInner {
+            wal_inner: None,
+            config: options.clone(),
+            offsets: Arc::new(Mutex::new(offset_map)),
+            is_readonly: Arc::new(atomic::AtomicBool::new(true)),
+            entry_index: AtomicU64::new(last_log_entry + 1),
+            table: ArcSwap::new(table),
+            mem_tables: RwLock::new(mem_tables),
+            segment_index: RwLock::new(SegmentIndex::build(&segment_files)),
+            segment_files: RwLock::new(segment_files),
+            block_cache: options.new_block_cache(),
+            entry_receiver: Mutex::new(entries_receiver),
+        };
+
+        log::info!(
+            "Store opened read-only, segment path {}, last log entry {}",
+            options.segment_path,
+            last_log_entry
+        );
+
+        Ok(Store {
+            inner: Arc::new(inner),
+            wal: None,
+        })
+    }
+
+    /// Whether the store rejects appends: opened with `Options::open_store_readonly`,
+    /// or turned read-only by a write error.
+    pub fn is_read_only(&self) -> bool {
+        self.is_readonly.load(atomic::Ordering::SeqCst)
+    }
+
+    /// Reload the segments and WAL of a read-only store to pick up the data
+    /// written by the process that owns the data directory since it was opened.
+    pub fn refresh(&self) -> Result<()> {
+        // a store with a WAL owns the data directory, even once it turned read-only
+        if self.wal_inner.is_some() {
+            return Err(errors::new_store_not_opened_read_only());
+        }
+        let (segment_files, mut mem_tables, offset_map) = reload::load_readonly(&self.config)?;
+        let table = mem_tables.pop_back().unwrap();
+        self.entry_index
+            .store(table.get_last_entry() + 1, atomic::Ordering::SeqCst);
+
+        // swap segments before memtables so readers never miss a range
+        {
+            let mut segment_files_guard = self.segment_files.write().unwrap();
+            *self.segment_index.write().unwrap() = SegmentIndex::build(&segment_files);
+            *segment_files_guard = segment_files;
+        }
+        *self.mem_tables.write().unwrap() = mem_tables;
+        self.table.store(table);
+        *self.offsets.lock().unwrap() = offset_map;
+        Ok(())
+    }
+
     pub fn print_metrics(&self) {
         println!("{}", metrics::encode_metrics());
     }
@@ -657,7 +714,7 @@ impl Store {
     }
 
     fn start(&self) -> () {
-        self.wal.start();
+        self.wal.as_ref().unwrap().start();
 
         let (sender, receiver) = sync_channel::<(path::PathBuf, MemTableArc)>(10);
         let cond = Arc::new((Mutex::new(0 as u64), Condvar::new()));
@@ -701,3 +758,97 @@ impl Drop for Store {
         // ref count of Arc
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    fn test_data_dir(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!(
+            "streamstore-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir.to_str().unwrap().to_string()
+    }
+
+    fn read_all(store: &Store, stream_id: StreamId) -> Vec<u8> {
+        let mut reader = store.new_stream_reader(stream_id).unwrap();
+        let mut data = Vec::new();
+        let mut buf = [0u8; 64];
+        loop {
+            let n = reader.read(&mut buf).unwrap();
+            if n == 0 {
+                break;
+            }
+            data.extend_from_slice(&buf[..n]);
+        }
+        data
+    }
+
+    #[tokio::test]
+    async fn test_open_store_readonly() {
+        let dir = test_data_dir("readonly");
+        let options = Options::new_with_data_path(&dir);
+        let store = options.open_store().unwrap();
+        store.append_async(1, b"hello ".to_vec()).await.unwrap();
+        store.append_async(2, b"other".to_vec()).await.unwrap();
+
+        let readonly = options.open_store_readonly().unwrap();
+        assert!(readonly.is_read_only());
+        assert!(!store.is_read_only());
+        assert_eq!(readonly.get_stream_range(1).unwrap(), (0, 6));
+        assert_eq!(read_all(&readonly, 1), b"hello ");
+        assert_eq!(read_all(&readonly, 2), b"other");
+
+        let err = readonly.append_async(1, b"nope".to_vec()).await.unwrap_err();
+        assert!(err.to_string().contains("store is read-only"));
+        assert!(readonly.append(1, b"nope".to_vec(), None).is_err());
+        assert!(store.refresh().is_err());
+
+        store.append_async(1, b"world".to_vec()).await.unwrap();
+        assert_eq!(readonly.get_stream_end(1).unwrap(), 6);
+        readonly.refresh().unwrap();
+        assert_eq!(readonly.get_stream_end(1).unwrap(), 11);
+        assert_eq!(read_all(&readonly, 1), b"hello world");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_read_with_block_cache() {
+        let dir = test_data_dir("block-cache");
+        let mut options = Options::new_with_data_path(&dir);
+        options.max_table_size(1024).block_cache_size(4096).block_size(100);
+        let store = options.open_store().unwrap();
+
+        let mut expected = Vec::new();
+        for i in 0..500 {
+            let data = format!("message-{:04};", i).into_bytes();
+            expected.extend_from_slice(&data);
+            store.append_async(7, data).await.unwrap();
+        }
+        assert_eq!(read_all(&store, 7), expected);
+
+        // reopen to read everything back from the segment files
+        drop(store);
+        let readonly = options.open_store_readonly().unwrap();
+        assert!(!readonly.segment_files.read().unwrap().is_empty());
+        assert_eq!(readonly.get_stream_range(7).unwrap(), (0, expected.len() as u64));
+        assert_eq!(read_all(&readonly, 7), expected);
+        assert!(readonly.block_cache.as_ref().unwrap().len() > 0);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_open_store_readonly_missing_dir() {
+        let dir = test_data_dir("readonly-missing");
+        let options = Options::new_with_data_path(&dir);
+        assert!(options.open_store_readonly().is_err());
+        assert!(!std::path::Path::new(&dir).exists());
+    }
+}